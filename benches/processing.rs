@@ -0,0 +1,98 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use mdbook_blox::config::Config;
+use mdbook_blox::{BloxProcessor, SourceChapter};
+use std::hint::black_box;
+use std::path::PathBuf;
+
+const BOOK_TOML: &str = r#"
+[preprocessor.blox.environments]
+exercise = {name = "Exercise"}
+theorem = {name = "Theorem"}
+"#;
+
+/// Many chapters with many short blox
+fn many_small_blocks() -> Vec<String> {
+    (0..50)
+        .map(|_| {
+            (0..200)
+                .map(|i| format!("Text {i}\n\n```blox exercise\nShort {i}\n```\n\n"))
+                .collect()
+        })
+        .collect()
+}
+
+/// Few chapters with long prose and some blox
+fn huge_chapters() -> Vec<String> {
+    (0..3)
+        .map(|_| {
+            (0..5_000)
+                .map(|i| match i % 100 {
+                    0 => format!("```blox theorem title = \"T{i}\"\nStatement {i}\n```\n\n"),
+                    _ => format!("Paragraph {i} with *emphasis* and `code`.\n\n"),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Labelled blox referenced thousands of times across chapters
+fn many_refs() -> Vec<String> {
+    (0..10)
+        .map(|c| {
+            let blox: String = (0..100)
+                .map(|i| format!("```blox theorem label = \"t{c}-{i}\"\nS\n```\n\n"))
+                .collect();
+            let refs: String = (0..500)
+                .map(|i| format!("See {{{{ blox-ref: t{}-{} }}}}.\n\n", (c + i) % 10, i % 100))
+                .collect();
+            blox + &refs
+        })
+        .collect()
+}
+
+fn source_chapters(contents: &[String]) -> Vec<SourceChapter<'_>> {
+    contents
+        .iter()
+        .enumerate()
+        .map(|(i, content)| SourceChapter {
+            content,
+            path: Some(PathBuf::from(format!("chapter_{i}.md"))),
+            number: Some(format!("{}.", i + 1)),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn processing(c: &mut Criterion) {
+    let config = Config::from_book_toml(BOOK_TOML).unwrap();
+
+    for (name, contents) in [
+        ("many_small_blocks", many_small_blocks()),
+        ("huge_chapters", huge_chapters()),
+        ("many_refs", many_refs()),
+    ] {
+        let chapters = source_chapters(&contents);
+        let mut group = c.benchmark_group(name);
+
+        // Sections are processed, then numbered
+        group.bench_function("parse", |b| {
+            b.iter(|| {
+                let mut processor = BloxProcessor::new(&config).unwrap();
+                processor.parse(black_box(&chapters)).unwrap();
+                processor
+            })
+        });
+
+        // Sections are stringified, then refs replaced
+        let mut processor = BloxProcessor::new(&config).unwrap();
+        processor.parse(&chapters).unwrap();
+        group.bench_function("render", |b| {
+            b.iter(|| processor.render(black_box(&chapters)).unwrap())
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, processing);
+criterion_main!(benches);
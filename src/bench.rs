@@ -0,0 +1,68 @@
+use crate::config::Config;
+use crate::mdbook_api::Book;
+use crate::preprocessor::source_chapters;
+use crate::process::BloxProcessor;
+use anyhow::Result;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Timings of processing a book repeatedly
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub chapters: usize,
+    pub blox: usize,
+    /// Processing and numbering of all sections
+    pub parse: Vec<Duration>,
+    /// Stringifying of all sections and replacing of refs
+    pub render: Vec<Duration>,
+}
+
+/// Parses and renders the blox of `book` `iterations` times.
+pub fn bench(book: &Book, config: &Config, iterations: usize) -> Result<BenchReport> {
+    let chapters = source_chapters(book);
+    let mut report = BenchReport {
+        chapters: chapters.len(),
+        blox: 0,
+        parse: Vec::with_capacity(iterations),
+        render: Vec::with_capacity(iterations),
+    };
+
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        let mut processor = BloxProcessor::new(config)?;
+        processor.parse(&chapters)?;
+        report.parse.push(start.elapsed());
+
+        let start = Instant::now();
+        processor.render(&chapters)?;
+        report.render.push(start.elapsed());
+
+        report.blox = (0..chapters.len())
+            .map(|i| processor.chapter_blox(i).len())
+            .sum();
+    }
+
+    Ok(report)
+}
+
+fn summary(times: &[Duration]) -> String {
+    let total: Duration = times.iter().sum();
+    let mean = total / times.len().max(1) as u32;
+    let min = times.iter().min().copied().unwrap_or_default();
+    let max = times.iter().max().copied().unwrap_or_default();
+    format!("mean {mean:.2?}, min {min:.2?}, max {max:.2?}")
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} chapters, {} blox, {} iterations",
+            self.chapters,
+            self.blox,
+            self.parse.len()
+        )?;
+        writeln!(f, "parse:  {}", summary(&self.parse))?;
+        writeln!(f, "render: {}", summary(&self.render))
+    }
+}
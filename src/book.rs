@@ -0,0 +1,75 @@
+use crate::config::{Config, ConfigOverride};
+use crate::mdbook_api::{Book, MdBookConfig, load_book};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// A book loaded from disk, outside of an mdBook build
+pub struct LoadedBook {
+    pub book: Book,
+    pub config: Config,
+    pub mdbook_config: MdBookConfig,
+}
+
+impl LoadedBook {
+    #[inline]
+    pub fn title(&self) -> Option<&str> {
+        self.mdbook_config.book.title.as_deref()
+    }
+}
+
+/// Finds the nearest directory containing a `book.toml`, starting at `start`
+pub fn find_book_dir(start: &Path) -> Result<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join("book.toml").is_file())
+        .map(|dir| dir.to_path_buf())
+        .with_context(|| {
+            format!(
+                "Could not find book.toml in {} or any parent directory",
+                start.display()
+            )
+        })
+}
+
+/// Loads the book in `dir` from disk, without running any preprocessors.
+///
+/// Unlike `mdbook build`, chapters missing from disk are never created.
+pub fn load(dir: &Path, overrides: &[ConfigOverride]) -> Result<LoadedBook> {
+    let book_toml = dir.join("book.toml");
+    let mdbook_config =
+        MdBookConfig::from_disk(&book_toml).context("Can't read configuration file")?;
+
+    let book = load_book(dir, &mdbook_config).context("Can't load book")?;
+    let config = Config::from_file(&book_toml, overrides)?;
+
+    Ok(LoadedBook {
+        book,
+        config,
+        mdbook_config,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    #[test]
+    fn test_find_book_dir() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let book = dir.path().join("book");
+        let nested = book.join("src/part");
+        fs::create_dir_all(&nested)?;
+
+        assert!(find_book_dir(&nested).is_err());
+        assert!(find_book_dir(&dir.path().join("missing")).is_err());
+
+        fs::write(book.join("book.toml"), "")?;
+        assert_eq!(find_book_dir(&nested)?, book);
+        assert_eq!(find_book_dir(&book)?, book);
+        assert!(find_book_dir(dir.path()).is_err());
+
+        Ok(())
+    }
+}
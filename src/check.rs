@@ -0,0 +1,20 @@
+use crate::config::Config;
+use crate::manifest::read_fragment_manifest;
+use crate::mdbook_api::Book;
+use crate::preprocessor::source_chapters;
+use crate::process::{BloxProcessor, RefProblem};
+use anyhow::Result;
+
+/// Parses all chapters and lists their duplicate labels and broken refs, resolving refs
+/// with the `refs_fallback` manifest like the preprocessor does.
+pub fn check(book: &Book, config: &Config) -> Result<Vec<RefProblem>> {
+    let chapters = source_chapters(book);
+    let mut processor = BloxProcessor::new(config)?;
+    processor.parse(&chapters)?;
+
+    if let Some(file) = config.refs_fallback.as_deref() {
+        processor.add_fallback(read_fragment_manifest(&config.book_path(file))?);
+    }
+
+    Ok(processor.check_refs(&chapters))
+}
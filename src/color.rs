@@ -0,0 +1,277 @@
+use anyhow::{Context, Result, anyhow, bail};
+use hex_color::HexColor;
+use serde::{Deserialize, Deserializer};
+
+/// Named colors of CSS, sorted by name
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xF0F8FF),
+    ("antiquewhite", 0xFAEBD7),
+    ("aqua", 0x00FFFF),
+    ("aquamarine", 0x7FFFD4),
+    ("azure", 0xF0FFFF),
+    ("beige", 0xF5F5DC),
+    ("bisque", 0xFFE4C4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD),
+    ("blue", 0x0000FF),
+    ("blueviolet", 0x8A2BE2),
+    ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887),
+    ("cadetblue", 0x5F9EA0),
+    ("chartreuse", 0x7FFF00),
+    ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50),
+    ("cornflowerblue", 0x6495ED),
+    ("cornsilk", 0xFFF8DC),
+    ("crimson", 0xDC143C),
+    ("cyan", 0x00FFFF),
+    ("darkblue", 0x00008B),
+    ("darkcyan", 0x008B8B),
+    ("darkgoldenrod", 0xB8860B),
+    ("darkgray", 0xA9A9A9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xA9A9A9),
+    ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B),
+    ("darkolivegreen", 0x556B2F),
+    ("darkorange", 0xFF8C00),
+    ("darkorchid", 0x9932CC),
+    ("darkred", 0x8B0000),
+    ("darksalmon", 0xE9967A),
+    ("darkseagreen", 0x8FBC8F),
+    ("darkslateblue", 0x483D8B),
+    ("darkslategray", 0x2F4F4F),
+    ("darkslategrey", 0x2F4F4F),
+    ("darkturquoise", 0x00CED1),
+    ("darkviolet", 0x9400D3),
+    ("deeppink", 0xFF1493),
+    ("deepskyblue", 0x00BFFF),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1E90FF),
+    ("firebrick", 0xB22222),
+    ("floralwhite", 0xFFFAF0),
+    ("forestgreen", 0x228B22),
+    ("fuchsia", 0xFF00FF),
+    ("gainsboro", 0xDCDCDC),
+    ("ghostwhite", 0xF8F8FF),
+    ("gold", 0xFFD700),
+    ("goldenrod", 0xDAA520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xADFF2F),
+    ("grey", 0x808080),
+    ("honeydew", 0xF0FFF0),
+    ("hotpink", 0xFF69B4),
+    ("indianred", 0xCD5C5C),
+    ("indigo", 0x4B0082),
+    ("ivory", 0xFFFFF0),
+    ("khaki", 0xF0E68C),
+    ("lavender", 0xE6E6FA),
+    ("lavenderblush", 0xFFF0F5),
+    ("lawngreen", 0x7CFC00),
+    ("lemonchiffon", 0xFFFACD),
+    ("lightblue", 0xADD8E6),
+    ("lightcoral", 0xF08080),
+    ("lightcyan", 0xE0FFFF),
+    ("lightgoldenrodyellow", 0xFAFAD2),
+    ("lightgray", 0xD3D3D3),
+    ("lightgreen", 0x90EE90),
+    ("lightgrey", 0xD3D3D3),
+    ("lightpink", 0xFFB6C1),
+    ("lightsalmon", 0xFFA07A),
+    ("lightseagreen", 0x20B2AA),
+    ("lightskyblue", 0x87CEFA),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xB0C4DE),
+    ("lightyellow", 0xFFFFE0),
+    ("lime", 0x00FF00),
+    ("limegreen", 0x32CD32),
+    ("linen", 0xFAF0E6),
+    ("magenta", 0xFF00FF),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66CDAA),
+    ("mediumblue", 0x0000CD),
+    ("mediumorchid", 0xBA55D3),
+    ("mediumpurple", 0x9370DB),
+    ("mediumseagreen", 0x3CB371),
+    ("mediumslateblue", 0x7B68EE),
+    ("mediumspringgreen", 0x00FA9A),
+    ("mediumturquoise", 0x48D1CC),
+    ("mediumvioletred", 0xC71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xF5FFFA),
+    ("mistyrose", 0xFFE4E1),
+    ("moccasin", 0xFFE4B5),
+    ("navajowhite", 0xFFDEAD),
+    ("navy", 0x000080),
+    ("oldlace", 0xFDF5E6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6B8E23),
+    ("orange", 0xFFA500),
+    ("orangered", 0xFF4500),
+    ("orchid", 0xDA70D6),
+    ("palegoldenrod", 0xEEE8AA),
+    ("palegreen", 0x98FB98),
+    ("paleturquoise", 0xAFEEEE),
+    ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5),
+    ("peachpuff", 0xFFDAB9),
+    ("peru", 0xCD853F),
+    ("pink", 0xFFC0CB),
+    ("plum", 0xDDA0DD),
+    ("powderblue", 0xB0E0E6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xFF0000),
+    ("rosybrown", 0xBC8F8F),
+    ("royalblue", 0x4169E1),
+    ("saddlebrown", 0x8B4513),
+    ("salmon", 0xFA8072),
+    ("sandybrown", 0xF4A460),
+    ("seagreen", 0x2E8B57),
+    ("seashell", 0xFFF5EE),
+    ("sienna", 0xA0522D),
+    ("silver", 0xC0C0C0),
+    ("skyblue", 0x87CEEB),
+    ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xFFFAFA),
+    ("springgreen", 0x00FF7F),
+    ("steelblue", 0x4682B4),
+    ("tan", 0xD2B48C),
+    ("teal", 0x008080),
+    ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347),
+    ("turquoise", 0x40E0D0),
+    ("violet", 0xEE82EE),
+    ("wheat", 0xF5DEB3),
+    ("white", 0xFFFFFF),
+    ("whitesmoke", 0xF5F5F5),
+    ("yellow", 0xFFFF00),
+    ("yellowgreen", 0x9ACD32),
+];
+
+/// Parses a color like `#336699`, `#33669980`, `rgb(51 102 153)`, `hsl(210, 50%, 40%)` or
+/// `steelblue`
+pub fn parse_color(s: &str) -> Result<HexColor> {
+    let s = s.trim();
+    if s.starts_with('#') {
+        return HexColor::parse(s).map_err(|err| anyhow!("Invalid color {s}: {err}"));
+    }
+
+    let lower = s.to_ascii_lowercase();
+    if let Some((function, args)) = lower.strip_suffix(')').and_then(|f| f.split_once('(')) {
+        // Both `rgb(1, 2, 3)` and `rgb(1 2 3 / 50%)`
+        let args: Vec<&str> = args
+            .split([',', ' ', '/'])
+            .filter(|a| !a.is_empty())
+            .collect();
+        let (rgb, alpha) = match (function.trim(), args.as_slice()) {
+            ("rgb" | "rgba", [r, g, b, alpha @ ..]) if alpha.len() <= 1 => {
+                let rgb = [channel(r, 255.0)?, channel(g, 255.0)?, channel(b, 255.0)?];
+                (rgb, alpha.first())
+            }
+            ("hsl" | "hsla", [h, s, l, alpha @ ..]) if alpha.len() <= 1 => {
+                let hue = number(h.trim_end_matches("deg"))?.rem_euclid(360.0);
+                let rgb = hsl_to_rgb(hue, percentage(s)?, percentage(l)?).map(|c| c * 255.0);
+                (rgb, alpha.first())
+            }
+            _ => bail!("Invalid color: {s}"),
+        };
+        let alpha = alpha.map_or(Ok(1.0), |a| channel(a, 1.0))? * 255.0;
+        let [r, g, b] = rgb.map(|c| c.round() as u8);
+        return Ok(HexColor::rgba(r, g, b, alpha.round() as u8));
+    }
+
+    NAMED_COLORS
+        .binary_search_by_key(&lower.as_str(), |(name, _)| name)
+        .map(|i| HexColor::from_u24(NAMED_COLORS[i].1))
+        .map_err(|_| anyhow!("Unknown color: {s}"))
+}
+
+fn number(s: &str) -> Result<f64> {
+    s.parse()
+        .with_context(|| format!("Invalid number in color: {s}"))
+}
+
+/// A channel like `255` or `100%`, from 0 to `max`
+fn channel(s: &str, max: f64) -> Result<f64> {
+    let value = match s.strip_suffix('%') {
+        Some(percent) => number(percent)? / 100.0 * max,
+        None => number(s)?,
+    };
+    Ok(value.clamp(0.0, max))
+}
+
+/// A saturation or lightness like `50%`, from 0 to 1
+fn percentage(s: &str) -> Result<f64> {
+    Ok((number(s.trim_end_matches('%'))? / 100.0).clamp(0.0, 1.0))
+}
+
+/// Red, green and blue from 0 to 1, as in the CSS specification
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> [f64; 3] {
+    let f = |n: f64| {
+        let k = (n + hue / 30.0) % 12.0;
+        let a = saturation * lightness.min(1.0 - lightness);
+        lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    [f(0.0), f(8.0), f(4.0)]
+}
+
+/// Deserializes a color in any of the forms of [`parse_color`]
+pub fn deserialize_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<HexColor, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_color(&s).map_err(serde::de::Error::custom)
+}
+
+pub fn deserialize_color_option<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<HexColor>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| parse_color(&s))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_color() -> Result<()> {
+        assert!(NAMED_COLORS.is_sorted_by_key(|(name, _)| *name));
+
+        let cases = [
+            ("#336699", HexColor::from_u24(0x336699)),
+            ("#33669980", HexColor::rgba(0x33, 0x66, 0x99, 0x80)),
+            ("SteelBlue", HexColor::from_u24(0x4682B4)),
+            ("rgb(51, 102, 153)", HexColor::from_u24(0x336699)),
+            (
+                "rgb(20% 40% 60% / 50%)",
+                HexColor::rgba(0x33, 0x66, 0x99, 0x80),
+            ),
+            (
+                "rgba(51,102,153,0.5)",
+                HexColor::rgba(0x33, 0x66, 0x99, 0x80),
+            ),
+            ("hsl(210, 50%, 40%)", HexColor::from_u24(0x336699)),
+            ("hsl(120deg 100% 25%)", HexColor::from_u24(0x008000)),
+            ("hsla(-360, 100%, 50%, 1)", HexColor::from_u24(0xFF0000)),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(parse_color(s)?, expected, "{s}");
+        }
+
+        for s in ["#12345", "nocolor", "rgb(1, 2)", "hsl(a, 50%, 50%)"] {
+            assert!(parse_color(s).is_err(), "{s}");
+        }
+
+        Ok(())
+    }
+}
@@ -1,60 +1,344 @@
+use crate::color::{deserialize_color, deserialize_color_option};
+use crate::link::SOURCE_LINK_RENDERERS;
+#[cfg(feature = "mdbook")]
+use crate::mdbook_api::{self, PreprocessorContext};
+#[cfg(feature = "template")]
+use crate::render::BloxTemplate;
 use anyhow::{Context, Result};
 use hex_color::HexColor;
-use mdbook::preprocess::PreprocessorContext;
 use serde::{Deserialize, Deserializer, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 /// Preprocessor name
-pub const PREPROCESSOR_NAME: &'static str = "blox";
-pub const CODE_BLOCK_KEYWORD: &'static str = PREPROCESSOR_NAME;
+pub const PREPROCESSOR_NAME: &str = "blox";
+pub const CODE_BLOCK_KEYWORD: &str = PREPROCESSOR_NAME;
 
 pub fn default_css_file() -> String {
     format!("assets/{PREPROCESSOR_NAME}.css")
 }
 
-#[derive(Deserialize)]
-pub struct MdbookConfig {
-    #[serde(default)]
-    preprocessor: PreprocessorsConfig,
-}
-
-#[derive(Default, Deserialize)]
-pub struct PreprocessorsConfig {
-    #[serde(default)]
-    blox: Config,
-}
+/// File of defaults for the chapters in a directory of the book source, like a part config
+pub const DIRECTORY_CONFIG_FILE: &str = ".blox.toml";
+/// Prefix of environment variables overriding configuration values
+pub const ENV_OVERRIDE_PREFIX: &str = "MDBOOK_BLOX__";
+/// Environment variable enabling `dry_run`, e.g. `MDBOOK_BLOX_DRY_RUN=1`
+pub const DRY_RUN_VAR: &str = "MDBOOK_BLOX_DRY_RUN";
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(default)]
 pub struct Config {
-    #[serde(deserialize_with = "sanitize_string_toml_ascii")]
-    pub css: String,
+    pub css: CssConfig,
+    /// File the navigation script is written to by the `css` command, e.g. `assets/blox.js`
+    pub js: Option<String>,
+    /// Inline the structural CSS rules at the top of chapters with blox
+    pub inline_critical_css: bool,
+    /// Lift blox with links or collapsible parts on hover and focus, and darken their header
+    pub interactive_css: bool,
+    /// Leave out all generated animations and transitions, not only for readers preferring
+    /// reduced motion
+    pub reduced_motion: bool,
+    /// Put the generated rules in the `@layer blox` cascade layer, below unlayered theme rules,
+    /// with an unlayered copy for browsers without cascade layers
+    pub css_layer: bool,
+    /// Leave the environment rules out of the stylesheet and inline those of the environments
+    /// each chapter uses at its end, for books with many environments
+    pub chapter_css: bool,
+    /// Render fenced blox with as many lines as their source, for debugging
+    pub preserve_lines: bool,
+    /// Render blox which fail to parse as error boxes instead of failing the build
+    pub lenient: bool,
+    /// List each blox in the `see_also` of the blox it lists
+    pub reciprocal_see_also: bool,
+    /// Make `\label{}`s of LaTeX equations in blox referable, linking to the blox
+    pub equation_labels: bool,
+    /// Render refs to labels defined nowhere as `??` placeholders, like LaTeX, numbered by first
+    /// use like blox, and report all of them once after rendering instead of warning about each
+    /// ref
+    pub ref_placeholders: bool,
+    /// Convert quotes, dashes and ellipses of titles and footers, like mdBook does for text
+    pub smart_punctuation: bool,
+    /// Make the number in the header a link to the blox itself
+    pub link_number: bool,
+    /// Wrap the number in the header in a `<span class="blox-number" data-number="2.3">`, for
+    /// print stylesheets and scripts restyling or deriving numbers
+    pub number_spans: bool,
+    /// Render headers of numbered blox as headings, for the outline of HTML-to-PDF pipelines
+    pub pdf_outline: bool,
+    /// Panes of blox content, started by lines like `---proof`, rendered collapsed
+    pub collapsed_panes: Vec<String>,
+    /// Fill the `{{ blank: answer }}` blanks of blox with their answers, for a teacher edition
+    pub answers: bool,
+    /// Render blox with `solution_of` where a chapter has `{{ blox-solutions }}`, in book order,
+    /// instead of where they are written
+    pub collect_solutions: bool,
+    /// Also parse blox in `:::blox env {options}` … `:::` containers, like in other Markdown
+    /// flavors, alongside fenced blocks
+    pub directives: bool,
+    /// Render GitHub alerts like `> [!NOTE]` as blox of the environment of the same name, like
+    /// `note`, if configured. Blox fenced within an alert are not nested in it.
+    pub github_alerts: bool,
+    /// Number blox within the `##` sections of chapters, like `2.3.1` for the first blox under
+    /// the third `##` heading of chapter 2
+    pub number_within_headings: bool,
+    /// Precede each rendered blox by a comment with a hash of its HTML, like
+    /// `<!-- blox:1a2b3c4d -->`, for tools detecting changed blox
+    pub fingerprint: bool,
+    /// Markup of rendered blox, Typst when preprocessing for the `typst` renderer
+    pub format: Option<OutputFormat>,
+    /// Link to pages without `.html` extension, and to `index.html` by directory
+    pub pretty_urls: bool,
+    /// Manifest of blox fragments to write, `.json` or plain text
+    pub fragment_manifest: Option<PathBuf>,
+    /// Fragment manifest (JSON) of a full build, resolving refs to blox outside a partial build
+    pub refs_fallback: Option<PathBuf>,
+    /// URL of the published book the fallback manifest belongs to. Links are relative if unset.
+    pub refs_fallback_url: Option<String>,
+    /// What the HTML ids of blox are derived from
+    pub id_source: IdSource,
+    /// Colors of the environments
+    pub palette: Palette,
+    /// Handlebars template of the HTML of blox, relative to the book root, with the `template`
+    /// feature
+    pub template: Option<PathBuf>,
+    /// `template`, compiled once when read along with the configuration
+    #[cfg(feature = "template")]
+    #[serde(skip)]
+    pub compiled_template: Option<BloxTemplate>,
+    /// Process the book and report as usual, but return it unchanged and write no manifest
+    pub dry_run: bool,
     defaults: ConfigDefaults,
     #[serde(deserialize_with = "sanitize_map_keys_toml_ascii")]
     pub environments: HashMap<String, EnvironmentConfig>,
+    /// Defaults scoped to book parts, keyed by part title
+    pub parts: HashMap<String, PartConfig>,
+    /// Defaults of the chapters in a directory, read from its `.blox.toml`, keyed by path
+    /// relative to the book source
+    #[serde(skip)]
+    pub directories: BTreeMap<PathBuf, PartConfig>,
+    /// Chapters listing the blox of some environments, like a list of theorems, keyed by name
+    pub lists: BTreeMap<String, ListConfig>,
+    /// Blox options applied with `profile = "name"`, keyed by name
+    pub profiles: HashMap<String, Profile>,
+    /// Number prefixes of unnumbered chapters, keyed by chapter path
+    pub chapter_prefix: HashMap<PathBuf, String>,
+    /// Start values of counters in chapters, overriding `counter_start`, keyed by chapter
+    /// path, then counter
+    pub chapter_counters: HashMap<PathBuf, HashMap<String, usize>>,
+    /// Renderer the book is preprocessed for
+    #[serde(skip)]
+    renderer: String,
+    /// Book root directory
+    #[serde(skip)]
+    root: PathBuf,
 }
 
 impl Config {
+    #[cfg(feature = "mdbook")]
     pub fn from_context(ctx: &PreprocessorContext) -> Result<Self> {
-        let table = ctx
-            .config
-            .get_preprocessor(PREPROCESSOR_NAME)
+        let table = mdbook_api::preprocessor_table(ctx, PREPROCESSOR_NAME)?
             .context("No configuration in book.toml")?;
-        let value = toml::Value::Table(table.clone());
-        let config: Self = Self::deserialize(value)?;
+        let overrides = ConfigOverride::from_env_vars(std::env::vars());
+        let mut config = Self::from_table(table, &overrides)?;
+        config.renderer = mdbook_api::renderer(ctx).to_string();
+        config.root = mdbook_api::root(ctx).to_path_buf();
+        config.read_directory_configs(&mdbook_api::source_dir(ctx))?;
+        config.read_template()?;
+        config.dry_run |= std::env::var(DRY_RUN_VAR).is_ok_and(|v| is_enabled(&v));
 
         Ok(config)
     }
 
-    pub fn from_file(file: &PathBuf) -> Result<Self> {
+    /// Reads a `book.toml`, applying overrides from environment variables, then `overrides`
+    #[cfg(feature = "mdbook")]
+    pub fn from_file(file: &PathBuf, overrides: &[ConfigOverride]) -> Result<Self> {
         let data = fs::read_to_string(file).context("Can't read configuration file")?;
-        let book_config: MdbookConfig =
-            toml::from_str(&data).context("Invalid configuration file")?;
-        Ok(book_config.preprocessor.blox)
+        let mut all_overrides = ConfigOverride::from_env_vars(std::env::vars());
+        all_overrides.extend_from_slice(overrides);
+
+        let mut config = Self::from_book_toml_with(&data, &all_overrides)?;
+        config.root = file.parent().map(PathBuf::from).unwrap_or_default();
+        let src = mdbook_api::book_src(&data)?;
+        config.read_directory_configs(&config.root.join(src))?;
+        config.read_template()?;
+        Ok(config)
+    }
+
+    /// Reads the configuration from the contents of a `book.toml`
+    pub fn from_book_toml(data: &str) -> Result<Self> {
+        Self::from_book_toml_with(data, &[])
+    }
+
+    pub fn from_book_toml_with(data: &str, overrides: &[ConfigOverride]) -> Result<Self> {
+        let book_config: toml::Value =
+            toml::from_str(data).context("Invalid configuration file")?;
+        let table = book_config
+            .get("preprocessor")
+            .and_then(|p| p.get(PREPROCESSOR_NAME))
+            .and_then(|c| c.as_table())
+            .cloned()
+            .unwrap_or_default();
+
+        Self::from_table(table, overrides).context("Invalid configuration file")
+    }
+
+    /// Reads the `[preprocessor.blox]` table with `overrides` applied in order
+    pub fn from_table(mut table: toml::value::Table, overrides: &[ConfigOverride]) -> Result<Self> {
+        for config_override in overrides {
+            config_override.apply(&mut table)?;
+        }
+
+        let mut config = Self::deserialize(toml::Value::Table(table))?;
+        config.apply_palette();
+        Ok(config)
+    }
+
+    /// Resolves a path relative to the book root
+    #[inline]
+    pub fn book_path(&self, path: &Path) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Markup of rendered blox
+    #[inline]
+    pub fn format(&self) -> OutputFormat {
+        self.format.unwrap_or(match self.renderer.as_str() {
+            "typst" => OutputFormat::Typst,
+            _ => OutputFormat::Html,
+        })
+    }
+
+    /// Links point to Markdown sources instead of rendered pages, for renderers like `linkcheck`
+    /// checking the sources. Those renderers are preprocessed like any other.
+    #[inline]
+    pub fn source_links(&self) -> bool {
+        SOURCE_LINK_RENDERERS.contains(&self.renderer.as_str())
+    }
+    /// Returns a copy of the configuration with the defaults of `part` applied, which take
+    /// precedence over both global and environment settings
+    pub fn scoped(&self, part: &str) -> Option<Self> {
+        self.scoped_to(Some(part), None)
+    }
+    /// Returns a copy of the configuration with the defaults of `part`, then those of the
+    /// directories containing `chapter` from the outermost applied
+    pub fn scoped_to(&self, part: Option<&str>, chapter: Option<&Path>) -> Option<Self> {
+        let scopes = self.scopes(part, chapter);
+        if scopes.is_empty() {
+            return None;
+        }
+
+        let mut config = self.clone();
+        for (_, scope) in scopes {
+            for env in config.environments.values_mut() {
+                env.color = scope.color.or(env.color);
+                env.header = scope.header().or(env.header());
+                env.hide_footer = scope.hide_footer.or(env.hide_footer);
+                env.numbered = scope.numbered.or(env.numbered);
+            }
+        }
+
+        Some(config)
+    }
+    /// CSS classes of the part and directories with scoped defaults a chapter is in
+    pub fn scope_classes(&self, part: Option<&str>, chapter: Option<&Path>) -> Option<String> {
+        let classes: Vec<String> = self
+            .scopes(part, chapter)
+            .into_iter()
+            .map(|(class, _)| class)
+            .collect();
+        (!classes.is_empty()).then(|| classes.join(" "))
     }
+    /// Scoped defaults of a chapter with their CSS classes, in order of precedence
+    fn scopes(&self, part: Option<&str>, chapter: Option<&Path>) -> Vec<(String, &PartConfig)> {
+        let part = part.and_then(|p| Some((part_class(p), self.parts.get(p)?)));
+        let mut directories: Vec<(String, &PartConfig)> = chapter
+            .and_then(Path::parent)
+            .map(|dir| {
+                dir.ancestors()
+                    .filter_map(|d| Some((directory_class(d), self.directories.get(d)?)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        directories.reverse();
 
+        part.into_iter().chain(directories).collect()
+    }
+    /// Reads and checks the `template` file
+    pub fn read_template(&mut self) -> Result<()> {
+        let Some(template) = self.template.as_deref() else {
+            return Ok(());
+        };
+
+        let file = self.book_path(template);
+        #[cfg(not(feature = "template"))]
+        log::warn!(
+            "Built without the `template` feature, ignoring blox template {}",
+            file.display()
+        );
+        #[cfg(feature = "template")]
+        {
+            let source = fs::read_to_string(&file)
+                .with_context(|| format!("Can't read blox template {}", file.display()))?;
+            let template = BloxTemplate::new(source)
+                .with_context(|| format!("Invalid blox template {}", file.display()))?;
+            self.compiled_template = Some(template);
+        }
+        Ok(())
+    }
+    /// Reads the `.blox.toml` files in the subdirectories of the book source `src`
+    pub fn read_directory_configs(&mut self, src: &Path) -> Result<()> {
+        self.directories.clear();
+        if src.is_dir() {
+            read_directory_configs(src, src, &mut self.directories)?;
+        }
+        Ok(())
+    }
+    /// Replaces the colors of the environments, in order, by those of the palette
+    fn apply_palette(&mut self) {
+        let Some(colors) = self.palette.colors() else {
+            return;
+        };
+
+        let keys: Vec<String> = self
+            .environment_keys()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        if let Some(reused) = keys.get(colors.len()..).filter(|k| !k.is_empty()) {
+            log::warn!(
+                "The palette has {} colors, so environments {} reuse the colors of earlier ones",
+                colors.len(),
+                reused.join(", ")
+            );
+        }
+        for (key, color) in keys.iter().zip(colors.iter().cycle()) {
+            if let Some(env) = self.environments.get_mut(key) {
+                env.color = Some(HexColor::from_u24(*color));
+            }
+        }
+    }
+    /// Number prefix of an unnumbered chapter, like `Int.` for `chapter_prefix = "Int"`
+    #[inline]
+    pub fn chapter_prefix(&self, path: &Path) -> Option<String> {
+        let prefix = self.chapter_prefix.get(path)?;
+        match prefix.ends_with('.') {
+            true => Some(prefix.clone()),
+            false => Some(format!("{prefix}.")),
+        }
+    }
+    /// Environment keys by `order`, then alphabetically
+    pub fn environment_keys(&self) -> Vec<&str> {
+        let mut keys: Vec<(i32, &str)> = self
+            .environments
+            .iter()
+            .map(|(key, env)| (env.order, key.as_str()))
+            .collect();
+        keys.sort();
+        keys.into_iter().map(|(_, key)| key).collect()
+    }
     #[inline]
     pub fn has_environment(&self, key: &str) -> bool {
         self.environments.contains_key(key)
@@ -72,10 +356,52 @@ impl Config {
         Ok(format!("{CODE_BLOCK_KEYWORD}-{key}"))
     }
     #[inline]
-    pub fn name(&self, key: &str) -> &str {
-        self.get(key)
+    pub fn name(&self, key: &str) -> Cow<'_, str> {
+        let name = self
+            .get(key)
             .map(|e| e.name.as_str())
-            .unwrap_or("ENVIRONMENT")
+            .unwrap_or("ENVIRONMENT");
+        self.name_case(key).apply(name)
+    }
+    #[inline]
+    pub fn kind(&self, key: &str) -> EnvironmentKind {
+        self.get(key).map(|e| e.kind).unwrap_or_default()
+    }
+    /// Whether the caption of figures and tables is above them, by default only for tables
+    #[inline]
+    pub fn caption_above(&self, key: &str) -> bool {
+        self.get(key)
+            .and_then(|e| e.caption_above)
+            .unwrap_or(self.kind(key) == EnvironmentKind::Table)
+    }
+    #[inline]
+    pub fn lightbox(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|e| e.lightbox.as_deref())
+    }
+    /// Number of the first blox of a counter, and of the first of each section
+    #[inline]
+    pub fn counter_start(&self, key: &str) -> usize {
+        self.get(key)
+            .and_then(|e| e.counter_start)
+            .unwrap_or(self.defaults.counter_start)
+    }
+    #[inline]
+    pub fn counter_step(&self, key: &str) -> usize {
+        self.get(key)
+            .and_then(|e| e.counter_step)
+            .unwrap_or(self.defaults.counter_step)
+    }
+    #[inline]
+    pub fn number_style(&self, key: &str) -> NumberStyle {
+        self.get(key)
+            .and_then(|e| e.number_style)
+            .unwrap_or(self.defaults.number_style)
+    }
+    #[inline]
+    pub fn name_case(&self, key: &str) -> NameCase {
+        self.get(key)
+            .and_then(|e| e.name_case)
+            .unwrap_or(self.defaults.name_case)
     }
     #[inline]
     pub fn color(&self, key: &str) -> &HexColor {
@@ -83,22 +409,147 @@ impl Config {
             .and_then(|e| e.color.as_ref())
             .unwrap_or(&self.defaults.color)
     }
+    /// Counter numbering the blox of an environment, the environment itself unless shared
+    #[inline]
+    pub fn counter<'c>(&'c self, key: &'c str) -> &'c str {
+        self.get(key)
+            .and_then(|e| e.counter.as_deref())
+            .unwrap_or(key)
+    }
+    /// Color in the dark themes, if it differs from `color`
+    #[inline]
+    pub fn color_dark(&self, key: &str) -> Option<&HexColor> {
+        self.get(key)
+            .and_then(|e| e.color_dark.as_ref())
+            .or(self.defaults.color_dark.as_ref())
+    }
+    #[inline]
+    pub fn border_width(&self, key: &str) -> &str {
+        self.get(key)
+            .and_then(|e| e.border_width.as_deref())
+            .unwrap_or(&self.defaults.border_width)
+    }
+    #[inline]
+    pub fn border_style(&self, key: &str) -> &str {
+        self.get(key)
+            .and_then(|e| e.border_style.as_deref())
+            .unwrap_or(&self.defaults.border_style)
+    }
+    #[inline]
+    pub fn radius(&self, key: &str) -> Option<&str> {
+        self.get(key)
+            .and_then(|e| e.radius.as_deref())
+            .or(self.defaults.radius.as_deref())
+    }
+    /// Opacity of the header tint, as alpha from 0 to 255
+    #[inline]
+    pub fn header_alpha(&self, key: &str) -> u8 {
+        let percent = self
+            .get(key)
+            .and_then(|e| e.header_opacity)
+            .unwrap_or(self.defaults.header_opacity)
+            .min(100);
+        ((u32::from(percent) * 255 + 50) / 100) as u8
+    }
     pub fn prefix_number(&self, key: &str) -> bool {
         self.get(key)
             .and_then(|e| e.prefix_number)
             .unwrap_or(self.defaults.prefix_number)
     }
     #[inline]
-    pub fn hide_name(&self, key: &str) -> bool {
+    pub fn unique_titles(&self, key: &str) -> bool {
         self.get(key)
-            .and_then(|e| e.hide_name)
-            .unwrap_or(self.defaults.hide_name)
+            .and_then(|e| e.unique_titles)
+            .unwrap_or(self.defaults.unique_titles)
+    }
+    #[inline]
+    pub fn show_reading_time(&self, key: &str) -> bool {
+        self.get(key)
+            .and_then(|e| e.show_reading_time)
+            .unwrap_or(self.defaults.show_reading_time)
+    }
+    #[inline]
+    pub fn navigation(&self, key: &str) -> bool {
+        self.get(key)
+            .and_then(|e| e.navigation)
+            .unwrap_or(self.defaults.navigation)
+    }
+    #[inline]
+    pub fn qed(&self, key: &str) -> bool {
+        self.get(key)
+            .and_then(|e| e.qed)
+            .unwrap_or(self.defaults.qed)
+    }
+    #[inline]
+    pub fn print(&self, key: &str) -> PrintVisibility {
+        self.get(key)
+            .and_then(|e| e.print)
+            .unwrap_or(self.defaults.print)
+    }
+    #[inline]
+    pub fn content_prefix(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|e| e.content_prefix.as_deref())
+    }
+    #[inline]
+    pub fn content_suffix(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|e| e.content_suffix.as_deref())
+    }
+    #[inline]
+    pub fn unique_titles_ignore(&self, key: &str) -> &[String] {
+        self.get(key)
+            .map(|e| e.unique_titles_ignore.as_slice())
+            .unwrap_or_default()
+    }
+    /// Header visibility of an environment, see [`HeaderConfig`] for the precedence
+    #[inline]
+    pub fn header(&self, key: &str) -> HeaderConfig {
+        let defaults = self.defaults.header.or(HeaderConfig::from_hidden(
+            Some(self.defaults.hide_header),
+            Some(self.defaults.hide_name),
+        ));
+
+        match self.get(key) {
+            Some(e) => e.header().or(defaults),
+            None => defaults,
+        }
+    }
+    #[inline]
+    pub fn hide_name(&self, key: &str) -> bool {
+        !self.header(key).resolve().name
     }
     #[inline]
     pub fn hide_header(&self, key: &str) -> bool {
+        !self.header(key).resolve().show
+    }
+    #[inline]
+    pub fn nameless_numbering(&self, key: &str) -> NamelessNumbering {
+        self.get(key)
+            .and_then(|e| e.nameless_numbering)
+            .unwrap_or(self.defaults.nameless_numbering)
+    }
+    #[inline]
+    pub fn ref_text_when_hidden(&self, key: &str) -> RefText {
+        self.get(key)
+            .and_then(|e| e.ref_text_when_hidden)
+            .unwrap_or(self.defaults.ref_text_when_hidden)
+    }
+    #[inline]
+    pub fn hide_footer(&self, key: &str) -> bool {
+        self.get(key)
+            .and_then(|e| e.hide_footer)
+            .unwrap_or(self.defaults.hide_footer)
+    }
+    #[inline]
+    pub fn collapsible(&self, key: &str) -> bool {
         self.get(key)
-            .and_then(|e| e.hide_header)
-            .unwrap_or(self.defaults.hide_header)
+            .and_then(|e| e.collapsible)
+            .unwrap_or(self.defaults.collapsible)
+    }
+    #[inline]
+    pub fn open(&self, key: &str) -> bool {
+        self.get(key)
+            .and_then(|e| e.open)
+            .unwrap_or(self.defaults.open)
     }
     #[inline]
     pub fn numbered(&self, key: &str) -> bool {
@@ -111,21 +562,406 @@ impl Config {
 impl Default for Config {
     fn default() -> Self {
         Self {
-            css: default_css_file(),
+            css: CssConfig::File(default_css_file()),
+            js: None,
+            inline_critical_css: false,
+            interactive_css: false,
+            reduced_motion: false,
+            css_layer: false,
+            chapter_css: false,
+            preserve_lines: false,
+            lenient: false,
+            reciprocal_see_also: false,
+            equation_labels: false,
+            ref_placeholders: false,
+            smart_punctuation: false,
+            link_number: false,
+            number_spans: false,
+            pdf_outline: false,
+            collapsed_panes: vec!["proof".to_string()],
+            answers: false,
+            collect_solutions: false,
+            directives: false,
+            github_alerts: false,
+            number_within_headings: false,
+            fingerprint: false,
+            format: None,
+            pretty_urls: false,
+            fragment_manifest: None,
+            refs_fallback: None,
+            refs_fallback_url: None,
+            id_source: IdSource::default(),
+            palette: Palette::default(),
+            template: None,
+            #[cfg(feature = "template")]
+            compiled_template: None,
+            dry_run: false,
             defaults: ConfigDefaults::default(),
             environments: HashMap::new(),
+            parts: HashMap::new(),
+            directories: BTreeMap::new(),
+            lists: BTreeMap::new(),
+            profiles: HashMap::new(),
+            chapter_prefix: HashMap::new(),
+            chapter_counters: HashMap::new(),
+            renderer: String::new(),
+            root: PathBuf::new(),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdSource {
+    /// Only labelled blox have ids
+    #[default]
+    Label,
+    /// Anonymous blox get ids from a hash of their content, which survive reordering
+    ContentHash,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Palette {
+    /// Colors as configured
+    #[default]
+    Configured,
+    /// Okabe-Ito colors, distinguishable with the common forms of color blindness. There are
+    /// eight, reused with a warning by further environments.
+    ColorblindSafe,
+}
+
+impl Palette {
+    fn colors(&self) -> Option<&'static [u32]> {
+        match self {
+            Self::Configured => None,
+            Self::ColorblindSafe => Some(&[
+                0x0072B2, // Blue
+                0xE69F00, // Orange
+                0x009E73, // Bluish green
+                0xD55E00, // Vermillion
+                0xCC79A7, // Reddish purple
+                0x56B4E9, // Sky blue
+                0xF0E442, // Yellow
+                0x000000, // Black
+            ]),
+        }
+    }
+}
+
+/// A chapter listing blox, e.g. `[preprocessor.blox.lists.theorem] path = "theorems.md"`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ListConfig {
+    /// Chapter path, relative to the book source. A chapter of the summary with this path is
+    /// filled with the list, otherwise the list is added after the last chapter.
+    pub path: PathBuf,
+    /// Heading of the chapter, the plural of the first environment name if unset
+    pub title: Option<String>,
+    /// Environments listed, the name of the list if empty
+    pub environments: Vec<String>,
+}
+
+/// Blox options of a profile, e.g. `[preprocessor.blox.profiles.exam] hide_header = true`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Profile(pub toml::value::Table);
+
+/// Markup blox are rendered to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    #[default]
+    Html,
+    /// A call of a Typst function named after the environment, with the content and footer
+    /// converted to Typst markup, for mdBook-to-Typst pipelines
+    Typst,
+}
+
+/// Stylesheets written by `blox css`, e.g. `css = { screen = "blox.css", print = "print.css" }`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum CssConfig {
+    /// A single stylesheet for all media
+    File(String),
+    /// Stylesheets by media type
+    Media(BTreeMap<String, String>),
+}
+
+impl CssConfig {
+    /// Pairs of media type and file
+    pub fn targets(&self) -> Vec<(&str, &str)> {
+        match self {
+            Self::File(file) => vec![("all", file.as_str())],
+            Self::Media(files) => files
+                .iter()
+                .map(|(media, file)| (media.as_str(), file.as_str()))
+                .collect(),
+        }
+    }
+}
+
+/// Override of a configuration value, like `defaults.color=#112233`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigOverride {
+    /// Dotted key within `[preprocessor.blox]`
+    pub key: String,
+    /// TOML value, or a string if it doesn't parse as one
+    pub value: String,
+}
+
+impl FromStr for ConfigOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (key, value) = s
+            .split_once('=')
+            .with_context(|| format!("Expected key=value: {s}"))?;
+        anyhow::ensure!(!key.trim().is_empty(), "Empty configuration key: {s}");
+
+        Ok(Self {
+            key: key.trim().to_string(),
+            value: value.trim().to_string(),
+        })
+    }
+}
+
+impl ConfigOverride {
+    /// Overrides from variables like `MDBOOK_BLOX__DEFAULTS__NUMBERED=false`, sorted by key
+    pub fn from_env_vars(vars: impl IntoIterator<Item = (String, String)>) -> Vec<Self> {
+        let mut overrides: Vec<Self> = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let key = name.strip_prefix(ENV_OVERRIDE_PREFIX)?;
+                Some(Self {
+                    key: key.to_lowercase().replace("__", "."),
+                    value,
+                })
+            })
+            .collect();
+        overrides.sort_by(|a, b| a.key.cmp(&b.key));
+        overrides
+    }
+
+    fn apply(&self, table: &mut toml::value::Table) -> Result<()> {
+        let mut keys: Vec<&str> = self.key.split('.').collect();
+        let last = keys.pop().context("Empty configuration key")?;
+
+        let mut table = table;
+        for key in keys {
+            table = table
+                .entry(key.to_string())
+                .or_insert_with(|| toml::Value::Table(Default::default()))
+                .as_table_mut()
+                .with_context(|| format!("Can't override {}: {key} is not a table", self.key))?;
+        }
+
+        let value = toml::from_str::<toml::value::Table>(&format!("value = {}", self.value))
+            .ok()
+            .and_then(|mut t| t.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(self.value.clone()));
+        table.insert(last.to_string(), value);
+
+        Ok(())
+    }
+}
+
+/// Numbering of blox whose name or header is hidden
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NamelessNumbering {
+    /// Not numbered
+    #[default]
+    Off,
+    /// Numbered, with the number alone in the header
+    Header,
+    /// Numbered, with the number only used by refs
+    Refs,
+}
+
+/// Text of plain refs to blox with a hidden header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RefText {
+    /// Name, number and title, like `Theorem 2.4: Title`
+    #[default]
+    Full,
+    /// Title, or the full text if there is none
+    Title,
+    /// Name and number, or the full text if unnumbered
+    Number,
+}
+
+/// Structure of the blox of an environment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EnvironmentKind {
+    /// Header, content and footer
+    #[default]
+    Block,
+    /// A single image with the header as caption below it
+    Figure,
+    /// Display math, with its number in parentheses flush right
+    Equation,
+    /// A Markdown table with the header as caption, above it by default
+    Table,
+    /// A block defining the term in its title, listed by `{{ blox-glossary }}`
+    Glossary,
+}
+
+/// Casing of environment names in headers and refs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameCase {
+    #[default]
+    AsIs,
+    /// First letter of each word upper case, the rest lower case
+    Title,
+    Upper,
+    Lower,
+}
+
+impl NameCase {
+    pub fn apply<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self {
+            NameCase::AsIs => Cow::Borrowed(name),
+            NameCase::Upper => Cow::Owned(name.to_uppercase()),
+            NameCase::Lower => Cow::Owned(name.to_lowercase()),
+            NameCase::Title => Cow::Owned(
+                name.split(' ')
+                    .map(|word| {
+                        let mut chars = word.chars();
+                        match chars.next() {
+                            Some(first) => first
+                                .to_uppercase()
+                                .chain(chars.flat_map(char::to_lowercase))
+                                .collect(),
+                            None => String::new(),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+        }
+    }
+}
+
+/// Style of the number of a blox within its section
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum NumberStyle {
+    #[default]
+    #[serde(rename = "arabic")]
+    Arabic,
+    #[serde(rename = "roman")]
+    LowerRoman,
+    #[serde(rename = "Roman")]
+    UpperRoman,
+    /// `a` to `z`, then `aa`, `ab`, ...
+    #[serde(rename = "alpha")]
+    LowerAlpha,
+    #[serde(rename = "Alpha")]
+    UpperAlpha,
+}
+
+impl NumberStyle {
+    pub fn format(&self, n: usize) -> String {
+        match self {
+            NumberStyle::Arabic => n.to_string(),
+            NumberStyle::LowerRoman => roman(n).to_lowercase(),
+            NumberStyle::UpperRoman => roman(n),
+            NumberStyle::LowerAlpha => alpha(n),
+            NumberStyle::UpperAlpha => alpha(n).to_uppercase(),
+        }
+    }
+}
+
+/// Roman numeral, arabic from 4000 as there is no standard form
+fn roman(mut n: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    if n == 0 || n >= 4000 {
+        return n.to_string();
+    }
+
+    let mut s = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            s.push_str(numeral);
+            n -= value;
+        }
+    }
+    s
+}
+
+/// Bijective base-26 letters, like spreadsheet columns
+fn alpha(mut n: usize) -> String {
+    if n == 0 {
+        return n.to_string();
+    }
+
+    let mut letters = Vec::new();
+    while n > 0 {
+        n -= 1;
+        letters.push(char::from(b'a' + (n % 26) as u8));
+        n /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+/// Visibility of blox when printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrintVisibility {
+    #[default]
+    Show,
+    Hide,
+    /// Only the header
+    OutlineOnly,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(default)]
 pub struct ConfigDefaults {
+    #[serde(deserialize_with = "deserialize_color")]
     color: HexColor,
+    #[serde(deserialize_with = "deserialize_color_option")]
+    color_dark: Option<HexColor>,
+    border_width: String,
+    border_style: String,
+    radius: Option<String>,
+    header_opacity: u8,
     prefix_number: bool,
+    nameless_numbering: NamelessNumbering,
+    ref_text_when_hidden: RefText,
+    unique_titles: bool,
+    show_reading_time: bool,
+    navigation: bool,
+    qed: bool,
+    print: PrintVisibility,
+    name_case: NameCase,
+    number_style: NumberStyle,
+    counter_start: usize,
+    counter_step: usize,
     // BloxOptions
+    header: HeaderConfig,
     hide_name: bool,
     hide_header: bool,
+    hide_footer: bool,
+    collapsible: bool,
+    open: bool,
     numbered: bool,
 }
 
@@ -133,9 +969,29 @@ impl Default for ConfigDefaults {
     fn default() -> Self {
         Self {
             color: HexColor::from_u24(0xCE0037), // SLU Red
+            color_dark: None,
+            border_width: "0.4em".to_string(),
+            border_style: "solid".to_string(),
+            radius: None,
+            header_opacity: 10,
             prefix_number: true,
+            nameless_numbering: NamelessNumbering::default(),
+            ref_text_when_hidden: RefText::default(),
+            unique_titles: false,
+            show_reading_time: false,
+            navigation: false,
+            qed: false,
+            print: PrintVisibility::default(),
+            name_case: NameCase::default(),
+            number_style: NumberStyle::default(),
+            counter_start: 1,
+            counter_step: 1,
+            header: HeaderConfig::default(),
             hide_name: false,
             hide_header: false,
+            hide_footer: false,
+            collapsible: false,
+            open: true,
             numbered: true,
         }
     }
@@ -145,11 +1001,78 @@ impl Default for ConfigDefaults {
 #[serde(default)]
 pub struct EnvironmentConfig {
     name: String,
+    name_case: Option<NameCase>,
+    /// Style of the blox number within the section, like `b` or `ii`
+    number_style: Option<NumberStyle>,
+    /// Structure of the rendered blox
+    kind: EnvironmentKind,
+    /// `data-lightbox` group of the images of figures
+    lightbox: Option<String>,
+    /// Put the caption of figures and tables above them instead of below
+    caption_above: Option<bool>,
+    /// Position in generated output like CSS, before environments of higher order
+    order: i32,
+    /// Counter shared with other environments, like `thmlike` for theorems and lemmas
+    counter: Option<String>,
+    /// Number of the first blox, like 0 for exercises numbered from 0
+    counter_start: Option<usize>,
+    /// Increment between numbers, like 10 for exercises numbered by tens
+    counter_step: Option<usize>,
+    /// Like `#336699`, `#33669980`, `rgb(51 102 153)`, `hsl(210, 50%, 40%)` or `steelblue`
+    #[serde(deserialize_with = "deserialize_color_option")]
     color: Option<HexColor>,
+    /// Color in the dark themes of mdBook, `color` if unset
+    #[serde(deserialize_with = "deserialize_color_option")]
+    color_dark: Option<HexColor>,
+    /// Width of the border at the start of the blox, like `0.4em`
+    border_width: Option<String>,
+    /// CSS style of the border, like `solid` or `dashed`
+    border_style: Option<String>,
+    /// Radius of the corners, like `0.3em`
+    radius: Option<String>,
+    /// Opacity of the tint of the header in percent, 10 by default
+    header_opacity: Option<u8>,
     prefix_number: Option<bool>,
+    nameless_numbering: Option<NamelessNumbering>,
+    ref_text_when_hidden: Option<RefText>,
+    /// Warn about blox with the same title
+    unique_titles: Option<bool>,
+    /// Titles allowed to occur more than once
+    unique_titles_ignore: Vec<String>,
+    /// Show the estimated reading time of the content in the header
+    show_reading_time: Option<bool>,
+    /// Link each blox to the previous and next one of the environment
+    navigation: Option<bool>,
+    /// End the blox with a tombstone `∎` in the footer, like proofs
+    qed: Option<bool>,
+    /// Visibility when printed, e.g. to leave solutions out of printed exams
+    print: Option<PrintVisibility>,
+    /// Markdown inserted before the content of every blox
+    content_prefix: Option<String>,
+    /// Markdown inserted after the content of every blox
+    content_suffix: Option<String>,
     // BloxOptions
+    header: HeaderConfig,
     hide_name: Option<bool>,
     hide_header: Option<bool>,
+    hide_footer: Option<bool>,
+    /// Render as a `<details>` element with the header as summary
+    collapsible: Option<bool>,
+    /// Collapsible blox are expanded until clicked
+    open: Option<bool>,
+    numbered: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PartConfig {
+    #[serde(deserialize_with = "deserialize_color_option")]
+    pub color: Option<HexColor>,
+    // BloxOptions
+    header: HeaderConfig,
+    hide_name: Option<bool>,
+    hide_header: Option<bool>,
+    hide_footer: Option<bool>,
     numbered: Option<bool>,
 }
 
@@ -157,23 +1080,224 @@ impl Default for EnvironmentConfig {
     fn default() -> Self {
         Self {
             name: "ENVIRONMENT UNDEFINED".to_string(),
+            name_case: None,
+            number_style: None,
+            kind: EnvironmentKind::default(),
+            lightbox: None,
+            caption_above: None,
+            order: 0,
+            counter: None,
+            counter_start: None,
+            counter_step: None,
             color: None,
+            color_dark: None,
+            border_width: None,
+            border_style: None,
+            radius: None,
+            header_opacity: None,
             prefix_number: None,
+            nameless_numbering: None,
+            ref_text_when_hidden: None,
+            unique_titles: None,
+            show_reading_time: None,
+            navigation: None,
+            qed: None,
+            print: None,
+            content_prefix: None,
+            content_suffix: None,
+            unique_titles_ignore: Vec::new(),
             // BloxOptions
+            header: HeaderConfig::default(),
             hide_name: None,
             hide_header: None,
+            hide_footer: None,
+            collapsible: None,
+            open: None,
             numbered: None,
         }
     }
 }
 
+impl EnvironmentConfig {
+    #[inline]
+    fn header(&self) -> HeaderConfig {
+        self.header
+            .or(HeaderConfig::from_hidden(self.hide_header, self.hide_name))
+    }
+}
+
+impl PartConfig {
+    #[inline]
+    fn header(&self) -> HeaderConfig {
+        self.header
+            .or(HeaderConfig::from_hidden(self.hide_header, self.hide_name))
+    }
+}
+
+/// Visibility of the parts of a blox header, e.g. `header = { name = false }`.
+///
+/// Each part is taken from the first level setting it: the block's `header`, the block's
+/// `hide_header`/`hide_name`, then the same for the book part, the environment and `defaults`.
+/// Parts set nowhere are shown. A hidden header hides all parts, and blox with a hidden name
+/// are only numbered with `nameless_numbering`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeaderConfig {
+    pub show: Option<bool>,
+    pub name: Option<bool>,
+    pub number: Option<bool>,
+    pub title: Option<bool>,
+}
+
+impl HeaderConfig {
+    /// From the `hide_header` and `hide_name` shorthands
+    pub fn from_hidden(hide_header: Option<bool>, hide_name: Option<bool>) -> Self {
+        Self {
+            show: hide_header.map(|h| !h),
+            name: hide_name.map(|h| !h),
+            ..Default::default()
+        }
+    }
+
+    /// Parts unset in `self` are taken from `other`
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            show: self.show.or(other.show),
+            name: self.name.or(other.name),
+            number: self.number.or(other.number),
+            title: self.title.or(other.title),
+        }
+    }
+
+    pub fn resolve(self) -> HeaderVisibility {
+        HeaderVisibility {
+            show: self.show.unwrap_or(true),
+            name: self.name.unwrap_or(true),
+            number: self.number.unwrap_or(true),
+            title: self.title.unwrap_or(true),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HeaderVisibility {
+    pub show: bool,
+    pub name: bool,
+    pub number: bool,
+    pub title: bool,
+}
+
+impl Default for HeaderVisibility {
+    fn default() -> Self {
+        HeaderConfig::default().resolve()
+    }
+}
+
+fn read_directory_configs(
+    src: &Path,
+    dir: &Path,
+    directories: &mut BTreeMap<PathBuf, PartConfig>,
+) -> Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Can't read directory {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+
+    for path in entries.into_iter().filter(|p| p.is_dir()) {
+        let file = path.join(DIRECTORY_CONFIG_FILE);
+        if file.is_file() {
+            let data = fs::read_to_string(&file)?;
+            let directory_config = toml::from_str(&data)
+                .with_context(|| format!("Invalid configuration file {}", file.display()))?;
+            let key = path.strip_prefix(src).unwrap_or(&path).to_path_buf();
+            directories.insert(key, directory_config);
+        }
+        read_directory_configs(src, &path, directories)?;
+    }
+
+    Ok(())
+}
+
+/// CSS class of blox in a book part
+pub fn part_class(part: &str) -> String {
+    let slug = to_toml_ascii(&part.trim().to_lowercase().replace(char::is_whitespace, "-"));
+    format!("{CODE_BLOCK_KEYWORD}-part-{slug}")
+}
+
+/// Whether a flag variable is set to something other than empty, `0` or `false`
+pub fn is_enabled(value: &str) -> bool {
+    !matches!(value.trim().to_lowercase().as_str(), "" | "0" | "false")
+}
+
+/// CSS class of blox in a directory of the book source with scoped defaults
+pub fn directory_class(dir: &Path) -> String {
+    let slug: Vec<String> = dir
+        .iter()
+        .map(|c| to_toml_ascii(&c.to_string_lossy().to_lowercase()))
+        .collect();
+    format!("{CODE_BLOCK_KEYWORD}-dir-{}", slug.join("-"))
+}
+
+/// ASCII form of Unicode text for identifiers: Latin letters lose their diacritics, other
+/// characters like CJK and emoji become `u` and their hex code point, so distinct labels stay
+/// distinct. Joiners, variation selectors and combining marks are dropped.
+pub fn transliterate(string: &str) -> String {
+    let mut out = String::with_capacity(string.len());
+    for c in string.chars() {
+        if c.is_ascii() {
+            out.push(c);
+        } else if matches!(c, '\u{200D}' | '\u{FE00}'..='\u{FE0F}' | '\u{0300}'..='\u{036F}') {
+            continue;
+        } else if let Some(latin) = latin_ascii(c.to_lowercase().next().unwrap_or(c)) {
+            match c.is_uppercase() {
+                true => out.push_str(&latin.to_uppercase()),
+                false => out.push_str(latin),
+            }
+        } else {
+            out.push_str(&format!("u{:x}", c as u32));
+        }
+    }
+    out
+}
+
+/// ASCII letters of a lowercase Latin letter with diacritics
+fn latin_ascii(c: char) -> Option<&'static str> {
+    Some(match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'ç' | 'ć' | 'č' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => "e",
+        'ğ' => "g",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'į' | 'ı' => "i",
+        'ł' | 'ľ' => "l",
+        'ñ' | 'ń' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+        'ŕ' | 'ř' => "r",
+        'ś' | 'š' | 'ş' => "s",
+        'ť' | 'ţ' => "t",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => "u",
+        'ý' | 'ÿ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        'þ' => "th",
+        _ => return None,
+    })
+}
+
 pub fn to_toml_ascii(string: &str) -> String {
-    string
+    transliterate(string)
         .chars()
         .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
         .collect()
 }
 
+/// Deserializes a string keeping only the characters of [`to_toml_ascii`]. No longer applied to
+/// `css`, which it turned from a path like `theme/blox.css` into `themebloxcss`, but kept for
+/// library users deserializing their own identifiers.
 pub fn sanitize_string_toml_ascii<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
 where
     D: Deserializer<'de>,
@@ -201,7 +1325,7 @@ pub(crate) mod test {
     use super::*;
     use pretty_assertions::assert_eq;
 
-    const CONFIG_STR: &'static str = r##"
+    const CONFIG_STR: &str = r##"
 [defaults]
 color = "#FF0000"
 numbered = true
@@ -220,21 +1344,15 @@ quote = {name = "Quote", color = "#CCCCCC", numbered = false, hide_name = true}
             EnvironmentConfig {
                 name: "Alert".to_string(),
                 color: Some(HexColor::from_u24(0x00FF00)),
-                prefix_number: None,
-                hide_name: None,
-                hide_header: None,
                 numbered: Some(false),
+                ..Default::default()
             },
         );
         config.environments.insert(
             "exercise".to_string(),
             EnvironmentConfig {
                 name: "Exercise".to_string(),
-                color: None,
-                prefix_number: None,
-                hide_name: None,
-                hide_header: None,
-                numbered: None,
+                ..Default::default()
             },
         );
         config.environments.insert(
@@ -242,10 +1360,9 @@ quote = {name = "Quote", color = "#CCCCCC", numbered = false, hide_name = true}
             EnvironmentConfig {
                 name: "Quote".to_string(),
                 color: Some(HexColor::from_u24(0xCCCCCC)),
-                prefix_number: None,
                 hide_name: Some(true),
-                hide_header: None,
                 numbered: Some(false),
+                ..Default::default()
             },
         );
 
@@ -274,4 +1391,312 @@ quote = {name = "Quote", color = "#CCCCCC", numbered = false, hide_name = true}
 
         Ok(())
     }
+
+    #[test]
+    fn test_part_scope() -> Result<()> {
+        let mut config = default_test_config();
+        config.parts.insert(
+            "Part II".to_string(),
+            PartConfig {
+                color: Some(HexColor::from_u24(0x0000FF)),
+                numbered: Some(false),
+                ..Default::default()
+            },
+        );
+
+        assert!(config.scoped("Part I").is_none());
+        let scoped = config.scoped("Part II").unwrap();
+        assert_eq!(*scoped.color("alert"), HexColor::from_u24(0x0000FF));
+        assert_eq!(scoped.numbered("exercise"), false);
+        assert_eq!(scoped.hide_name("quote"), true);
+        assert_eq!(part_class("Part II"), "blox-part-part-ii");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_scope() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let src = dir.path();
+        fs::create_dir_all(src.join("part2/sub"))?;
+        fs::write(
+            src.join("part2").join(DIRECTORY_CONFIG_FILE),
+            "color = \"#0000FF\"\nnumbered = false\n",
+        )?;
+        fs::write(
+            src.join("part2/sub").join(DIRECTORY_CONFIG_FILE),
+            "color = \"#00FF00\"\n",
+        )?;
+
+        let mut config = default_test_config();
+        config.read_directory_configs(src)?;
+        config.parts.insert(
+            "Part".to_string(),
+            PartConfig {
+                numbered: Some(true),
+                hide_footer: Some(true),
+                ..Default::default()
+            },
+        );
+
+        let chapter = Path::new("part2/sub/chapter.md");
+        assert!(
+            config
+                .scoped_to(None, Some(Path::new("intro.md")))
+                .is_none()
+        );
+        let scoped = config.scoped_to(Some("Part"), Some(chapter)).unwrap();
+        assert_eq!(*scoped.color("alert"), HexColor::from_u24(0x00FF00));
+        assert_eq!(scoped.numbered("exercise"), false);
+        assert_eq!(scoped.hide_footer("exercise"), true);
+        assert_eq!(
+            config.scope_classes(Some("Part"), Some(chapter)).as_deref(),
+            Some("blox-part-part blox-dir-part2 blox-dir-part2-sub")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transliterate() {
+        assert_eq!(to_toml_ascii("Théorème de Gauß"), "TheoremedeGauss");
+        assert_eq!(to_toml_ascii("ÆØÅ-æøå"), "AEOA-aeoa");
+        assert_eq!(to_toml_ascii("定理"), "u5b9au7406");
+        assert_eq!(to_toml_ascii("🚀"), "u1f680");
+        // Variation selectors and joiners don't make labels differ
+        assert_eq!(to_toml_ascii("❤️"), to_toml_ascii("❤"));
+        assert_eq!(to_toml_ascii("👩‍🔬"), "u1f469u1f52c");
+        assert_ne!(to_toml_ascii("🚀"), to_toml_ascii("🔥"));
+        assert_eq!(part_class("Teil Ü"), "blox-part-teil-u");
+        assert_eq!(transliterate("eq:sum"), "eq:sum");
+    }
+
+    #[test]
+    fn test_number_style() {
+        let format = |style: NumberStyle| [1, 4, 26, 28, 1994].map(|n| style.format(n));
+        assert_eq!(format(NumberStyle::Arabic), ["1", "4", "26", "28", "1994"]);
+        assert_eq!(
+            format(NumberStyle::LowerRoman),
+            ["i", "iv", "xxvi", "xxviii", "mcmxciv"]
+        );
+        assert_eq!(
+            format(NumberStyle::UpperRoman),
+            ["I", "IV", "XXVI", "XXVIII", "MCMXCIV"]
+        );
+        assert_eq!(
+            format(NumberStyle::LowerAlpha),
+            ["a", "d", "z", "ab", "bxr"]
+        );
+        assert_eq!(
+            format(NumberStyle::UpperAlpha),
+            ["A", "D", "Z", "AB", "BXR"]
+        );
+        assert_eq!(NumberStyle::UpperRoman.format(4000), "4000");
+    }
+
+    #[test]
+    fn test_palette() -> Result<()> {
+        let config = Config::from_book_toml(
+            r##"
+[preprocessor.blox]
+palette = "colorblind-safe"
+
+[preprocessor.blox.environments]
+correct = {name = "Correct", color = "#00FF00"}
+wrong = {name = "Wrong", color = "#FF0000", order = -1}
+"##,
+        )?;
+        assert_eq!(*config.color("wrong"), HexColor::from_u24(0x0072B2));
+        assert_eq!(*config.color("correct"), HexColor::from_u24(0xE69F00));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_precedence() -> Result<()> {
+        let mut config: Config = toml::from_str(
+            r#"
+[defaults]
+hide_name = true
+header = { title = false }
+
+[environments]
+alert = {name = "Alert", header = { name = true }, hide_name = true}
+quote = {name = "Quote", hide_header = true}
+note = {name = "Note", header = { number = false }}
+"#,
+        )?;
+
+        // The `header` table wins over the shorthands of the same level
+        assert_eq!(config.header("alert").resolve().name, true);
+        assert_eq!(config.hide_header("quote"), true);
+        // Unset parts fall through to the defaults
+        let note = config.header("note").resolve();
+        assert_eq!(
+            note,
+            HeaderVisibility {
+                show: true,
+                name: false,
+                number: false,
+                title: false
+            }
+        );
+
+        config.parts.insert(
+            "Part".to_string(),
+            PartConfig {
+                hide_name: Some(false),
+                header: HeaderConfig {
+                    title: Some(true),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let scoped = config.scoped("Part").unwrap();
+        assert_eq!(
+            scoped.header("note").resolve(),
+            HeaderVisibility {
+                number: false,
+                ..Default::default()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chapter_prefix() -> Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+[chapter_prefix]
+"interlude.md" = "Int"
+"appendix/a.md" = "A."
+"#,
+        )?;
+
+        assert_eq!(
+            config.chapter_prefix(Path::new("interlude.md")).as_deref(),
+            Some("Int.")
+        );
+        assert_eq!(
+            config.chapter_prefix(Path::new("appendix/a.md")).as_deref(),
+            Some("A.")
+        );
+        assert_eq!(config.chapter_prefix(Path::new("chapter.md")), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overrides() -> Result<()> {
+        let overrides = ConfigOverride::from_env_vars([
+            (
+                "MDBOOK_BLOX__DEFAULTS__NUMBERED".to_string(),
+                "false".to_string(),
+            ),
+            (
+                "MDBOOK_BLOX__ENVIRONMENTS__ALERT__NAME".to_string(),
+                "Warning".to_string(),
+            ),
+            ("MDBOOK_BOOK__TITLE".to_string(), "Title".to_string()),
+        ]);
+        assert_eq!(overrides.len(), 2);
+
+        let mut overrides = overrides;
+        overrides.push("defaults.color = #112233".parse()?);
+        overrides.push("pretty_urls=true".parse()?);
+        assert!("pretty_urls".parse::<ConfigOverride>().is_err());
+
+        let config = Config::from_book_toml_with(
+            r##"
+[preprocessor.blox.environments]
+alert = {name = "Alert", color = "#00FF00"}
+exercise = {name = "Exercise"}
+"##,
+            &overrides,
+        )?;
+        assert_eq!(config.numbered("exercise"), false);
+        assert_eq!(config.name("alert"), "Warning");
+        assert_eq!(*config.color("alert"), HexColor::from_u24(0x00FF00));
+        assert_eq!(*config.color("exercise"), HexColor::from_u24(0x112233));
+        assert!(config.pretty_urls);
+
+        let invalid: ConfigOverride = "css.file=x".parse()?;
+        assert!(
+            Config::from_book_toml_with("[preprocessor.blox]\ncss = \"a.css\"", &[invalid])
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_css_targets() -> Result<()> {
+        assert_eq!(
+            Config::default().css.targets(),
+            vec![("all", "assets/blox.css")]
+        );
+
+        let config: Config =
+            toml::from_str(r#"css = { screen = "assets/blox.css", print = "assets/print.css" }"#)?;
+        assert_eq!(
+            config.css.targets(),
+            vec![("print", "assets/print.css"), ("screen", "assets/blox.css")]
+        );
+
+        // Paths are kept as they are
+        let config: Config = toml::from_str(r#"css = "theme/blox-dark.css""#)?;
+        assert_eq!(config.css.targets(), vec![("all", "theme/blox-dark.css")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_environment_keys() -> Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+[environments]
+proof = { name = "Proof", order = 2 }
+theorem = { name = "Theorem", order = 1 }
+lemma = { name = "Lemma", order = 1 }
+alert = { name = "Alert" }
+"#,
+        )?;
+        assert_eq!(
+            config.environment_keys(),
+            vec!["alert", "lemma", "theorem", "proof"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_name_case() -> Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+defaults = { name_case = "title" }
+
+[environments]
+note = { name = "NOTE" }
+faq = { name = "faq entry", name_case = "upper" }
+tip = { name = "tip", name_case = "as-is" }
+"#,
+        )?;
+        assert_eq!(config.name("note"), "Note");
+        assert_eq!(config.name("faq"), "FAQ ENTRY");
+        assert_eq!(config.name("tip"), "tip");
+        assert_eq!(NameCase::Lower.apply("Über"), "über");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_id_source() -> Result<()> {
+        let config: Config = toml::from_str(r#"id_source = "content-hash""#)?;
+        assert_eq!(config.id_source, IdSource::ContentHash);
+        assert_eq!(Config::default().id_source, IdSource::Label);
+
+        Ok(())
+    }
 }
@@ -1,10 +1,17 @@
-use crate::config::{CODE_BLOCK_KEYWORD, Config};
+use crate::config::{
+    CODE_BLOCK_KEYWORD, Config, EnvironmentKind, PartConfig, PrintVisibility, directory_class,
+    part_class,
+};
 use anyhow::Result;
+use hex_color::HexColor;
+
+/// Selector of the dark themes of mdBook, set as class of the `html` element
+const DARK_THEMES: &str = ":is(.ayu, .navy, .coal)";
 
 pub struct BloxCss;
 impl BloxCss {
     pub fn block_class() -> String {
-        format!("{CODE_BLOCK_KEYWORD}")
+        CODE_BLOCK_KEYWORD.to_string()
     }
     pub fn header_class() -> String {
         format!("{CODE_BLOCK_KEYWORD}-header")
@@ -12,15 +19,130 @@ impl BloxCss {
     pub fn content_class() -> String {
         format!("{CODE_BLOCK_KEYWORD}-content")
     }
+    pub fn header_right_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-header-right")
+    }
+    pub fn number_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-number")
+    }
     pub fn footer_class() -> String {
         format!("{CODE_BLOCK_KEYWORD}-footer")
     }
+    pub fn error_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-error")
+    }
+    pub fn unbound_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-unbound")
+    }
+    pub fn figure_image_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-figure-image")
+    }
+    pub fn table_body_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-table-body")
+    }
+    pub fn math_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-math")
+    }
+    pub fn math_number_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-math-number")
+    }
+    pub fn nav_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-nav")
+    }
+    pub fn hint_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-hint")
+    }
+    pub fn reveal_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-reveal")
+    }
+    pub fn blank_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-blank")
+    }
+    pub fn pane_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-pane")
+    }
+    pub fn set_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-set")
+    }
+    pub fn set_header_class() -> String {
+        format!("{CODE_BLOCK_KEYWORD}-set-header")
+    }
 
+    /// Rules for all media
     pub fn base_css() -> String {
-        // let block_class = BloxCss::block_class();
-        // let header_class = BloxCss::header_class();
-        // let content_class = BloxCss::content_class();
-        // let footer_class = BloxCss::footer_class();
+        let mut css = Self::rules(true);
+        css.push_str(&format!(
+            r####"@media print {{
+  .{block_class} {{
+    box-shadow: none;
+  }}
+}}
+"####,
+            block_class = BloxCss::block_class(),
+        ));
+        css
+    }
+
+    /// Minimal structural rules, inlined to avoid unstyled blox before the stylesheet loads
+    pub fn critical_css() -> String {
+        format!(
+            "<style>.{block_class}{{display:flow-root;margin-block:1em;border-inline-start:0.4em solid;}}.{block_class}>div{{padding-inline:1em;}}.{block_class}>.{header_class}{{font-weight:bold;}}</style>\n\n",
+            block_class = BloxCss::block_class(),
+            header_class = BloxCss::header_class(),
+        )
+    }
+
+    /// Rules for the print media, without decoration
+    pub fn print_css() -> String {
+        Self::rules(false)
+    }
+
+    /// Hover and focus rules of blox that can be interacted with, collapsible or with links
+    pub fn interactive_css() -> String {
+        format!(
+            r####".{block_class}:is(details, :has(a[href], details)):is(:hover, :focus-within) {{
+  box-shadow: 0 0.3rem 1.2rem rgba(0, 0, 0, 0.12);
+}}
+.{block_class}:is(details, :has(a[href], details)):is(:hover, :focus-within) > .{header_class} {{
+  background-image: linear-gradient(rgba(0, 0, 0, 0.06), rgba(0, 0, 0, 0.06));
+}}
+"####,
+            block_class = BloxCss::block_class(),
+            header_class = BloxCss::header_class(),
+        )
+    }
+
+    /// Movement of the rules of [`Self::interactive_css`]
+    fn interactive_motion_css() -> String {
+        format!(
+            r####".{block_class}:is(details, :has(a[href], details)) {{
+  transition: box-shadow 0.15s, transform 0.15s;
+}}
+.{block_class}:is(details, :has(a[href], details)):is(:hover, :focus-within) {{
+  transform: translateY(-1px);
+}}
+"####,
+            block_class = BloxCss::block_class(),
+        )
+    }
+
+    /// Wraps rules with animations or transitions, which must only apply for readers who don't
+    /// prefer reduced motion. Nothing is generated with `reduced_motion`.
+    pub fn motion_css(config: &Config, rules: &str) -> String {
+        match config.reduced_motion {
+            true => String::new(),
+            false => format!("@media (prefers-reduced-motion: no-preference) {{\n{rules}}}\n"),
+        }
+    }
+
+    fn rules(shadow: bool) -> String {
+        let (shadow, nested_shadow) = match shadow {
+            true => (
+                "  box-shadow: 0 0.2rem 1rem rgba(0, 0, 0, 0.05);\n",
+                "  box-shadow: none;\n",
+            ),
+            false => ("", ""),
+        };
 
         format!(
             r####"
@@ -28,8 +150,7 @@ impl BloxCss {
   display: flow-root;
   margin-block: 1em;
   margin-inline: 0em;
-  box-shadow: 0 0.2rem 1rem rgba(0, 0, 0, 0.05);
-  border-inline-start-width: 0.4em;
+{shadow}  border-inline-start-width: 0.4em;
   border-inline-start-style: solid;
   break-inside: avoid;
 }}
@@ -37,9 +158,20 @@ impl BloxCss {
   padding-inline: 1em;
 }}
 .{block_class} > .{header_class} {{
-  display: flow-root;
+  display: flex;
+  flex-wrap: wrap;
+  justify-content: space-between;
+  align-items: baseline;
+  column-gap: 1em;
   font-weight: bold;
 }}
+.{block_class} > .{header_class} .{header_right_class} {{
+  font-weight: normal;
+}}
+.{block_class} > .{header_class} .{number_class} {{
+  color: inherit;
+  text-decoration: none;
+}}
 .{block_class} > .{content_class} {{
   margin-block: 1em;
 }}
@@ -48,48 +180,528 @@ impl BloxCss {
   font-style: italic;
   text-align: right;
 }}
+.{block_class} > :is(.{hint_class}, .{reveal_class}) {{
+  margin-block: 1em;
+}}
+.{block_class} > :is(.{hint_class}, .{reveal_class}) > summary {{
+  cursor: pointer;
+  font-style: italic;
+}}
 .{block_class} > .{header_class} > p, .{block_class} > .{footer_class} > p {{
   margin-block: 0.6em;
 }}
-@media print {{
-  .{block_class} {{
-    box-shadow: none;
-  }}
+.{block_class} > .{header_class} > :is(h2, h3, h4, h5, h6) {{
+  margin-block: 0.6em;
+  font-size: inherit;
+}}
+.{block_class} > .{nav_class} {{
+  display: flex;
+  justify-content: space-between;
+  padding-block-end: 0.5em;
+  font-size: 0.9em;
+}}
+.{block_class} > .{nav_class} > .{nav_class}-next {{
+  margin-inline-start: auto;
+}}
+details.{block_class} > summary.{header_class} {{
+  padding-inline: 1em;
+  cursor: pointer;
+  list-style: none;
+}}
+details.{block_class} > summary.{header_class}::before {{
+  content: "▸";
+}}
+details.{block_class}[open] > summary.{header_class}::before {{
+  content: "▾";
+}}
+details.{block_class} > summary.{header_class} > :is(p, h2, h3, h4, h5, h6) {{
+  margin-inline-end: auto;
+}}
+.{block_class} > figcaption {{
+  padding-inline: 1em;
+}}
+.{block_class} > .{figure_image_class} {{
+  text-align: center;
+}}
+.{block_class} > .{figure_image_class} img {{
+  max-width: 100%;
+}}
+.{block_class} > .{table_body_class} {{
+  overflow-x: auto;
+}}
+.{block_class}:has(> .{math_class}) {{
+  display: flex;
+  align-items: center;
+  border-inline-start: none;
+}}
+.{block_class} > .{math_class} {{
+  flex: 1;
+  overflow-x: auto;
+}}
+.{block_class} > .{math_number_class} {{
+  padding-inline: 1em;
+}}
+.{block_class} .{block_class} {{
+  margin-block: 0.6em;
+{nested_shadow}}}
+.{block_class} .{block_class} > div {{
+  padding-inline: 0.6em;
+}}
+.{block_class}[data-depth]:not([data-depth="1"]) {{
+  border-inline-start-width: 0.2em;
+  font-size: 0.95em;
+}}
+.{block_class}[lang] {{
+  hyphens: auto;
+}}
+.{block_class}.{error_class} {{
+  border: 0.15em solid #D32F2F;
+  border-inline-start-width: 0.4em;
+}}
+.{block_class}.{error_class} > .{header_class} {{
+  color: #D32F2F;
+}}
+.{unbound_class} {{
+  color: #D32F2F;
+  font-weight: bold;
+}}
+.{block_class} .{pane_class} {{
+  margin-block: 1em;
+}}
+.{block_class} .{pane_class} > :is(summary, .{pane_class}-title) {{
+  font-style: italic;
+}}
+.{block_class} details.{pane_class} > summary {{
+  cursor: pointer;
+}}
+.{block_class} .{blank_class} {{
+  display: inline-block;
+  min-width: 4em;
+  border-block-end: 1px solid;
+}}
+.{block_class} .{blank_class}:empty {{
+  min-width: 6em;
+}}
+.{set_class} {{
+  margin-block: 1em;
+}}
+.{set_class} > .{set_header_class} {{
+  font-size: 1.1em;
+  font-weight: bold;
 }}
 "####,
             block_class = BloxCss::block_class(),
             header_class = BloxCss::header_class(),
+            header_right_class = BloxCss::header_right_class(),
+            number_class = BloxCss::number_class(),
             content_class = BloxCss::content_class(),
             footer_class = BloxCss::footer_class(),
+            hint_class = BloxCss::hint_class(),
+            reveal_class = BloxCss::reveal_class(),
+            error_class = BloxCss::error_class(),
+            unbound_class = BloxCss::unbound_class(),
+            nav_class = BloxCss::nav_class(),
+            figure_image_class = BloxCss::figure_image_class(),
+            table_body_class = BloxCss::table_body_class(),
+            math_class = BloxCss::math_class(),
+            math_number_class = BloxCss::math_number_class(),
+            blank_class = BloxCss::blank_class(),
+            pane_class = BloxCss::pane_class(),
+            set_class = BloxCss::set_class(),
+            set_header_class = BloxCss::set_header_class(),
         )
     }
 }
 
+/// Cascade layer of the rules with `css_layer`
+pub const CSS_LAYER: &str = CODE_BLOCK_KEYWORD;
+/// Condition met by all browsers with cascade layers, which came before `:has()`
+const LAYER_SUPPORT: &str = "selector(:has(a))";
+
+/// Rules in the cascade layer, after an unlayered copy for browsers without cascade layers,
+/// which ignore layered rules. Browsers with cascade layers but without `:has()` use the copy
+/// too, so theme rules only override it by specificity there.
+fn layered_css(css: &str) -> String {
+    format!("@supports not {LAYER_SUPPORT} {{{css}}}\n@layer {CSS_LAYER} {{{css}}}\n")
+}
+
 pub fn css_from_config(config: &Config) -> Result<String> {
-    let mut css: String = BloxCss::base_css();
+    css_for_media(config, "all")
+}
+
+/// CSS for a media type, wrapped in a media query unless it is `all`.
+///
+/// The `print` stylesheet is kept light, without decoration.
+pub fn css_for_media(config: &Config, media: &str) -> Result<String> {
+    let mut css: String = match media {
+        "print" => BloxCss::print_css(),
+        "all" => BloxCss::base_css(),
+        _ => BloxCss::rules(true),
+    };
+    if config.interactive_css && media != "print" {
+        css.push_str(&BloxCss::interactive_css());
+        css.push_str(&BloxCss::motion_css(
+            config,
+            &BloxCss::interactive_motion_css(),
+        ));
+    }
+
+    // Inlined in the chapters instead
+    if !config.chapter_css {
+        for env in config.environment_keys() {
+            css.push_str(css_from_environment(config, env)?.as_str());
+        }
+    }
+
+    let mut print = String::new();
+    for env in config.environment_keys() {
+        print.push_str(print_css_from_environment(config, env)?.as_str());
+    }
+    match media {
+        "print" => css.push_str(&print),
+        "all" if !print.is_empty() => css.push_str(&format!("@media print {{{print}}}\n")),
+        _ => {}
+    }
+
+    // Parts and then directories come last to override environment colors
+    for (part, part_config) in config.parts.iter() {
+        css.push_str(css_from_scope(&part_class(part), part_config).as_str());
+    }
+    for (dir, dir_config) in config.directories.iter() {
+        css.push_str(css_from_scope(&directory_class(dir), dir_config).as_str());
+    }
 
-    for env in config.environments.keys() {
+    if config.css_layer {
+        css = layered_css(&css);
+    }
+    match media {
+        "all" => Ok(css),
+        _ => Ok(format!("@media {media} {{{css}}}\n")),
+    }
+}
+
+/// Rules of the environments used in a chapter, inlined at its end with `chapter_css`
+pub fn chapter_css<'e>(config: &Config, envs: impl IntoIterator<Item = &'e str>) -> Result<String> {
+    let mut css = String::new();
+    for env in envs {
         css.push_str(css_from_environment(config, env)?.as_str());
     }
+    if css.is_empty() {
+        return Ok(css);
+    }
+    if config.css_layer {
+        css = layered_css(&css);
+    }
+    Ok(format!("\n\n<style>{css}</style>\n"))
+}
 
-    Ok(css)
+/// A color as hex, with alpha only if it is not opaque
+fn css_color(color: &HexColor) -> String {
+    match color.a {
+        u8::MAX => color.display_rgb().to_string(),
+        _ => color.display_rgba().to_string(),
+    }
 }
 
 fn css_from_environment(config: &Config, env: &str) -> Result<String> {
     let block_class = BloxCss::block_class();
     let header_class = BloxCss::header_class();
     let group_str = config.group_str(env)?;
-    let color = config.color(env).display_rgb();
-    let tr_color = config.color(env).with_a(26).display_rgba();
+    let color = css_color(config.color(env));
+    let alpha = config.header_alpha(env);
+    let tr_color = config.color(env).with_a(alpha).display_rgba();
+    // Equations are not framed
+    let mut border = String::new();
+    if config.kind(env) != EnvironmentKind::Equation {
+        border.push_str(&format!(
+            "  border-inline-start-width: {};\n  border-inline-start-style: {};\n",
+            config.border_width(env),
+            config.border_style(env)
+        ));
+        if let Some(radius) = config.radius(env) {
+            border.push_str(&format!(
+                "  border-radius: {radius};\n  overflow: hidden;\n"
+            ));
+        }
+    }
 
-    Ok(format!(
+    let mut css = format!(
         r####"
 .{block_class}.{group_str} {{
   border-color: {color};
-}}
+{border}}}
 .{block_class}.{group_str} > .{header_class} {{
   background-color: {tr_color};
 }}
 "####
-    ))
+    );
+    if let Some(dark) = config.color_dark(env) {
+        let tr_dark = dark.with_a(alpha).display_rgba();
+        let dark = css_color(dark);
+        css.push_str(&format!(
+            r####"{DARK_THEMES} .{block_class}.{group_str} {{
+  border-color: {dark};
+}}
+{DARK_THEMES} .{block_class}.{group_str} > .{header_class} {{
+  background-color: {tr_dark};
+}}
+"####
+        ));
+    }
+
+    Ok(css)
+}
+
+fn print_css_from_environment(config: &Config, env: &str) -> Result<String> {
+    let block_class = BloxCss::block_class();
+    let header_class = BloxCss::header_class();
+    let group_str = config.group_str(env)?;
+
+    Ok(match config.print(env) {
+        PrintVisibility::Show => String::new(),
+        PrintVisibility::Hide => format!(
+            r####"
+.{block_class}.{group_str} {{
+  display: none;
+}}
+"####
+        ),
+        PrintVisibility::OutlineOnly => format!(
+            r####"
+.{block_class}.{group_str} > :not(.{header_class}) {{
+  display: none;
+}}
+"####
+        ),
+    })
+}
+
+fn css_from_scope(scope_class: &str, scope_config: &PartConfig) -> String {
+    let Some(color) = scope_config.color.as_ref() else {
+        return String::new();
+    };
+
+    let block_class = BloxCss::block_class();
+    let header_class = BloxCss::header_class();
+    let tr_color = color.with_a(26).display_rgba();
+    let color = css_color(color);
+
+    format!(
+        r####"
+.{block_class}.{scope_class} {{
+  border-color: {color};
+}}
+.{block_class}.{scope_class} > .{header_class} {{
+  background-color: {tr_color};
+}}
+"####
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::test::default_test_config;
+
+    #[test]
+    fn test_css_for_media() -> Result<()> {
+        let config = default_test_config();
+
+        let all = css_from_config(&config)?;
+        assert!(all.contains("box-shadow: 0 0.2rem"));
+        assert!(all.contains("@media print"));
+
+        let print = css_for_media(&config, "print")?;
+        assert!(print.starts_with("@media print {\n.blox {"));
+        assert!(!print.contains("box-shadow"));
+        assert!(print.contains(".blox.blox-alert {\n  border-color: #00FF00;"));
+
+        let screen = css_for_media(&config, "screen")?;
+        assert!(screen.starts_with("@media screen {"));
+        assert!(screen.contains("box-shadow: 0 0.2rem"));
+        assert!(!screen.contains("@media print"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_color_dark() -> Result<()> {
+        let config = Config::from_book_toml(
+            r##"
+[preprocessor.blox.defaults]
+color_dark = "#333333"
+
+[preprocessor.blox.environments]
+alert = {name = "Alert", color = "#FF0000", color_dark = "#880000"}
+note = {name = "Note"}
+"##,
+        )?;
+
+        let css = css_from_config(&config)?;
+        assert!(
+            css.contains(":is(.ayu, .navy, .coal) .blox.blox-alert {\n  border-color: #880000;")
+        );
+        assert!(css.contains(
+            ":is(.ayu, .navy, .coal) .blox.blox-alert > .blox-header {\n  background-color: #8800001A;"
+        ));
+        assert!(
+            css.contains(":is(.ayu, .navy, .coal) .blox.blox-note {\n  border-color: #333333;")
+        );
+        assert!(!css_from_config(&default_test_config())?.contains(".ayu"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interactive_css() -> Result<()> {
+        let mut config = default_test_config();
+        assert!(!css_from_config(&config)?.contains(":hover"));
+
+        config.interactive_css = true;
+        assert!(
+            css_from_config(&config)?.contains(
+                ".blox:is(details, :has(a[href], details)):is(:hover, :focus-within) {\n"
+            )
+        );
+        assert!(!css_for_media(&config, "print")?.contains(":hover"));
+
+        // Movement only for readers who don't prefer reduced motion
+        let css = css_from_config(&config)?;
+        let (still, motion) = css
+            .split_once("@media (prefers-reduced-motion: no-preference) {\n")
+            .unwrap();
+        assert!(!still.contains("transition") && !still.contains("transform"));
+        assert!(motion.contains("  transition: box-shadow 0.15s, transform 0.15s;"));
+
+        config.reduced_motion = true;
+        let css = css_from_config(&config)?;
+        assert!(css.contains(":hover") && !css.contains("transition"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_css_layer() -> Result<()> {
+        let mut config = default_test_config();
+        assert!(!css_from_config(&config)?.contains("@layer"));
+
+        config.css_layer = true;
+        let css = css_from_config(&config)?;
+        let (fallback, layered) = css.split_once("@layer blox {").unwrap();
+        assert!(fallback.starts_with("@supports not selector(:has(a)) {\n.blox {"));
+        assert!(layered.starts_with("\n.blox {") && layered.ends_with("}\n}\n"));
+        // The same rules
+        assert_eq!(
+            fallback,
+            format!("@supports not selector(:has(a)) {{{layered}")
+        );
+        assert!(
+            css_for_media(&config, "print")?
+                .starts_with("@media print {@supports not selector(:has(a)) {\n.blox {")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chapter_css() -> Result<()> {
+        let mut config = default_test_config();
+        config.chapter_css = true;
+        let css = css_from_config(&config)?;
+        assert!(!css.contains(".blox.blox-alert"));
+        assert!(css.contains("@media print"));
+
+        assert_eq!(chapter_css(&config, [])?, "");
+        let inline = chapter_css(&config, ["alert"])?;
+        assert!(inline.starts_with("\n\n<style>\n.blox.blox-alert {\n  border-color: #00FF00;"));
+        assert!(!inline.contains("blox-quote") && inline.ends_with("}\n</style>\n"));
+
+        config.css_layer = true;
+        let inline = chapter_css(&config, ["alert"])?;
+        assert!(
+            inline.starts_with("\n\n<style>@supports not selector(:has(a)) {\n.blox.blox-alert")
+        );
+        assert!(inline.contains("}\n@layer blox {\n.blox.blox-alert"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_borders() -> Result<()> {
+        let config = Config::from_book_toml(
+            r##"
+[preprocessor.blox.defaults]
+radius = "0.3em"
+
+[preprocessor.blox.environments]
+alert = {name = "Alert", color = "#FF0000", border_width = "2px", border_style = "dashed", header_opacity = 50}
+note = {name = "Note", color = "#FF0000"}
+equation = {name = "Equation", kind = "equation"}
+"##,
+        )?;
+
+        let css = css_from_config(&config)?;
+        assert!(css.contains(
+            ".blox.blox-alert {\n  border-color: #FF0000;\n  border-inline-start-width: 2px;\n  border-inline-start-style: dashed;\n  border-radius: 0.3em;\n"
+        ));
+        assert!(css.contains(".blox.blox-alert > .blox-header {\n  background-color: #FF000080;"));
+        assert!(css.contains(
+            ".blox.blox-note {\n  border-color: #FF0000;\n  border-inline-start-width: 0.4em;\n  border-inline-start-style: solid;\n  border-radius: 0.3em;\n"
+        ));
+        assert!(css.contains(".blox.blox-note > .blox-header {\n  background-color: #FF00001A;"));
+        assert!(css.contains(".blox.blox-equation {\n  border-color: #CE0037;\n}"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_color_forms() -> Result<()> {
+        let config = Config::from_book_toml(
+            r##"
+[preprocessor.blox.environments]
+alert = {name = "Alert", color = "hsl(210, 50%, 40%)", color_dark = "steelblue"}
+note = {name = "Note", color = "#33669980"}
+"##,
+        )?;
+
+        let css = css_from_config(&config)?;
+        assert!(css.contains(".blox.blox-alert {\n  border-color: #336699;"));
+        assert!(css.contains(".blox.blox-alert > .blox-header {\n  background-color: #3366991A;"));
+        assert!(
+            css.contains(":is(.ayu, .navy, .coal) .blox.blox-alert {\n  border-color: #4682B4;")
+        );
+        assert!(css.contains(".blox.blox-note {\n  border-color: #33669980;"));
+        assert!(
+            Config::from_book_toml("[preprocessor.blox.defaults]\ncolor = \"nocolor\"").is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_visibility() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+solution = { name = "Solution", print = "hide" }
+exercise = { name = "Exercise", print = "outline-only" }
+"#,
+        )?;
+
+        let hide = ".blox.blox-solution {\n  display: none;\n}";
+        let outline = ".blox.blox-exercise > :not(.blox-header) {\n  display: none;\n}";
+
+        let all = css_from_config(&config)?;
+        let print_block = &all[all.rfind("@media print {").unwrap()..];
+        assert!(print_block.contains(hide));
+        assert!(print_block.contains(outline));
+
+        let print = css_for_media(&config, "print")?;
+        assert!(print.contains(hide) && print.contains(outline));
+
+        let screen = css_for_media(&config, "screen")?;
+        assert!(!screen.contains("display: none"));
+
+        Ok(())
+    }
 }
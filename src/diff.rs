@@ -0,0 +1,285 @@
+use crate::config::{Config, ConfigOverride};
+use crate::mdbook_api;
+use crate::parse::Blox;
+use crate::process::{BloxProcessor, SourceChapter};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A blox-level change between two revisions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloxChange {
+    pub kind: ChangeKind,
+    /// Label, or `env:hash` for anonymous blox
+    pub key: String,
+    pub environment: String,
+    pub path: Option<PathBuf>,
+    pub title: String,
+}
+
+impl fmt::Display for BloxChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = match self.kind {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Modified => '~',
+        };
+        let path = self
+            .path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+
+        write!(
+            f,
+            "{sign} {} [{}] {path}: {}",
+            self.key, self.environment, self.title
+        )
+    }
+}
+
+/// Comparable state of a blox
+struct BloxState {
+    environment: String,
+    path: Option<PathBuf>,
+    title: String,
+    fingerprint: (u64, Option<String>, Option<String>),
+}
+
+/// Compares the blox of the book in `dir` between two git revisions.
+///
+/// Labelled blox are matched by label, anonymous blox by environment and content. The
+/// configuration of each revision is read with overrides from environment variables, then
+/// `overrides`.
+pub fn diff_revisions(
+    dir: &Path,
+    from: &str,
+    to: &str,
+    overrides: &[ConfigOverride],
+) -> Result<Vec<BloxChange>> {
+    let mut all_overrides = ConfigOverride::from_env_vars(std::env::vars());
+    all_overrides.extend_from_slice(overrides);
+
+    let old = blox_at_revision(dir, from, &all_overrides)?;
+    let new = blox_at_revision(dir, to, &all_overrides)?;
+
+    let mut changes: Vec<BloxChange> = Vec::new();
+    let change = |kind, key: &str, state: &BloxState| BloxChange {
+        kind,
+        key: key.to_string(),
+        environment: state.environment.clone(),
+        path: state.path.clone(),
+        title: state.title.clone(),
+    };
+
+    for (key, state) in old.iter() {
+        match new.get(key) {
+            None => changes.push(change(ChangeKind::Removed, key, state)),
+            Some(new_state) if new_state.fingerprint != state.fingerprint => {
+                changes.push(change(ChangeKind::Modified, key, new_state))
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, state) in new.iter() {
+        if !old.contains_key(key) {
+            changes.push(change(ChangeKind::Added, key, state));
+        }
+    }
+
+    changes.sort_by(|a, b| (&a.path, &a.key).cmp(&(&b.path, &b.key)));
+    Ok(changes)
+}
+
+fn blox_at_revision(
+    dir: &Path,
+    rev: &str,
+    overrides: &[ConfigOverride],
+) -> Result<HashMap<String, BloxState>> {
+    let book_toml = git(dir, &["show", &format!("{rev}:./book.toml")])?;
+    let config = Config::from_book_toml_with(&book_toml, overrides)?;
+    let src = mdbook_api::book_src(&book_toml)?;
+
+    let files = git(
+        dir,
+        &[
+            "ls-tree",
+            "-r",
+            "--name-only",
+            rev,
+            "--",
+            &src.to_string_lossy(),
+        ],
+    )?;
+
+    let mut contents = Vec::new();
+    for file in files.lines().filter(|f| f.ends_with(".md")) {
+        let content = git(dir, &["show", &format!("{rev}:./{file}")])?;
+        let path = Path::new(file)
+            .strip_prefix(&src)
+            .unwrap_or(Path::new(file));
+        contents.push((path.to_path_buf(), content));
+    }
+
+    let chapters: Vec<SourceChapter> = contents
+        .iter()
+        .map(|(path, content)| SourceChapter {
+            content,
+            path: Some(path.clone()),
+            ..Default::default()
+        })
+        .collect();
+    let blox_list = BloxProcessor::collect(&chapters, &config)
+        .with_context(|| format!("Can't parse blox at revision {rev}"))?;
+
+    let mut states = HashMap::new();
+    let mut anonymous_count: HashMap<String, usize> = HashMap::new();
+    for blox in blox_list.iter() {
+        let key = match blox.label() {
+            Some(label) => label.to_string(),
+            None => {
+                let key = format!("{}:{:016x}", blox.env(), blox.content_hash());
+                let n = anonymous_count.entry(key.clone()).or_default();
+                *n += 1;
+                match n {
+                    1 => key,
+                    _ => format!("{key}#{n}"),
+                }
+            }
+        };
+
+        states.insert(key, blox_state(&config, blox));
+    }
+
+    Ok(states)
+}
+
+fn blox_state(config: &Config, blox: &Blox) -> BloxState {
+    BloxState {
+        environment: blox.env().to_string(),
+        path: blox.path().cloned(),
+        title: blox.title_full(config),
+        fingerprint: (
+            blox.content_hash(),
+            blox.title().map(|s| s.to_string()),
+            blox.footer().map(|s| s.to_string()),
+        ),
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Can't run git")?;
+
+    anyhow::ensure!(
+        output.status.success(),
+        "git {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    fn commit(dir: &Path, chapter: &str) -> Result<()> {
+        fs::write(dir.join("src/chapter.md"), chapter)?;
+        git(dir, &["add", "-A"])?;
+        git(
+            dir,
+            &[
+                "-c",
+                "user.name=blox",
+                "-c",
+                "user.email=blox@example.com",
+                "commit",
+                "-q",
+                "-m",
+                "chapter",
+            ],
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_revisions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path();
+        fs::create_dir(dir.join("src"))?;
+        fs::write(
+            dir.join("book.toml"),
+            "[preprocessor.blox.environments]\nnote = { name = \"Note\" }\n",
+        )?;
+        git(dir, &["init", "-q"])?;
+
+        commit(
+            dir,
+            r#"```blox note label = "kept"
+Kept
+```
+
+```blox note label = "edited", title = "Old"
+Edited
+```
+
+```blox note label = "gone"
+Gone
+```
+"#,
+        )?;
+        commit(
+            dir,
+            r#"```blox note label = "kept"
+Kept
+```
+
+```blox note label = "edited", title = "New"
+Edited
+```
+
+```blox note
+Anonymous
+```
+"#,
+        )?;
+
+        let changes: Vec<String> = diff_revisions(dir, "HEAD~1", "HEAD", &[])?
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "~ edited [note] chapter.md: Note 2: New",
+                "- gone [note] chapter.md: Note 3",
+                "+ note:9270ee0b1fe35b20 [note] chapter.md: Note 3",
+            ]
+        );
+        assert!(diff_revisions(dir, "HEAD", "HEAD", &[])?.is_empty());
+        assert!(diff_revisions(dir, "missing", "HEAD", &[]).is_err());
+
+        let overrides = ["environments.note.name=Remark".parse()?];
+        let changes = diff_revisions(dir, "HEAD~1", "HEAD", &overrides)?;
+        assert_eq!(changes[0].title, "Remark 2: New");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use crate::config::Config;
+use crate::mdbook_api::Book;
+use crate::parse::Blox;
+use crate::preprocessor::source_chapters;
+use crate::process::{BloxProcessor, SourceChapter};
+use anyhow::{Context, Result};
+use std::fmt::Write;
+use std::path::Path;
+
+/// Describes every blox of a chapter: its parsed options, number, id and the HTML replacing it.
+///
+/// `chapter` is relative to the book source directory.
+pub fn explain(book: &Book, config: &Config, chapter: &Path) -> Result<String> {
+    explain_chapter(&source_chapters(book), config, chapter)
+}
+
+fn explain_chapter(chapters: &[SourceChapter], config: &Config, chapter: &Path) -> Result<String> {
+    let section_id = chapters
+        .iter()
+        .position(|c| c.path.as_deref() == Some(chapter))
+        .with_context(|| format!("Chapter not found in book: {}", chapter.display()))?;
+
+    let mut processor = BloxProcessor::new(config)?;
+    processor.parse(chapters)?;
+
+    let mut out = String::new();
+    let blox_list = processor.chapter_blox(section_id);
+    if blox_list.is_empty() {
+        writeln!(out, "No blox in {}", chapter.display())?;
+    }
+
+    for (i, blox) in blox_list.into_iter().enumerate() {
+        let html = processor.blox_html(blox);
+        let html = processor.replace_refs(html, &chapters[section_id])?;
+
+        writeln!(out, "# Blox {} ({})", i + 1, blox.title_full(config))?;
+        explain_options(&mut out, config, blox)?;
+        writeln!(out, "\n{html}\n")?;
+    }
+
+    Ok(out)
+}
+
+fn explain_options(out: &mut String, config: &Config, blox: &Blox) -> Result<()> {
+    fn opt<T: ToString>(value: Option<T>) -> String {
+        value.map(|v| v.to_string()).unwrap_or("-".to_string())
+    }
+
+    writeln!(out, "environment     = {}", blox.env())?;
+    writeln!(out, "label           = {}", opt(blox.label()))?;
+    writeln!(out, "number          = {}", opt(blox.number()))?;
+    writeln!(out, "id              = {}", opt(blox.id_str(config)))?;
+    writeln!(out, "title           = {}", opt(blox.title()))?;
+    writeln!(out, "footer          = {}", opt(blox.footer()))?;
+    writeln!(out, "header          = {:?}", blox.header)?;
+    writeln!(out, "header_right    = {}", opt(blox.header_right()))?;
+    writeln!(out, "hide_footer     = {}", blox.hide_footer())?;
+    writeln!(out, "collapsible     = {}", blox.collapsible())?;
+    writeln!(out, "qed             = {}", blox.qed())?;
+    writeln!(out, "open            = {}", blox.open())?;
+    writeln!(out, "statement_only  = {}", blox.statement_only())?;
+    writeln!(out, "verbatim        = {}", blox.verbatim())?;
+    writeln!(out, "index           = {}", blox.in_index())?;
+    writeln!(out, "defer_rendering = {}", blox.defer_rendering())?;
+    writeln!(out, "points          = {}", opt(blox.points()))?;
+    writeln!(out, "solution_of     = {}", opt(blox.solution_of()))?;
+    writeln!(out, "proof_of        = {}", opt(blox.proof_of()))?;
+    writeln!(out, "see_also        = {:?}", blox.see_also())?;
+    writeln!(out, "updated         = {}", opt(blox.updated()))?;
+    writeln!(out, "tags            = {:?}", blox.tags())?;
+    writeln!(out, "lang            = {}", opt(blox.lang()))?;
+    writeln!(out, "set             = {}", opt(blox.set()))?;
+    writeln!(out, "class           = {}", opt(blox.class()))?;
+    writeln!(out, "attrs           = {:?}", blox.attrs())?;
+    writeln!(out, "hints           = {}", blox.hints().len())?;
+    writeln!(out, "part            = {}", opt(blox.part()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::test::default_test_config;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_explain() -> Result<()> {
+        let config = default_test_config();
+        let chapters = [
+            SourceChapter {
+                content: "```blox exercise\nFirst\n```\n",
+                path: Some(PathBuf::from("first.md")),
+                number: Some("1.".to_string()),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "Text\n\n```blox exercise label = \"ex\", title = \"Sum\"\nSecond\n```\n",
+                path: Some(PathBuf::from("second.md")),
+                number: Some("2.".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let out = explain_chapter(&chapters, &config, Path::new("second.md"))?;
+        assert!(out.starts_with("# Blox 1 (Exercise 2.1: Sum)\n"));
+        assert!(out.contains("number          = 2.1\n"));
+        assert!(out.contains("id              = blox-exercise-ex\n"));
+        assert!(out.contains(r#"<div id="blox-exercise-ex" class="blox blox-exercise">"#));
+
+        assert!(explain_chapter(&chapters, &config, Path::new("third.md")).is_err());
+        assert_eq!(
+            explain_chapter(&chapters[..1], &config, Path::new("first.md"))?
+                .matches("# Blox")
+                .count(),
+            1
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use super::{ExportField, ExportItem};
+
+/// Renders flashcards as Anki's tab separated text import, with HTML fields and the environment
+/// as tag
+pub fn render(items: &[ExportItem], front: ExportField, back: ExportField) -> String {
+    let mut output = String::from("#separator:tab\n#html:true\n#tags column:3\n");
+
+    for item in items {
+        output.push_str(&format!(
+            "{}\t{}\t{}\n",
+            tsv_field(item.field(front)),
+            tsv_field(item.field(back)),
+            tsv_field(&item.environment),
+        ));
+    }
+
+    output
+}
+
+/// Fields can't contain tabs or newlines, which are insignificant in HTML anyway
+fn tsv_field(s: &str) -> String {
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render() {
+        let item = ExportItem {
+            id: "blox-definition-1".to_string(),
+            environment: "definition".to_string(),
+            name: "Definition".to_string(),
+            title: Some("Group".to_string()),
+            full_title: "Definition 1.1: Group".to_string(),
+            content: "<p>A set\\twith</p>\n<p>an operation</p>".to_string(),
+            markdown: "A set\\twith\n\nan operation".to_string(),
+            footer: None,
+            points: None,
+            solution: None,
+            solution_markdown: None,
+        };
+
+        assert_eq!(
+            render(&[item], ExportField::Title, ExportField::Content),
+            "#separator:tab\n#html:true\n#tags column:3\nGroup\t<p>A set\\twith</p> <p>an operation</p>\tdefinition\n"
+        );
+    }
+}
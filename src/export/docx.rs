@@ -0,0 +1,78 @@
+use super::ExportItem;
+
+/// Renders Markdown for pandoc, converted to Word by `pandoc -o book.docx`.
+///
+/// Blox are divs with the environment name as Word style, with parts styled as `Name Title`,
+/// `Name Footer` and `Name Solution`, to be defined in the reference document.
+pub fn render(items: &[ExportItem]) -> String {
+    let mut output = String::new();
+
+    for item in items {
+        let name = item.name.replace('"', "");
+        output.push_str(&format!(
+            "::::: {{#{id} custom-style=\"{name}\"}}\n",
+            id = item.id
+        ));
+        output.push_str(&styled(&format!("{name} Title"), &item.full_title));
+        if !item.markdown.trim().is_empty() {
+            output.push_str(&format!("{}\n\n", item.markdown.trim()));
+        }
+        if let Some(footer) = item.footer.as_deref() {
+            output.push_str(&styled(&format!("{name} Footer"), footer));
+        }
+        if let Some(solution) = item.solution_markdown.as_deref() {
+            output.push_str(&styled(&format!("{name} Solution"), solution));
+        }
+        output.push_str(":::::\n\n");
+    }
+
+    output
+}
+
+fn styled(style: &str, markdown: &str) -> String {
+    format!(
+        "::: {{custom-style=\"{style}\"}}\n{}\n:::\n\n",
+        markdown.trim()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render() {
+        let item = ExportItem {
+            id: "blox-exercise-sum".to_string(),
+            environment: "exercise".to_string(),
+            name: "Exercise".to_string(),
+            title: Some("Sum".to_string()),
+            full_title: "Exercise 1.1: Sum".to_string(),
+            content: "<p>Add <em>both</em>.</p>".to_string(),
+            markdown: "\nAdd *both*.\n".to_string(),
+            footer: None,
+            points: None,
+            solution: Some("<p>3</p>".to_string()),
+            solution_markdown: Some("3".to_string()),
+        };
+
+        assert_eq!(
+            render(&[item]),
+            r#"::::: {#blox-exercise-sum custom-style="Exercise"}
+::: {custom-style="Exercise Title"}
+Exercise 1.1: Sum
+:::
+
+Add *both*.
+
+::: {custom-style="Exercise Solution"}
+3
+:::
+
+:::::
+
+"#
+        );
+    }
+}
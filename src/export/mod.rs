@@ -0,0 +1,255 @@
+mod anki;
+mod docx;
+mod moodle;
+mod qti;
+mod wordlist;
+
+use crate::config::Config;
+use crate::mdbook_api::Book;
+use crate::parse::{Blox, BloxOwned};
+use crate::preprocessor::source_chapters;
+use crate::process::BloxProcessor;
+use anyhow::Result;
+use pulldown_cmark::{Parser, html};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// QTI 1.2 item bank, as imported by most LMSs
+    Qti,
+    /// Moodle XML question bank
+    MoodleXml,
+    /// Tab separated flashcards for Anki
+    AnkiTsv,
+    /// Markdown with Word styles, for pandoc
+    DocxFragments,
+    /// The parsed blox, as serialized by serde
+    Json,
+    /// Words of titles and footers, for spellcheckers
+    Wordlist,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "qti" => Ok(Self::Qti),
+            "moodle-xml" => Ok(Self::MoodleXml),
+            "anki-tsv" => Ok(Self::AnkiTsv),
+            "docx-fragments" => Ok(Self::DocxFragments),
+            "json" => Ok(Self::Json),
+            "wordlist" => Ok(Self::Wordlist),
+            _ => anyhow::bail!("Unknown export format: {s}"),
+        }
+    }
+}
+
+/// A blox field selectable as side of a flashcard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportField {
+    /// The title, falling back on the full title
+    Title,
+    /// Environment name, number and title
+    FullTitle,
+    Content,
+    Footer,
+    Solution,
+}
+
+impl FromStr for ExportField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "title" => Ok(Self::Title),
+            "full-title" => Ok(Self::FullTitle),
+            "content" => Ok(Self::Content),
+            "footer" => Ok(Self::Footer),
+            "solution" => Ok(Self::Solution),
+            _ => anyhow::bail!("Unknown export field: {s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Environments to export, all if empty
+    pub envs: Vec<String>,
+    /// Front of flashcards
+    pub front: ExportField,
+    /// Back of flashcards
+    pub back: ExportField,
+}
+
+impl ExportOptions {
+    pub fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            envs: Vec::new(),
+            front: ExportField::Title,
+            back: ExportField::Content,
+        }
+    }
+}
+
+/// A blox prepared for export, with Markdown rendered to HTML
+#[derive(Debug, Clone, PartialEq)]
+struct ExportItem {
+    id: String,
+    environment: String,
+    /// Name of the environment
+    name: String,
+    title: Option<String>,
+    full_title: String,
+    content: String,
+    /// Content as Markdown
+    markdown: String,
+    footer: Option<String>,
+    points: Option<f64>,
+    solution: Option<String>,
+    /// Solution as Markdown
+    solution_markdown: Option<String>,
+}
+
+impl ExportItem {
+    fn field(&self, field: ExportField) -> &str {
+        match field {
+            ExportField::Title => self.title.as_deref().unwrap_or(&self.full_title),
+            ExportField::FullTitle => &self.full_title,
+            ExportField::Content => &self.content,
+            ExportField::Footer => self.footer.as_deref().unwrap_or_default(),
+            ExportField::Solution => self.solution.as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+/// Exports the blox of the selected environments.
+///
+/// Blox with `solution_of` are not exported themselves, but attached to the blox they solve.
+pub fn export(book: &Book, config: &Config, options: &ExportOptions) -> Result<String> {
+    let blox_list = BloxProcessor::collect(&source_chapters(book), config)?;
+    let items = || export_items(config, &blox_list, &options.envs);
+
+    let output = match options.format {
+        ExportFormat::Qti => qti::render(&items()),
+        ExportFormat::MoodleXml => moodle::render(&items()),
+        ExportFormat::AnkiTsv => anki::render(&items(), options.front, options.back),
+        ExportFormat::DocxFragments => docx::render(&items()),
+        ExportFormat::Json => export_json(&blox_list, &options.envs)?,
+        ExportFormat::Wordlist => wordlist::render(&blox_list, &options.envs),
+    };
+
+    Ok(output)
+}
+
+/// All selected blox in the index, including solutions
+fn export_json(blox_list: &[Blox], envs: &[String]) -> Result<String> {
+    let blox_list: Vec<BloxOwned> = blox_list
+        .iter()
+        .filter(|b| b.in_index())
+        .filter(|b| envs.is_empty() || envs.iter().any(|e| e == b.env()))
+        .cloned()
+        .map(Blox::into_owned)
+        .collect();
+
+    Ok(serde_json::to_string_pretty(&blox_list)?)
+}
+
+fn export_items(config: &Config, blox_list: &[Blox], envs: &[String]) -> Vec<ExportItem> {
+    blox_list
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| b.solution_of().is_none() && b.in_index())
+        .filter(|(_, b)| envs.is_empty() || envs.iter().any(|e| e == b.env()))
+        .map(|(n, blox)| {
+            let solution_markdown = blox.label().and_then(|label| {
+                blox_list
+                    .iter()
+                    .find(|s| s.solution_of() == Some(label))
+                    .map(|s| s.content.to_string())
+            });
+
+            ExportItem {
+                id: blox
+                    .id_str(config)
+                    .or_else(|| blox.group_str(config).map(|g| format!("{g}-{n}")))
+                    .unwrap_or_else(|| n.to_string()),
+                environment: blox.env().to_string(),
+                name: config.name(blox.env()).to_string(),
+                title: blox.title().map(|s| s.to_string()),
+                full_title: blox.title_full(config),
+                content: markdown_to_html(&blox.content),
+                markdown: blox.content.to_string(),
+                footer: blox.footer().map(|s| s.to_string()),
+                points: blox.points(),
+                solution: solution_markdown.as_deref().map(markdown_to_html),
+                solution_markdown,
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn markdown_to_html(markdown: &str) -> String {
+    let mut output = String::new();
+    html::push_html(&mut output, Parser::new(markdown.trim()));
+    output.trim_end().to_string()
+}
+
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::test::default_test_config;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("qti".parse::<ExportFormat>().ok(), Some(ExportFormat::Qti));
+        assert_eq!(
+            "moodle-xml".parse::<ExportFormat>().ok(),
+            Some(ExportFormat::MoodleXml)
+        );
+        assert!("docx".parse::<ExportFormat>().is_err());
+        assert_eq!(
+            "solution".parse::<ExportField>().ok(),
+            Some(ExportField::Solution)
+        );
+    }
+
+    #[test]
+    fn test_index() -> Result<()> {
+        let config = default_test_config();
+        let source = "```\nContent\n```";
+        let blox_list: Vec<Blox> = [
+            "blox exercise label = \"warm-up\", index = false",
+            "blox exercise label = \"proof\"",
+        ]
+        .iter()
+        .map(|header| Blox::parse(&config, source, header).map(Option::unwrap))
+        .collect::<Result<_>>()?;
+
+        let items = export_items(&config, &blox_list, &[]);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].id, "blox-exercise-proof");
+        assert!(!export_json(&blox_list, &[])?.contains("warm-up"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(
+            xml_escape(r#"<a href="x">&</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use super::{ExportItem, xml_escape};
+
+/// Renders essay questions in Moodle XML format
+pub fn render(items: &[ExportItem]) -> String {
+    let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<quiz>\n");
+
+    for item in items {
+        let solution = item.solution.as_deref().unwrap_or_default();
+
+        output.push_str(&format!(
+            r#"  <question type="essay">
+    <name><text>{title}</text></name>
+    <questiontext format="html"><text>{content}</text></questiontext>
+    <generalfeedback format="html"><text>{solution}</text></generalfeedback>
+    <defaultgrade>{points}</defaultgrade>
+    <idnumber>{id}</idnumber>
+    <graderinfo format="html"><text>{solution}</text></graderinfo>
+  </question>
+"#,
+            title = xml_escape(&item.full_title),
+            content = xml_escape(&item.content),
+            solution = xml_escape(solution),
+            points = item.points.unwrap_or(1.0),
+            id = xml_escape(&item.id),
+        ));
+    }
+
+    output.push_str("</quiz>\n");
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render() {
+        let item = ExportItem {
+            id: "blox-exercise-1".to_string(),
+            environment: "exercise".to_string(),
+            name: "Exercise".to_string(),
+            title: None,
+            full_title: "Exercise 1.1".to_string(),
+            content: "<p>Is 1 < 2?</p>".to_string(),
+            markdown: "Is 1 < 2?".to_string(),
+            footer: None,
+            points: None,
+            solution: None,
+            solution_markdown: None,
+        };
+
+        assert_eq!(
+            render(&[item]),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<quiz>
+  <question type="essay">
+    <name><text>Exercise 1.1</text></name>
+    <questiontext format="html"><text>&lt;p&gt;Is 1 &lt; 2?&lt;/p&gt;</text></questiontext>
+    <generalfeedback format="html"><text></text></generalfeedback>
+    <defaultgrade>1</defaultgrade>
+    <idnumber>blox-exercise-1</idnumber>
+    <graderinfo format="html"><text></text></graderinfo>
+  </question>
+</quiz>
+"#
+        );
+    }
+}
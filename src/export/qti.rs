@@ -0,0 +1,105 @@
+use super::{ExportItem, xml_escape};
+
+/// Renders essay items as a QTI 1.2 assessment
+pub fn render(items: &[ExportItem]) -> String {
+    let mut output = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<questestinterop xmlns="http://www.imsglobal.org/xsd/ims_qtiasiv1p2">
+  <assessment ident="blox" title="blox">
+    <section ident="root_section">
+"#,
+    );
+
+    for item in items {
+        let feedback = item
+            .solution
+            .as_deref()
+            .map(|s| {
+                format!(
+                    r#"        <itemfeedback ident="general_fb">
+          <flow_mat><material><mattext texttype="text/html">{}</mattext></material></flow_mat>
+        </itemfeedback>
+"#,
+                    xml_escape(s)
+                )
+            })
+            .unwrap_or_default();
+
+        output.push_str(&format!(
+            r#"      <item ident="{id}" title="{title}">
+        <itemmetadata>
+          <qtimetadata>
+            <qtimetadatafield><fieldlabel>question_type</fieldlabel><fieldentry>essay_question</fieldentry></qtimetadatafield>
+            <qtimetadatafield><fieldlabel>points_possible</fieldlabel><fieldentry>{points}</fieldentry></qtimetadatafield>
+          </qtimetadata>
+        </itemmetadata>
+        <presentation>
+          <material><mattext texttype="text/html">{content}</mattext></material>
+          <response_str ident="response1" rcardinality="Single">
+            <render_fib><response_label ident="answer1" rshuffle="No"/></render_fib>
+          </response_str>
+        </presentation>
+{feedback}      </item>
+"#,
+            id = xml_escape(&item.id),
+            title = xml_escape(&item.full_title),
+            points = item.points.unwrap_or(1.0),
+            content = xml_escape(&item.content),
+        ));
+    }
+
+    output.push_str("    </section>\n  </assessment>\n</questestinterop>\n");
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render() {
+        let item = ExportItem {
+            id: "blox-exercise-sum".to_string(),
+            environment: "exercise".to_string(),
+            name: "Exercise".to_string(),
+            title: Some("Sum".to_string()),
+            full_title: "Exercise 1.1: Sum & Product".to_string(),
+            content: "<p>Add <em>both</em>.</p>".to_string(),
+            markdown: "Add *both*.".to_string(),
+            footer: None,
+            points: Some(2.5),
+            solution: Some("<p>3</p>".to_string()),
+            solution_markdown: Some("3".to_string()),
+        };
+
+        assert_eq!(
+            render(&[item]),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<questestinterop xmlns="http://www.imsglobal.org/xsd/ims_qtiasiv1p2">
+  <assessment ident="blox" title="blox">
+    <section ident="root_section">
+      <item ident="blox-exercise-sum" title="Exercise 1.1: Sum &amp; Product">
+        <itemmetadata>
+          <qtimetadata>
+            <qtimetadatafield><fieldlabel>question_type</fieldlabel><fieldentry>essay_question</fieldentry></qtimetadatafield>
+            <qtimetadatafield><fieldlabel>points_possible</fieldlabel><fieldentry>2.5</fieldentry></qtimetadatafield>
+          </qtimetadata>
+        </itemmetadata>
+        <presentation>
+          <material><mattext texttype="text/html">&lt;p&gt;Add &lt;em&gt;both&lt;/em&gt;.&lt;/p&gt;</mattext></material>
+          <response_str ident="response1" rcardinality="Single">
+            <render_fib><response_label ident="answer1" rshuffle="No"/></render_fib>
+          </response_str>
+        </presentation>
+        <itemfeedback ident="general_fb">
+          <flow_mat><material><mattext texttype="text/html">&lt;p&gt;3&lt;/p&gt;</mattext></material></flow_mat>
+        </itemfeedback>
+      </item>
+    </section>
+  </assessment>
+</questestinterop>
+"#
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::parse::Blox;
+use pulldown_cmark::{Event, Parser};
+use std::collections::BTreeSet;
+
+/// Lists the words of titles, header texts, footers and hint titles of the selected blox, one
+/// per line, for spellcheckers which skip fence headers
+pub fn render(blox_list: &[Blox], envs: &[String]) -> String {
+    let mut words = BTreeSet::new();
+
+    for blox in blox_list
+        .iter()
+        .filter(|b| envs.is_empty() || envs.iter().any(|e| e == b.env()))
+    {
+        let hint_titles = blox.hints().iter().filter_map(|h| h.title.as_deref());
+        let texts = [blox.title(), blox.header_right(), blox.footer()]
+            .into_iter()
+            .flatten()
+            .chain(hint_titles);
+
+        for text in texts {
+            words.extend(text_words(text));
+        }
+    }
+
+    words.into_iter().map(|w| format!("{w}\n")).collect()
+}
+
+/// Words of the text of Markdown, without code, link targets, HTML and blox refs
+fn text_words(markdown: &str) -> Vec<String> {
+    let markdown = strip_refs(markdown);
+    let mut words = Vec::new();
+
+    for event in Parser::new(&markdown) {
+        let Event::Text(text) = event else {
+            continue;
+        };
+        words.extend(
+            text.split(|c: char| !(c.is_alphanumeric() || "'’-".contains(c)))
+                .map(|w| w.trim_matches(|c| "'’-".contains(c)))
+                .filter(|w| w.chars().any(char::is_alphabetic))
+                .map(|w| w.to_string()),
+        );
+    }
+
+    words
+}
+
+/// Removes `{{ blox-ref: label }}` and the like
+fn strip_refs(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        match rest[start..].find("}}") {
+            Some(end) => rest = &rest[start + end + 2..],
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::test::default_test_config;
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let config = default_test_config();
+        let blox = Blox::parse(
+            &config,
+            "```\nContent\n```",
+            r#"blox exercise title = "Cauchy's `lim` theorem", footer = "After [Rudin](https://x.org), see {{ blox-ref: other }}", header_right = "MA101""#,
+        )?
+        .unwrap();
+
+        assert_eq!(
+            render(&[blox], &[]),
+            "After\nCauchy's\nMA101\nRudin\nsee\ntheorem\n"
+        );
+
+        Ok(())
+    }
+}
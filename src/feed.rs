@@ -0,0 +1,340 @@
+use crate::config::Config;
+use crate::export::{markdown_to_html, xml_escape};
+use crate::link::html_target;
+use crate::mdbook_api::Book;
+#[cfg(test)]
+use crate::mdbook_api::{self, Chapter};
+use crate::parse::Blox;
+use crate::preprocessor::source_chapters;
+use crate::process::BloxProcessor;
+use anyhow::Result;
+use serde_json::json;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Atom,
+    /// JSON Feed 1.1
+    Json,
+}
+
+impl FromStr for FeedFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "atom" => Ok(Self::Atom),
+            "json" => Ok(Self::Json),
+            _ => anyhow::bail!("Unknown feed format: {s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FeedOptions {
+    pub format: FeedFormat,
+    pub title: String,
+    /// URL of the published book, which links are made relative to
+    pub base_url: String,
+    /// Maximum number of entries
+    pub limit: usize,
+}
+
+struct FeedEntry {
+    id: String,
+    url: String,
+    title: String,
+    content: String,
+    updated: String,
+}
+
+/// A point in time of an `updated` option
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Timestamp {
+    /// Seconds and nanoseconds since the Unix epoch, in UTC
+    utc: (i64, u32),
+    /// The timestamp in RFC 3339 format, with its original offset
+    rfc3339: String,
+}
+
+/// Generates a feed of the most recently `updated` blox.
+pub fn feed(book: &Book, config: &Config, options: &FeedOptions) -> Result<String> {
+    let mut blox_list: Vec<(Timestamp, Blox)> =
+        BloxProcessor::collect(&source_chapters(book), config)?
+            .into_iter()
+            .filter(|b| b.in_index())
+            .filter_map(|b| {
+                let updated = b.updated()?.to_string();
+                match timestamp(&updated) {
+                    Ok(timestamp) => Some((timestamp, b)),
+                    Err(err) => {
+                        log::warn!("Leaving blox out of the feed: {err}");
+                        None
+                    }
+                }
+            })
+            .collect();
+    blox_list.sort_by_key(|(updated, _)| std::cmp::Reverse(updated.utc));
+
+    let base_url = match options.base_url.ends_with('/') {
+        true => options.base_url.clone(),
+        false => format!("{}/", options.base_url),
+    };
+
+    let entries: Vec<FeedEntry> = blox_list
+        .iter()
+        .take(options.limit)
+        .map(|(updated, blox)| {
+            let page = blox
+                .path()
+                .map(|p| html_target(p, config.pretty_urls))
+                .unwrap_or_default();
+            let url = match blox.id_str(config) {
+                Some(id) => format!("{base_url}{page}#{id}"),
+                None => format!("{base_url}{page}"),
+            };
+
+            FeedEntry {
+                id: url.clone(),
+                url,
+                title: blox.title_full(config),
+                content: markdown_to_html(&blox.content),
+                updated: updated.rfc3339.clone(),
+            }
+        })
+        .collect();
+
+    let output = match options.format {
+        FeedFormat::Atom => atom(options, &base_url, &entries),
+        FeedFormat::Json => json_feed(options, &base_url, &entries)?,
+    };
+
+    Ok(output)
+}
+
+/// Parses a TOML date or datetime. Bare dates are taken as midnight and local datetimes as
+/// UTC, while times without a date are rejected.
+fn timestamp(datetime: &str) -> Result<Timestamp> {
+    let invalid = || anyhow::anyhow!("Invalid updated timestamp: {datetime}");
+    let number = |s: &str| s.parse::<i64>().map_err(|_| invalid());
+
+    let (date, time) = match datetime.split_once(['T', 't', ' ']) {
+        Some((date, time)) => (date, time),
+        None if datetime.len() == 10 => (datetime, "00:00:00Z"),
+        None => anyhow::bail!("Updated time without a date: {datetime}"),
+    };
+    let [year, month, day] = date.splitn(3, '-').collect::<Vec<_>>()[..] else {
+        return Err(invalid());
+    };
+    let (year, month, day) = (number(year)?, number(month)?, number(day)?);
+
+    // Local datetimes have no offset
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(i) => time.split_at(i),
+        None => (time, "Z"),
+    };
+    let [hour, minute, second] = time.splitn(3, ':').collect::<Vec<_>>()[..] else {
+        return Err(invalid());
+    };
+    let (second, fraction) = second.split_once('.').unwrap_or((second, ""));
+    let nanos = format!("{fraction:0<9}").get(..9).map_or(Ok(0), number)? as u32;
+    let offset_seconds = match offset {
+        "Z" | "z" => 0,
+        _ => {
+            let (hours, minutes) = offset[1..].split_once(':').ok_or_else(invalid)?;
+            let seconds = number(hours)? * 3600 + number(minutes)? * 60;
+            if offset.starts_with('-') {
+                -seconds
+            } else {
+                seconds
+            }
+        }
+    };
+
+    let seconds = days_from_civil(year, month, day) * 86400
+        + number(hour)? * 3600
+        + number(minute)? * 60
+        + number(second)?
+        - offset_seconds;
+    let offset = match offset {
+        "z" => "Z",
+        offset => offset,
+    };
+    let fraction = match fraction {
+        "" => String::new(),
+        fraction => format!(".{fraction}"),
+    };
+
+    Ok(Timestamp {
+        utc: (seconds, nanos),
+        rfc3339: format!("{date}T{hour}:{minute}:{second}{fraction}{offset}"),
+    })
+}
+
+/// Days since the Unix epoch of a date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn atom(options: &FeedOptions, base_url: &str, entries: &[FeedEntry]) -> String {
+    let updated = entries
+        .first()
+        .map(|e| e.updated.as_str())
+        .unwrap_or("1970-01-01T00:00:00Z");
+
+    let mut output = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>{title}</title>
+  <id>{base_url}</id>
+  <link href="{base_url}"/>
+  <updated>{updated}</updated>
+"#,
+        title = xml_escape(&options.title),
+        base_url = xml_escape(base_url),
+    );
+
+    for entry in entries {
+        output.push_str(&format!(
+            r#"  <entry>
+    <title>{title}</title>
+    <id>{id}</id>
+    <link href="{url}"/>
+    <updated>{updated}</updated>
+    <content type="html">{content}</content>
+  </entry>
+"#,
+            title = xml_escape(&entry.title),
+            id = xml_escape(&entry.id),
+            url = xml_escape(&entry.url),
+            updated = entry.updated,
+            content = xml_escape(&entry.content),
+        ));
+    }
+
+    output.push_str("</feed>\n");
+    output
+}
+
+fn json_feed(options: &FeedOptions, base_url: &str, entries: &[FeedEntry]) -> Result<String> {
+    let items: Vec<_> = entries
+        .iter()
+        .map(|e| {
+            json!({
+                "id": e.id,
+                "url": e.url,
+                "title": e.title,
+                "content_html": e.content,
+                "date_modified": e.updated,
+            })
+        })
+        .collect();
+
+    let feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": options.title,
+        "home_page_url": base_url,
+        "items": items,
+    });
+
+    Ok(serde_json::to_string_pretty(&feed)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_timestamp() -> Result<()> {
+        let rfc3339 = |s| timestamp(s).map(|t| t.rfc3339);
+        assert_eq!(rfc3339("2025-10-01")?, "2025-10-01T00:00:00Z");
+        assert_eq!(rfc3339("2025-10-01T12:00:00Z")?, "2025-10-01T12:00:00Z");
+        assert_eq!(rfc3339("2025-10-01 12:00:00.5")?, "2025-10-01T12:00:00.5Z");
+        assert_eq!(
+            rfc3339("2025-10-01t12:00:00+02:00")?,
+            "2025-10-01T12:00:00+02:00"
+        );
+        assert!(timestamp("12:00:00").is_err());
+
+        assert_eq!(timestamp("1970-01-02")?.utc, (86400, 0));
+        assert_eq!(
+            timestamp("2000-03-01T00:00:00.25Z")?.utc,
+            (951868800, 250_000_000)
+        );
+        // Later in UTC, despite being earlier as text
+        assert!(timestamp("2025-10-01T10:00:00-05:00")? > timestamp("2025-10-01T12:00:00Z")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_atom() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+note = { name = "Note" }
+"#,
+        )?;
+        let content = r#"```blox note label = "a", title = "A & B", updated = 2025-10-01T12:00:00Z
+*First*
+```
+
+```blox note label = "b", updated = 2025-10-01T10:00:00-05:00
+Second
+```
+
+```blox note label = "c", updated = 2025-09-01
+Third
+```
+
+```blox note
+Never updated
+```
+"#;
+        let mut book = Book::new();
+        mdbook_api::push_chapter(
+            &mut book,
+            Chapter::new("Notes", content.to_string(), "notes.md", Vec::new()),
+        );
+        let options = FeedOptions {
+            format: FeedFormat::Atom,
+            title: "Book".to_string(),
+            base_url: "https://example.com/book".to_string(),
+            limit: 2,
+        };
+
+        assert_eq!(
+            feed(&book, &config, &options)?,
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Book</title>
+  <id>https://example.com/book/</id>
+  <link href="https://example.com/book/"/>
+  <updated>2025-10-01T10:00:00-05:00</updated>
+  <entry>
+    <title>Note 2</title>
+    <id>https://example.com/book/notes.html#blox-note-b</id>
+    <link href="https://example.com/book/notes.html#blox-note-b"/>
+    <updated>2025-10-01T10:00:00-05:00</updated>
+    <content type="html">&lt;p&gt;Second&lt;/p&gt;</content>
+  </entry>
+  <entry>
+    <title>Note 1: A &amp; B</title>
+    <id>https://example.com/book/notes.html#blox-note-a</id>
+    <link href="https://example.com/book/notes.html#blox-note-a"/>
+    <updated>2025-10-01T12:00:00Z</updated>
+    <content type="html">&lt;p&gt;&lt;em&gt;First&lt;/em&gt;&lt;/p&gt;</content>
+  </entry>
+</feed>
+"#
+        );
+
+        Ok(())
+    }
+}
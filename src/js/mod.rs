@@ -0,0 +1,4 @@
+/// Script adding previous/next links to blox of environments with `navigation`
+pub fn nav_js() -> &'static str {
+    include_str!("nav.js")
+}
@@ -0,0 +1,58 @@
+// Previous/next links between blox of the same environment, from the `data-blox-prev` and
+// `data-blox-next` attributes. `[` and `]` follow them from the targeted or first visible blox.
+(function () {
+    "use strict";
+
+    const blocks = document.querySelectorAll(".blox[data-blox-prev], .blox[data-blox-next]");
+
+    function link(href, title, text, className) {
+        const a = document.createElement("a");
+        a.href = href;
+        a.className = className;
+        a.textContent = text;
+        if (title) {
+            a.title = title;
+        }
+        return a;
+    }
+
+    blocks.forEach(function (block) {
+        const nav = document.createElement("nav");
+        nav.className = "blox-nav";
+
+        const prev = block.dataset.bloxPrev;
+        const next = block.dataset.bloxNext;
+        if (prev) {
+            nav.appendChild(link(prev, block.dataset.bloxPrevTitle, "← previous", "blox-nav-prev"));
+        }
+        if (next) {
+            nav.appendChild(link(next, block.dataset.bloxNextTitle, "next →", "blox-nav-next"));
+        }
+
+        block.appendChild(nav);
+    });
+
+    function current() {
+        const target = location.hash && document.getElementById(decodeURIComponent(location.hash.slice(1)));
+        if (target && target.matches(".blox[data-blox-prev], .blox[data-blox-next]")) {
+            return target;
+        }
+        return Array.from(blocks).find(function (block) {
+            const rect = block.getBoundingClientRect();
+            return rect.bottom > 0 && rect.top < window.innerHeight;
+        });
+    }
+
+    document.addEventListener("keydown", function (event) {
+        if (event.altKey || event.ctrlKey || event.metaKey || event.target.closest("input, textarea")) {
+            return;
+        }
+
+        const key = { "[": "bloxPrev", "]": "bloxNext" }[event.key];
+        const block = key && current();
+        if (block && block.dataset[key]) {
+            event.preventDefault();
+            window.location.href = block.dataset[key];
+        }
+    });
+})();
@@ -0,0 +1,96 @@
+use std::path::{Component, Path};
+
+/// Chapter file stems that mdBook renders as the index page of their directory
+const INDEX_STEMS: [&str; 2] = ["index", "README"];
+
+/// Renderers checking links against the Markdown sources rather than the rendered HTML
+pub const SOURCE_LINK_RENDERERS: [&str; 1] = ["linkcheck"];
+
+/// Splits the directory part of a relative path into `/`-joinable parts.
+fn dir_parts(source: &Path) -> Vec<String> {
+    source
+        .parent()
+        .into_iter()
+        .flat_map(|p| p.components())
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+            Component::ParentDir => Some("..".to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Keeps the relative chapter source path (`part/chapter.md`) as link target, which is what
+/// mdbook-linkcheck resolves and validates.
+pub fn source_target(source: &Path) -> String {
+    let mut parts = dir_parts(source);
+    if let Some(name) = source.file_name() {
+        parts.push(name.to_string_lossy().into_owned());
+    }
+
+    parts.join("/")
+}
+
+/// Translates a relative chapter source path (`part/chapter.md`) into the link target of the
+/// rendered page, following mdBook's HTML output mapping.
+///
+/// `index.md` and `README.md` map to `index.html`, or to the directory URL if `pretty_urls` is
+/// set. With `pretty_urls`, the `.html` extension of other pages is dropped as well.
+pub fn html_target(source: &Path, pretty_urls: bool) -> String {
+    let mut parts = dir_parts(source);
+
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let is_index = INDEX_STEMS.contains(&stem.as_str());
+
+    match (is_index, pretty_urls) {
+        (true, true) => {
+            if parts.is_empty() {
+                return "./".to_string();
+            }
+            parts.push(String::new());
+        }
+        (true, false) => parts.push("index.html".to_string()),
+        (false, true) => parts.push(stem),
+        (false, false) => parts.push(format!("{stem}.html")),
+    }
+
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_source_target() {
+        assert_eq!(source_target(&PathBuf::from("chapter.md")), "chapter.md");
+        assert_eq!(
+            source_target(&PathBuf::from("../part/README.md")),
+            "../part/README.md"
+        );
+    }
+
+    fn check(source: &str, pretty_urls: bool, expected: &str) {
+        assert_eq!(html_target(&PathBuf::from(source), pretty_urls), expected);
+    }
+
+    #[test]
+    fn test_html_target() {
+        check("chapter.md", false, "chapter.html");
+        check("../part/chapter.md", false, "../part/chapter.html");
+        check("index.md", false, "index.html");
+        check("README.md", false, "index.html");
+        check("part/README.md", false, "part/index.html");
+
+        check("chapter.md", true, "chapter");
+        check("../part/chapter.md", true, "../part/chapter");
+        check("index.md", true, "./");
+        check("part/index.md", true, "part/");
+        check("../README.md", true, "../");
+    }
+}
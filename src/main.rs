@@ -2,19 +2,24 @@
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use mdbook::preprocess::{CmdPreprocessor, Preprocessor};
 use mdbook_blox::BloxPreProcessor;
-use mdbook_blox::config::Config;
-use semver::{Version, VersionReq};
+use mdbook_blox::config::{Config, ConfigOverride};
+use mdbook_blox::export::{ExportField, ExportFormat, ExportOptions};
+use mdbook_blox::feed::{FeedFormat, FeedOptions};
 use std::fs;
-use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 
 /// mdbook preprocessor to add support for admonition-like blocks
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Book directory, by default the nearest one with a `book.toml`
+    #[arg(short = 'd', long, global = true, visible_alias = "dir")]
+    book_dir: Option<PathBuf>,
+    /// Override a configuration value, e.g. `--set defaults.color=#112233`
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    overrides: Vec<ConfigOverride>,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -23,10 +28,60 @@ struct Cli {
 enum Commands {
     /// Check whether a renderer is supported by this preprocessor
     Supports { renderer: String },
-    /// Generate css
-    Css {
+    /// Generate css, and the navigation script if `js` is set
+    Css,
+    /// Export blox to other formats (qti, moodle-xml, anki-tsv, docx-fragments, json, wordlist)
+    Export {
         #[arg(long)]
-        dir: Option<PathBuf>,
+        format: ExportFormat,
+        /// Environment to export, may be repeated (all if omitted)
+        #[arg(long = "env")]
+        envs: Vec<String>,
+        /// Flashcard front (title, full-title, content, footer, solution)
+        #[arg(long, default_value = "title")]
+        front: ExportField,
+        /// Flashcard back (title, full-title, content, footer, solution)
+        #[arg(long, default_value = "content")]
+        back: ExportField,
+        /// Output file (stdout if omitted)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Print the parsed options, number, id and HTML of every blox in a chapter
+    Explain {
+        /// Chapter file, e.g. `src/chapter_1.md`
+        chapter: PathBuf,
+    },
+    /// Time the processing of the book, e.g. to compare releases
+    Bench {
+        #[arg(long, default_value_t = 10)]
+        iterations: usize,
+    },
+    /// Check the refs of the book, failing on unknown or duplicate labels and on numbered refs
+    /// to unnumbered blox
+    Check,
+    /// Compare the blox of two git revisions of the book
+    Diff { from: String, to: String },
+    /// Generate a feed of recently updated blox (atom, json)
+    Feed {
+        #[arg(long, default_value = "atom")]
+        format: FeedFormat,
+        /// URL of the published book
+        #[arg(long)]
+        base_url: String,
+        /// Maximum number of entries
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Output file (stdout if omitted)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert the mdbook-admonish blocks of the book to blox, adding their environments to
+    /// the `book.toml`
+    Migrate {
+        /// List the files to convert without writing them
+        #[arg(long)]
+        dry_run: bool,
     },
 }
 
@@ -45,55 +100,221 @@ fn main() {
 
 fn run(cli: Cli) -> Result<()> {
     match cli.command {
-        None => handle_preprocessing(),
+        None => {
+            reject_unused("the preprocessor", cli.book_dir.as_ref(), &cli.overrides)?;
+            handle_preprocessing()
+        }
         Some(Commands::Supports { renderer }) => {
+            reject_unused("supports", cli.book_dir.as_ref(), &cli.overrides)?;
             handle_supports(renderer);
         }
-        Some(Commands::Css { dir }) => handle_css(dir.unwrap_or_else(|| PathBuf::from("."))),
+        Some(Commands::Css) => handle_css(book_dir(cli.book_dir)?, &cli.overrides),
+        Some(Commands::Export {
+            format,
+            envs,
+            front,
+            back,
+            output,
+        }) => {
+            let options = ExportOptions {
+                envs,
+                front,
+                back,
+                ..ExportOptions::new(format)
+            };
+            handle_export(book_dir(cli.book_dir)?, &cli.overrides, &options, output)
+        }
+        Some(Commands::Explain { chapter }) => {
+            handle_explain(book_dir(cli.book_dir)?, &cli.overrides, &chapter)
+        }
+        Some(Commands::Bench { iterations }) => {
+            handle_bench(book_dir(cli.book_dir)?, &cli.overrides, iterations)
+        }
+        Some(Commands::Check) => handle_check(book_dir(cli.book_dir)?, &cli.overrides),
+        Some(Commands::Diff { from, to }) => {
+            handle_diff(book_dir(cli.book_dir)?, &cli.overrides, &from, &to)
+        }
+        Some(Commands::Feed {
+            format,
+            base_url,
+            limit,
+            output,
+        }) => handle_feed(
+            book_dir(cli.book_dir)?,
+            &cli.overrides,
+            format,
+            base_url,
+            limit,
+            output,
+        ),
+        Some(Commands::Migrate { dry_run }) => {
+            reject_unused("migrate", None, &cli.overrides)?;
+            handle_migrate(book_dir(cli.book_dir)?, dry_run)
+        }
     }
 }
 
-fn handle_preprocessing() -> Result<()> {
-    log::debug!("Start preprocessing blox");
-    let (ctx, book) = CmdPreprocessor::parse_input(io::stdin())?;
-
-    let book_version = Version::parse(&ctx.mdbook_version)?;
-    let version_req = VersionReq::parse(mdbook::MDBOOK_VERSION)?;
-
-    if !version_req.matches(&book_version) {
-        eprintln!(
-            "Warning: The {} plugin was built against version {} of mdbook,\
-             but we're being called from version {}",
-            mdbook_blox::PREPROCESSOR_NAME,
-            mdbook::MDBOOK_VERSION,
-            ctx.mdbook_version
-        );
-    }
+/// Fails on global options given to a command which would ignore them
+fn reject_unused(
+    command: &str,
+    book_dir: Option<&PathBuf>,
+    overrides: &[ConfigOverride],
+) -> Result<()> {
+    anyhow::ensure!(
+        book_dir.is_none(),
+        "--book-dir is not supported by {command}"
+    );
+    anyhow::ensure!(overrides.is_empty(), "--set is not supported by {command}");
+    Ok(())
+}
 
-    let processed_book = BloxPreProcessor.run(&ctx, book)?;
-    serde_json::to_writer(io::stdout(), &processed_book)?;
+/// The given book directory, or the nearest one from the working directory
+fn book_dir(dir: Option<PathBuf>) -> Result<PathBuf> {
+    match dir {
+        Some(dir) => Ok(dir),
+        None => mdbook_blox::book::find_book_dir(&std::env::current_dir()?),
+    }
+}
 
-    Ok(())
+fn handle_preprocessing() -> Result<()> {
+    log::debug!("Start preprocessing blox");
+    mdbook_blox::run_stdio()
 }
 
 fn handle_supports(renderer: String) -> ! {
-    if BloxPreProcessor.supports_renderer(&renderer) {
+    if BloxPreProcessor::new().supports(&renderer) {
         process::exit(0);
     } else {
         process::exit(1);
     }
 }
 
-fn handle_css(dir: PathBuf) -> anyhow::Result<()> {
+fn handle_css(dir: PathBuf, overrides: &[ConfigOverride]) -> anyhow::Result<()> {
     let book_toml = dir.join("book.toml");
     log::info!("Reading configuration file '{}'", book_toml.display());
 
-    let config = Config::from_file(&book_toml)?;
-    let css = mdbook_blox::css::css_from_config(&config)?;
+    let config = Config::from_file(&book_toml, overrides)?;
+    for (media, file) in config.css.targets() {
+        let css = mdbook_blox::css::css_for_media(&config, media)?;
+
+        let output = dir.join(file);
+        log::info!("Writing custom CSS file '{}'", output.display());
+        fs::write(output, css)?;
+    }
 
-    let output = dir.join(config.css);
-    log::info!("Writing custom CSS file '{}'", output.display());
-    fs::write(output, css)?;
+    if let Some(file) = config.js.as_ref() {
+        let output = dir.join(file);
+        log::info!("Writing navigation script '{}'", output.display());
+        fs::write(output, mdbook_blox::js::nav_js())?;
+    }
+
+    Ok(())
+}
+
+fn handle_export(
+    dir: PathBuf,
+    overrides: &[ConfigOverride],
+    options: &ExportOptions,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let loaded = mdbook_blox::book::load(&dir, overrides)?;
+    let exported = mdbook_blox::export::export(&loaded.book, &loaded.config, options)?;
+
+    match output {
+        Some(output) => {
+            log::info!("Writing export '{}'", output.display());
+            fs::write(output, exported)?;
+        }
+        None => print!("{exported}"),
+    }
+
+    Ok(())
+}
+
+fn handle_explain(dir: PathBuf, overrides: &[ConfigOverride], chapter: &Path) -> Result<()> {
+    let loaded = mdbook_blox::book::load(&dir, overrides)?;
+
+    // Accept paths of chapter files, or relative to the source directory
+    let src = &loaded.mdbook_config.book.src;
+    let chapter = match (chapter.canonicalize(), dir.join(src).canonicalize()) {
+        (Ok(file), Ok(src_dir)) if file.starts_with(&src_dir) => {
+            file.strip_prefix(&src_dir)?.to_path_buf()
+        }
+        _ => chapter.strip_prefix(src).unwrap_or(chapter).to_path_buf(),
+    };
+
+    let explained = mdbook_blox::explain::explain(&loaded.book, &loaded.config, &chapter)?;
+    print!("{explained}");
+
+    Ok(())
+}
+
+fn handle_bench(dir: PathBuf, overrides: &[ConfigOverride], iterations: usize) -> Result<()> {
+    let loaded = mdbook_blox::book::load(&dir, overrides)?;
+    let report = mdbook_blox::bench::bench(&loaded.book, &loaded.config, iterations)?;
+    print!("{report}");
+
+    Ok(())
+}
+
+fn handle_check(dir: PathBuf, overrides: &[ConfigOverride]) -> Result<()> {
+    let loaded = mdbook_blox::book::load(&dir, overrides)?;
+    let problems = mdbook_blox::check::check(&loaded.book, &loaded.config)?;
+    for problem in problems.iter() {
+        println!("{problem}");
+    }
+
+    anyhow::ensure!(
+        problems.is_empty(),
+        "{} broken blox references",
+        problems.len()
+    );
+    Ok(())
+}
+
+fn handle_diff(dir: PathBuf, overrides: &[ConfigOverride], from: &str, to: &str) -> Result<()> {
+    let changes = mdbook_blox::diff::diff_revisions(&dir, from, to, overrides)?;
+    for change in changes.iter() {
+        println!("{change}");
+    }
+
+    Ok(())
+}
+
+fn handle_feed(
+    dir: PathBuf,
+    overrides: &[ConfigOverride],
+    format: FeedFormat,
+    base_url: String,
+    limit: usize,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let loaded = mdbook_blox::book::load(&dir, overrides)?;
+    let options = FeedOptions {
+        format,
+        title: loaded.title().unwrap_or("Recently updated").to_string(),
+        base_url,
+        limit,
+    };
+    let feed = mdbook_blox::feed::feed(&loaded.book, &loaded.config, &options)?;
+
+    match output {
+        Some(output) => {
+            log::info!("Writing feed '{}'", output.display());
+            fs::write(output, feed)?;
+        }
+        None => print!("{feed}"),
+    }
+
+    Ok(())
+}
+
+fn handle_migrate(dir: PathBuf, dry_run: bool) -> Result<()> {
+    let report = mdbook_blox::migrate::migrate(&dir, dry_run)?;
+    print!("{report}");
+    if !dry_run && report.blocks > 0 {
+        log::info!("Remove [preprocessor.admonish] from the book.toml once the book builds");
+    }
 
     Ok(())
 }
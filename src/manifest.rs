@@ -0,0 +1,90 @@
+#[cfg(feature = "mdbook")]
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "mdbook")]
+use std::fs;
+#[cfg(feature = "mdbook")]
+use std::path::Path;
+
+/// A linkable blox, as listed in the fragment manifest
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FragmentEntry {
+    /// Link to the blox, relative to the book root (`chapter.html#id`)
+    pub href: String,
+    pub id: String,
+    pub environment: String,
+    pub label: Option<String>,
+    pub number: Option<String>,
+    pub title: Option<String>,
+}
+
+/// Writes the manifest as JSON if `file` ends with `.json`, else one `href` per line.
+#[cfg(feature = "mdbook")]
+pub fn write_fragment_manifest(file: &Path, entries: &[FragmentEntry]) -> Result<()> {
+    let data = match file.extension().is_some_and(|e| e == "json") {
+        true => serde_json::to_string_pretty(entries)?,
+        false => entries
+            .iter()
+            .map(|e| format!("{}\n", e.href))
+            .collect::<String>(),
+    };
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).context("Can't create fragment manifest directory")?;
+    }
+
+    log::info!("Writing fragment manifest '{}'", file.display());
+    fs::write(file, data).context("Can't write fragment manifest")
+}
+
+/// Reads a JSON manifest written by `write_fragment_manifest`
+#[cfg(feature = "mdbook")]
+pub fn read_fragment_manifest(file: &Path) -> Result<Vec<FragmentEntry>> {
+    let data = fs::read_to_string(file)
+        .with_context(|| format!("Can't read fragment manifest '{}'", file.display()))?;
+    serde_json::from_str(&data).context("Invalid fragment manifest")
+}
+
+#[cfg(all(test, feature = "mdbook"))]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_fragment_manifest() -> Result<()> {
+        let entries = vec![
+            FragmentEntry {
+                href: "chapter_1.html#blox-exercise-sum".to_string(),
+                id: "blox-exercise-sum".to_string(),
+                environment: "exercise".to_string(),
+                label: Some("sum".to_string()),
+                number: Some("1.1".to_string()),
+                title: Some("Sum".to_string()),
+            },
+            FragmentEntry {
+                href: "part/chapter_2.html#blox-alert-1".to_string(),
+                id: "blox-alert-1".to_string(),
+                environment: "alert".to_string(),
+                label: None,
+                number: None,
+                title: None,
+            },
+        ];
+        let dir = tempfile::tempdir()?;
+
+        let json = dir.path().join("out/fragments.json");
+        write_fragment_manifest(&json, &entries)?;
+        assert_eq!(read_fragment_manifest(&json)?, entries);
+
+        let text = dir.path().join("fragments.txt");
+        write_fragment_manifest(&text, &entries)?;
+        assert_eq!(
+            fs::read_to_string(&text)?,
+            "chapter_1.html#blox-exercise-sum\npart/chapter_2.html#blox-alert-1\n"
+        );
+        assert!(read_fragment_manifest(&text).is_err());
+        assert!(read_fragment_manifest(&dir.path().join("missing.json")).is_err());
+
+        Ok(())
+    }
+}
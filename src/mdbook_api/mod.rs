@@ -0,0 +1,53 @@
+//! The parts of the mdBook API the crate uses.
+//!
+//! Everything else goes through this module, so the differences between the versions of the
+//! preprocessor API stay in the backend selected by the `mdbook-0_4` or `mdbook-0_5` feature.
+//! Features are additive, so `mdbook-0_5` takes precedence when both are enabled.
+
+#[cfg(not(any(feature = "mdbook-0_4", feature = "mdbook-0_5")))]
+compile_error!("The `mdbook` feature needs one of the features `mdbook-0_4` or `mdbook-0_5`");
+
+#[cfg(all(feature = "mdbook-0_4", not(feature = "mdbook-0_5")))]
+mod v0_4;
+#[cfg(all(feature = "mdbook-0_4", not(feature = "mdbook-0_5")))]
+pub use v0_4::*;
+#[cfg(feature = "mdbook-0_5")]
+mod v0_5;
+#[cfg(feature = "mdbook-0_5")]
+pub use v0_5::*;
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Appends a chapter after the last item of a book
+#[inline]
+pub fn push_chapter(book: &mut Book, chapter: Chapter) {
+    book.push_item(chapter);
+}
+
+/// Name of the renderer the book is preprocessed for, like `html`
+#[inline]
+pub fn renderer(ctx: &PreprocessorContext) -> &str {
+    &ctx.renderer
+}
+/// Book root directory, containing the `book.toml`
+#[inline]
+pub fn root(ctx: &PreprocessorContext) -> &Path {
+    &ctx.root
+}
+/// Book source directory, containing the `SUMMARY.md`
+#[inline]
+pub fn source_dir(ctx: &PreprocessorContext) -> PathBuf {
+    ctx.root.join(&ctx.config.book.src)
+}
+/// Version of mdBook running the preprocessor
+#[inline]
+pub fn mdbook_version(ctx: &PreprocessorContext) -> &str {
+    &ctx.mdbook_version
+}
+
+/// Source directory of a book, relative to its root, from the contents of its `book.toml`
+pub fn book_src(book_toml: &str) -> Result<PathBuf> {
+    Ok(MdBookConfig::from_str(book_toml)?.book.src)
+}
@@ -0,0 +1,46 @@
+//! Backend for mdBook 0.4
+
+use anyhow::Result;
+pub use mdbook::book::{Book, BookItem, Chapter};
+pub use mdbook::preprocess::{Preprocessor, PreprocessorContext};
+pub use mdbook::{Config as MdBookConfig, MDBOOK_VERSION};
+use std::io::Read;
+use std::path::Path;
+
+/// Top-level items of a book, in summary order
+#[inline]
+pub fn book_items(book: &Book) -> &[BookItem] {
+    &book.sections
+}
+#[inline]
+pub fn book_items_mut(book: &mut Book) -> &mut Vec<BookItem> {
+    &mut book.sections
+}
+
+/// Section number of a chapter, like `1.2.` for `[1, 2]`
+#[cfg(test)]
+pub fn section_number(numbers: Vec<u32>) -> mdbook::book::SectionNumber {
+    mdbook::book::SectionNumber(numbers)
+}
+
+/// The `[preprocessor.<name>]` table of the book configuration
+#[inline]
+pub fn preprocessor_table(
+    ctx: &PreprocessorContext,
+    name: &str,
+) -> Result<Option<toml::value::Table>> {
+    Ok(ctx.config.get_preprocessor(name).cloned())
+}
+
+/// Parses the `[context, book]` JSON mdBook passes to preprocessors
+#[inline]
+pub fn parse_input(reader: impl Read) -> Result<(PreprocessorContext, Book)> {
+    mdbook::preprocess::CmdPreprocessor::parse_input(reader)
+}
+
+/// Loads the chapters of the book in `root` from disk, without creating missing ones
+pub fn load_book(root: &Path, config: &MdBookConfig) -> Result<Book> {
+    let mut build = config.build.clone();
+    build.create_missing = false;
+    mdbook::book::load_book(root.join(&config.book.src), &build)
+}
@@ -0,0 +1,46 @@
+//! Backend for mdBook 0.5, split into the `mdbook-preprocessor` and `mdbook-driver` crates
+
+use anyhow::Result;
+use mdbook_driver::MDBook;
+pub use mdbook_preprocessor::book::{Book, BookItem, Chapter};
+pub use mdbook_preprocessor::config::Config as MdBookConfig;
+pub use mdbook_preprocessor::{MDBOOK_VERSION, Preprocessor, PreprocessorContext};
+use std::io::Read;
+use std::path::Path;
+
+/// Top-level items of a book, in summary order
+#[inline]
+pub fn book_items(book: &Book) -> &[BookItem] {
+    &book.items
+}
+#[inline]
+pub fn book_items_mut(book: &mut Book) -> &mut Vec<BookItem> {
+    &mut book.items
+}
+
+/// Section number of a chapter, like `1.2.` for `[1, 2]`
+#[cfg(test)]
+pub fn section_number(numbers: Vec<u32>) -> mdbook_preprocessor::book::SectionNumber {
+    mdbook_preprocessor::book::SectionNumber::new(numbers)
+}
+
+/// The `[preprocessor.<name>]` table of the book configuration
+pub fn preprocessor_table(
+    ctx: &PreprocessorContext,
+    name: &str,
+) -> Result<Option<toml::value::Table>> {
+    ctx.config.get(&format!("preprocessor.{name}"))
+}
+
+/// Parses the `[context, book]` JSON mdBook passes to preprocessors
+#[inline]
+pub fn parse_input(reader: impl Read) -> Result<(PreprocessorContext, Book)> {
+    mdbook_preprocessor::parse_input(reader)
+}
+
+/// Loads the chapters of the book in `root` from disk, without creating missing ones
+pub fn load_book(root: &Path, config: &MdBookConfig) -> Result<Book> {
+    let mut config = config.clone();
+    config.build.create_missing = false;
+    Ok(MDBook::load_with_config(root, config)?.book)
+}
@@ -0,0 +1,357 @@
+use crate::config::{CODE_BLOCK_KEYWORD, PREPROCESSOR_NAME};
+use crate::mdbook_api;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Language of the fenced blocks of mdbook-admonish
+const ADMONISH_KEYWORD: &str = "admonish";
+
+/// Directives of mdbook-admonish with their aliases and colors
+const DIRECTIVES: [(&str, &[&str], u32); 12] = [
+    ("note", &[], 0x448AFF),
+    ("abstract", &["summary", "tldr"], 0x00B0FF),
+    ("info", &["todo"], 0x00B8D4),
+    ("tip", &["hint", "important"], 0x00BFA5),
+    ("success", &["check", "done"], 0x00C853),
+    ("question", &["help", "faq"], 0x64DD17),
+    ("warning", &["caution", "attention"], 0xFF9100),
+    ("failure", &["fail", "missing"], 0xFF5252),
+    ("danger", &["error"], 0xFF1744),
+    ("bug", &[], 0xF50057),
+    ("example", &[], 0x7C4DFF),
+    ("quote", &["cite"], 0x9E9E9E),
+];
+
+/// Changes made, or to be made, by a migration
+#[derive(Debug, Clone, Default)]
+pub struct MigrateReport {
+    /// Chapter files with converted blocks, relative to the book directory
+    pub files: Vec<PathBuf>,
+    pub blocks: usize,
+    /// Environments added to the `book.toml`
+    pub environments: Vec<String>,
+}
+
+impl fmt::Display for MigrateReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for file in self.files.iter() {
+            writeln!(f, "{}", file.display())?;
+        }
+        writeln!(
+            f,
+            "{} admonish blocks in {} files, {} new environments",
+            self.blocks,
+            self.files.len(),
+            self.environments.len()
+        )
+    }
+}
+
+/// Converts the mdbook-admonish blocks of the book in `dir` to blox, and adds their
+/// environments to its `book.toml`. Nothing is written with `dry_run`.
+pub fn migrate(dir: &Path, dry_run: bool) -> Result<MigrateReport> {
+    let book_toml = dir.join("book.toml");
+    let data = fs::read_to_string(&book_toml).context("Can't read configuration file")?;
+    let src = dir.join(mdbook_api::book_src(&data)?);
+
+    let mut chapters = Vec::new();
+    markdown_files(&src, &mut chapters)?;
+
+    let mut report = MigrateReport::default();
+    let mut directives = BTreeSet::new();
+    for file in chapters {
+        let source = fs::read_to_string(&file)?;
+        let migrated = migrate_markdown(&source);
+        if migrated.blocks == 0 {
+            continue;
+        }
+
+        if !dry_run {
+            fs::write(&file, &migrated.content)?;
+        }
+        report.blocks += migrated.blocks;
+        directives.extend(migrated.directives);
+        report
+            .files
+            .push(file.strip_prefix(dir).unwrap_or(&file).to_path_buf());
+    }
+
+    let (tables, environments) = environment_tables(&data, &directives)?;
+    report.environments = environments;
+    if !dry_run && !tables.is_empty() {
+        fs::write(&book_toml, format!("{}\n{tables}", data.trim_end()))?;
+    }
+
+    Ok(report)
+}
+
+/// Markdown with its admonish blocks converted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigratedMarkdown {
+    pub content: String,
+    pub blocks: usize,
+    /// Directives of the converted blocks, without aliases
+    pub directives: BTreeSet<String>,
+}
+
+/// Converts the opening fences of admonish blocks, leaving other fenced blocks as they are
+pub fn migrate_markdown(source: &str) -> MigratedMarkdown {
+    let mut migrated = MigratedMarkdown::default();
+    // Character and length of the fence of the open block
+    let mut open: Option<(char, usize)> = None;
+
+    for line in source.split_inclusive('\n') {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let fence = &line[indent..];
+        let fence_char = fence.chars().next().filter(|c| matches!(c, '`' | '~'));
+        let fence_len = fence_char.map_or(0, |c| fence.len() - fence.trim_start_matches(c).len());
+        let info = fence[fence_len..].trim();
+
+        if indent >= 4 || fence_len < 3 {
+            migrated.content.push_str(line);
+            continue;
+        }
+        match open {
+            Some((c, len)) => {
+                if fence_char == Some(c) && fence_len >= len && info.is_empty() {
+                    open = None;
+                }
+            }
+            None => {
+                open = fence_char.map(|c| (c, fence_len));
+                if let Some((directive, header)) = convert_info(info) {
+                    let newline = &line[line.trim_end_matches(['\n', '\r']).len()..];
+                    migrated.content.push_str(&line[..indent + fence_len]);
+                    migrated.content.push_str(&header);
+                    migrated.content.push_str(newline);
+                    migrated.blocks += 1;
+                    migrated.directives.insert(directive);
+                    continue;
+                }
+            }
+        }
+        migrated.content.push_str(line);
+    }
+
+    migrated
+}
+
+/// The directive and blox header of an admonish info string, like `blox warning title = "x"`
+/// for `admonish caution title="x"`
+fn convert_info(info: &str) -> Option<(String, String)> {
+    let rest = info.strip_prefix(ADMONISH_KEYWORD)?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut tokens = tokens(rest.trim()).into_iter().peekable();
+    let directive = match tokens.next_if(|t| !t.contains('=')) {
+        Some(name) => canonical_directive(&name),
+        None => "note".to_string(),
+    };
+
+    let mut options = Vec::new();
+    for token in tokens {
+        let Some((key, value)) = token.split_once('=') else {
+            log::warn!("Ignoring admonish option: {token}");
+            continue;
+        };
+        let value = unquote(value);
+        match key {
+            "title" if value.is_empty() => options.push("hide_header = true".to_string()),
+            "title" | "class" => {
+                options.push(format!("{key} = {}", toml::Value::String(value)));
+            }
+            "collapsible" => options.push(format!("collapsible = {}", value == "true")),
+            _ => log::warn!("Ignoring admonish option: {token}"),
+        }
+    }
+
+    let header = match options.is_empty() {
+        true => format!("{CODE_BLOCK_KEYWORD} {directive}"),
+        false => format!("{CODE_BLOCK_KEYWORD} {directive} {}", options.join(", ")),
+    };
+    Some((directive, header))
+}
+
+/// Splits an info string at whitespace outside of quotes
+fn tokens(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for c in s.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        token.push(c);
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+/// Value of an option, without quotes and escapes
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(inner) => inner.replace("\\\"", "\"").replace("\\\\", "\\"),
+        None => value.to_string(),
+    }
+}
+
+/// Directive without alias, like `warning` for `caution`
+fn canonical_directive(name: &str) -> String {
+    let name = name.to_lowercase();
+    DIRECTIVES
+        .iter()
+        .find(|(directive, aliases, _)| *directive == name || aliases.contains(&name.as_str()))
+        .map_or(name, |(directive, _, _)| directive.to_string())
+}
+
+/// Tables of the environments of `directives` missing from the `book.toml` contents `data`,
+/// with the names of the environments
+fn environment_tables(data: &str, directives: &BTreeSet<String>) -> Result<(String, Vec<String>)> {
+    let book_config: toml::Value = toml::from_str(data).context("Invalid configuration file")?;
+    let preprocessor = book_config
+        .get("preprocessor")
+        .and_then(|p| p.get(PREPROCESSOR_NAME));
+    let configured = preprocessor.and_then(|p| p.get("environments"));
+
+    let mut tables = String::new();
+    let mut environments = Vec::new();
+    if preprocessor.is_none() && !directives.is_empty() {
+        tables.push_str(&format!("\n[preprocessor.{PREPROCESSOR_NAME}]\n"));
+    }
+
+    for directive in directives {
+        if configured.and_then(|e| e.get(directive)).is_some() {
+            continue;
+        }
+        let color = DIRECTIVES
+            .iter()
+            .find(|(d, _, _)| d == directive)
+            .map_or(0x448AFF, |(_, _, color)| *color);
+        let mut name = directive.clone();
+        name[..1].make_ascii_uppercase();
+
+        tables.push_str(&format!(
+            "\n[preprocessor.{PREPROCESSOR_NAME}.environments.{directive}]\nname = \"{name}\"\ncolor = \"#{color:06X}\"\nnumbered = false\n"
+        ));
+        environments.push(directive.clone());
+    }
+
+    Ok((tables, environments))
+}
+
+/// Markdown files in `dir` and its subdirectories, in order
+fn markdown_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Can't read directory {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            markdown_files(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "md") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_migrate_markdown() {
+        let source = r#"# Chapter
+
+```admonish
+Plain
+```
+
+~~~admonish caution title="Mind \"this\"" collapsible=true
+Careful
+~~~
+
+````markdown
+```admonish tip
+Shown as source
+```
+````
+
+```admonish example title=""
+Untitled
+```
+"#;
+        let migrated = migrate_markdown(source);
+        assert_eq!(
+            migrated.content,
+            r#"# Chapter
+
+```blox note
+Plain
+```
+
+~~~blox warning title = "Mind \"this\"", collapsible = true
+Careful
+~~~
+
+````markdown
+```admonish tip
+Shown as source
+```
+````
+
+```blox example hide_header = true
+Untitled
+```
+"#
+        );
+        assert_eq!(migrated.blocks, 3);
+        assert_eq!(
+            migrated.directives.into_iter().collect::<Vec<_>>(),
+            ["example", "note", "warning"]
+        );
+        assert_eq!(convert_info("admonishment"), None);
+    }
+
+    #[test]
+    fn test_environment_tables() -> Result<()> {
+        let data = "[book]\ntitle = \"Book\"\n\n[preprocessor.blox.environments]\nnote = { name = \"Remark\" }\n";
+        let directives = ["note", "warning"].map(String::from).into();
+        let (tables, environments) = environment_tables(data, &directives)?;
+        assert_eq!(environments, ["warning"]);
+
+        let config = Config::from_book_toml(&format!("{data}{tables}"))?;
+        assert_eq!(config.name("note"), "Remark");
+        assert_eq!(config.name("warning"), "Warning");
+        assert!(!config.numbered("warning"));
+
+        let (tables, _) = environment_tables("[book]\n", &directives)?;
+        assert!(tables.starts_with("\n[preprocessor.blox]\n"));
+
+        Ok(())
+    }
+}
@@ -1,28 +1,123 @@
-use crate::config::{CODE_BLOCK_KEYWORD, Config, to_toml_ascii};
+use crate::config::{
+    CODE_BLOCK_KEYWORD, Config, EnvironmentKind, HeaderConfig, HeaderVisibility, IdSource,
+    NamelessNumbering, NumberStyle, Profile, RefText, to_toml_ascii,
+};
+use crate::link::{html_target, source_target};
+use crate::render::{BloxRender, HeaderParts};
+use crate::typography::smart_punctuation;
 use anyhow::{Context, Result};
 use pathdiff::diff_paths;
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
 use std::path::PathBuf;
+use toml::value::Datetime;
 
-#[derive(Debug, Deserialize, Clone, Default)]
+/// A parsed blox, the data model shared by rendering, exports and external tools.
+///
+/// The content borrows from the chapter source, see [`BloxOwned`] to keep a blox on its own.
+/// Serializes with serde, without the fields only used for rendering.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[serde(default)]
 pub struct Blox<'a> {
     /// Must be a key that matches environments in config
     pub environment: String,
+    /// Chapter path, relative to the book source directory
     pub path: Option<PathBuf>,
+    /// Markdown content
     pub content: Cow<'a, str>,
 
+    /// Only rendered where placed by `{{ blox-render: label }}`
     pub defer_rendering: bool,
 
     pub title: Option<String>,
     pub footer: Option<String>,
+    /// Secondary text at the right of the header
+    pub header_right: Option<String>,
     pub label: Option<String>,
+    /// Number within its counter, like `2.1`
     pub number: Option<String>,
+    pub points: Option<f64>,
+    /// Label of the blox this blox is a solution of
+    pub solution_of: Option<String>,
+    /// Label of the blox this blox is a proof of
+    pub proof_of: Option<String>,
+    /// Labels of related blox, listed in the footer
+    pub see_also: Vec<String>,
+    #[serde(
+        serialize_with = "serialize_datetime",
+        deserialize_with = "deserialize_datetime"
+    )]
+    pub updated: Option<Datetime>,
+    pub tags: Vec<String>,
+    /// Language of the blox, if it differs from the book
+    pub lang: Option<String>,
+    /// Exercise set the blox is numbered within, like `3.2`
+    pub set: Option<String>,
+    /// Extra CSS classes, separated by spaces
+    pub class: Option<String>,
+    /// Extra HTML attributes of the rendered blox
+    pub attrs: BTreeMap<String, String>,
+    pub hints: Vec<Hint>,
+    /// CSS classes of the book part and directories the blox is in, if they have scoped defaults
+    #[serde(skip)]
+    pub part: Option<String>,
+    /// Heading level of the blox in the PDF outline, below the section it is in
+    #[serde(skip)]
+    pub outline_level: u8,
+    /// Number of blox the blox is nested in
+    #[serde(skip)]
+    pub depth: u8,
+    /// Number of `##` headings before the blox in its chapter
+    #[serde(skip)]
+    pub heading: usize,
+    /// A proof of the blox is rendered in another chapter, noted in the footer
+    #[serde(skip)]
+    pub proof_elsewhere: bool,
+    /// Suffix of a content hash id shared with an earlier blox of the page, from `2` up
+    #[serde(skip)]
+    pub id_suffix: Option<usize>,
+    /// Id of an unlabelled blox, from the first equation `\label` in it, like `eq-square`
+    #[serde(skip)]
+    pub equation_id: Option<String>,
 
     // Defaultable
-    pub hide_name: bool,
-    pub hide_header: bool,
+    /// Parts of the header shown
+    pub header: HeaderVisibility,
+    pub hide_footer: bool,
+    /// Rendered as a `<details>` element with the header as summary
+    pub collapsible: bool,
+    /// Ends with a tombstone in the footer
+    pub qed: bool,
+    /// Collapsible blox is collapsed initially
+    pub closed: bool,
+    /// Only the header is rendered
+    pub statement_only: bool,
+    /// Content is shown as preformatted text instead of Markdown
+    pub verbatim: bool,
+    /// Left out of exports and feeds, while still numbered and referable
+    pub no_index: bool,
+}
+
+/// A blox owning its content
+pub type BloxOwned = Blox<'static>;
+
+/// Dates as in TOML, like `2025-10-01`
+fn serialize_datetime<S: Serializer>(
+    date: &Option<Datetime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    date.as_ref().map(|d| d.to_string()).serialize(serializer)
+}
+
+fn deserialize_datetime<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Datetime>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|d| d.parse().map_err(serde::de::Error::custom))
+        .transpose()
 }
 
 impl<'a> PartialEq for Blox<'a> {
@@ -30,79 +125,175 @@ impl<'a> PartialEq for Blox<'a> {
         self.environment == other.environment
             && self.title == other.title
             && self.footer == other.footer
+            && self.header_right == other.header_right
             && self.label == other.label
             && self.number == other.number
+            && self.points == other.points
+            && self.solution_of == other.solution_of
+            && self.proof_of == other.proof_of
+            && self.see_also == other.see_also
+            && self.updated == other.updated
+            && self.tags == other.tags
+            && self.lang == other.lang
+            && self.set == other.set
+            && self.class == other.class
+            && self.attrs == other.attrs
+            && self.hints == other.hints
             && self.defer_rendering == other.defer_rendering
-            && self.hide_name == other.hide_name
-            && self.hide_header == other.hide_header
+            && self.header == other.header
+            && self.hide_footer == other.hide_footer
+            && self.collapsible == other.collapsible
+            && self.qed == other.qed
+            && self.closed == other.closed
+            && self.statement_only == other.statement_only
+            && self.verbatim == other.verbatim
+            && self.no_index == other.no_index
     }
 }
 
 impl<'a> Blox<'a> {
+    /// Copies the content, detaching the blox from the chapter source
+    pub fn into_owned(self) -> BloxOwned {
+        Blox {
+            content: Cow::Owned(self.content.into_owned()),
+            ..self
+        }
+    }
+
     #[cfg(test)]
     pub fn new(environment: &str) -> Self {
-        let mut blox = Self::default();
-        blox.environment = environment.to_string();
-        blox
+        Self {
+            environment: environment.to_string(),
+            ..Default::default()
+        }
     }
 
-    /// Tries to parse `blox env [options]`
+    /// Tries to parse a fenced block with the header `blox env [options]`, and options on a
+    /// closing fence like ```` ``` footer = "Adapted from Rudin" ````
     pub fn parse(config: &Config, content: &'a str, header: &str) -> Result<Option<Self>> {
-        let header = header.trim();
-
-        // If the header doesn't start with `blox`, we exit early
-        if !header.starts_with(CODE_BLOCK_KEYWORD) {
-            return Ok(None);
-        }
+        let (content, header) = match closing_options(content) {
+            Some(closing) => {
+                let header = header.trim();
+                let separator = match HeaderTokens::parse(config, header)? {
+                    Some(HeaderTokens {
+                        options: Some(_), ..
+                    }) => ",",
+                    Some(_) => "",
+                    None => return Ok(None),
+                };
+                (
+                    &content[..closing.start],
+                    Cow::Owned(format!("{header}{separator} {}", closing.options)),
+                )
+            }
+            None => (content, Cow::Borrowed(header)),
+        };
 
-        let Some((keyword, rest)) = header.split_once(' ') else {
+        let Some(mut blox) = Self::from_header(config, &header)? else {
             return Ok(None);
         };
 
-        // False alarm -- header must start with something like `bloxx`
-        if keyword != CODE_BLOCK_KEYWORD {
-            return Ok(None);
-        }
+        blox.content = extract_content(content).context("Invalid fenced block")?;
+        Ok(Some(blox))
+    }
 
-        let (env, opts_str) = match rest.trim().split_once(' ') {
-            Some((e, o)) => (e, Some(o.trim()).filter(|s| !s.is_empty())),
-            None => (rest, None),
+    /// Tries to parse the blox of a `:::blox env {options}` directive
+    pub(crate) fn from_directive(
+        config: &Config,
+        directive: &Directive<'a>,
+    ) -> Result<Option<Self>> {
+        let Some(mut blox) = Self::from_header(config, &directive.header)? else {
+            return Ok(None);
         };
 
-        anyhow::ensure!(!env.is_empty(), "No blox environment specified");
+        blox.content = Cow::Borrowed(directive.content);
+        Ok(Some(blox))
+    }
 
-        anyhow::ensure!(
-            config.has_environment(env),
-            "Blox environment not defined in book.toml"
-        );
+    /// Tries to parse `blox env [options]`, leaving the content empty
+    pub fn from_header(config: &Config, header: &str) -> Result<Option<Self>> {
+        let header = header.trim();
+        let Some(HeaderTokens {
+            env,
+            options: opts_str,
+        }) = HeaderTokens::parse(config, header)?
+        else {
+            return Ok(None);
+        };
 
         // Parse CodeBlockOptions from header
         let options = match opts_str {
-            Some(o) => CodeBlockOptions::from_string(o)?,
+            Some(o) => CodeBlockOptions::from_header(header, o, &config.profiles)?,
             None => CodeBlockOptions::default(),
         };
 
-        let hide_header = options.hide_header.unwrap_or(config.hide_header(env));
-        // Hide name if header is hidden
-        let hide_name = hide_header || options.hide_name.unwrap_or(config.hide_name(env));
-        // Only numbered if name is not hidden and is numbered
-        let number = (!hide_name && options.numbered.unwrap_or(config.numbered(env)))
-            .then_some(String::new());
+        let mut visibility = options
+            .header
+            .or(HeaderConfig::from_hidden(
+                options.hide_header,
+                options.hide_name,
+            ))
+            .or(config.header(env))
+            .resolve();
+        let numbered = options.numbered.unwrap_or(config.numbered(env));
+        let number = match (
+            visibility.show && visibility.name,
+            config.nameless_numbering(env),
+        ) {
+            (true, _) => numbered,
+            (false, NamelessNumbering::Off) => false,
+            (false, NamelessNumbering::Header) => numbered,
+            (false, NamelessNumbering::Refs) => {
+                visibility.number = false;
+                numbered
+            }
+        }
+        .then_some(String::new());
+
+        let typography = |s: String| match config.smart_punctuation {
+            true => smart_punctuation(&s),
+            false => s,
+        };
 
         let opts = Self {
             environment: env.to_string(),
 
-            content: extract_content(content)?,
+            content: Cow::default(),
             path: None,
 
-            title: options.title,
-            footer: options.footer,
-            label: options.label.as_deref().map(to_toml_ascii),
+            title: options.title.map(typography),
+            footer: options.footer.map(typography),
+            header_right: options.header_right.map(typography),
+            label: options.label.as_deref().map(label_ascii),
             defer_rendering: options.defer_rendering,
+            points: options.points,
+            solution_of: options.solution_of.as_deref().map(label_ascii),
+            proof_of: options.proof_of.as_deref().map(label_ascii),
+            see_also: options.see_also.iter().map(|l| label_ascii(l)).collect(),
+            updated: options.updated,
+            tags: options.tags,
+            lang: options.lang,
+            set: options.set,
+            class: options.class,
+            attrs: options.attrs,
+            hints: options.hints,
+            part: None,
+            outline_level: 2,
+            depth: 0,
+            heading: 0,
+            proof_elsewhere: false,
+            id_suffix: None,
+            equation_id: None,
 
             // Defaultable
-            hide_header,
-            hide_name,
+            header: visibility,
+            hide_footer: options.hide_footer.unwrap_or(config.hide_footer(env)),
+            collapsible: options.collapsible.unwrap_or(config.collapsible(env)),
+            qed: config.qed(env),
+            closed: !options.open.unwrap_or(config.open(env)),
+            statement_only: options.statement_only,
+            verbatim: options.verbatim,
+            no_index: !options.index.unwrap_or(true),
             number,
         };
 
@@ -117,6 +308,14 @@ impl<'a> Blox<'a> {
     pub fn title(&self) -> Option<&str> {
         self.title.as_deref()
     }
+    /// The term a blox of the `glossary` kind defines, its title
+    #[inline]
+    pub fn glossary_term(&self, config: &Config) -> Option<&str> {
+        match config.kind(self.env()) {
+            EnvironmentKind::Glossary => self.title(),
+            _ => None,
+        }
+    }
     #[inline]
     pub fn title_numbered(&self, config: &Config) -> Option<String> {
         let num = self.number()?;
@@ -132,40 +331,177 @@ impl<'a> Blox<'a> {
     }
     #[inline]
     pub fn title_full(&self, config: &Config) -> String {
-        let mut s = config.name(self.env()).to_string();
-
-        if let Some(n) = self.number() {
-            s.push_str(&format!(" {n}"));
+        let parts = HeaderParts {
+            name: Some(config.name(self.env())),
+            number: self.number(),
+            title: self.title(),
+            of: None,
+        };
+        parts.compose(str::to_string).unwrap_or_default()
+    }
+    #[inline]
+    pub fn title_auto(&self, config: &Config) -> Option<String> {
+        if !self.header.show {
+            let full = || self.title_full(config);
+            return Some(match config.ref_text_when_hidden(self.env()) {
+                RefText::Full => full(),
+                RefText::Title => self.title().map(|s| s.to_owned()).unwrap_or_else(full),
+                RefText::Number => self.title_numbered(config).unwrap_or_else(full),
+            });
         }
 
-        if let Some(title) = self.title() {
-            s.push_str(&format!(": {title}"));
+        if !self.header.name {
+            return self.title().or(self.number()).map(|s| s.to_owned());
+        }
+        // Figures and tables are referred to by number only, like `Figure 3.2`
+        if matches!(
+            config.kind(self.env()),
+            EnvironmentKind::Figure | EnvironmentKind::Table
+        ) && let Some(numbered) = self.title_numbered(config)
+        {
+            return Some(numbered);
         }
 
-        s
+        Some(self.title_full(config))
     }
-    #[inline]
-    pub fn title_auto(&self, config: &Config) -> Option<String> {
-        if self.hide_name {
-            return self.title().map(|s| s.to_owned());
+    /// Parts of the header that are shown, `None` if the header is hidden
+    pub fn header_parts<'b>(&'b self, config: &'b Config) -> Option<HeaderParts<'b>> {
+        if !self.header.show {
+            return None;
         }
 
-        Some(self.title_full(config))
+        Some(HeaderParts {
+            name: self.header.name.then(|| config.name(self.env())),
+            number: self.header_number().filter(|_| self.header.number),
+            title: self.title().filter(|_| self.header.title),
+            // Linked to the proven blox along with the refs
+            of: self.proof_of(),
+        })
+    }
+    /// Header text without its hidden parts, `None` if nothing is shown
+    pub fn header_text(&self, config: &Config) -> Option<String> {
+        self.header_parts(config)?
+            .compose(|n| BloxRender::number_html(config, self, n))
     }
     #[inline]
     pub fn footer(&self) -> Option<&str> {
         self.footer.as_deref()
     }
+    /// Footer text with the "See also" line, `None` if nothing is shown.
+    ///
+    /// Related blox are given as ref placeholders, replaced along with the other refs.
+    pub fn footer_text(&self) -> Option<String> {
+        if self.hide_footer || self.statement_only {
+            return None;
+        }
+
+        let see_also = (!self.see_also.is_empty()).then(|| {
+            let refs: Vec<String> = self
+                .see_also
+                .iter()
+                .map(|label| format!("{{{{blox-nref: {label}}}}}"))
+                .collect();
+            format!("See also: {}", refs.join(", "))
+        });
+
+        let solution_of = self
+            .solution_of()
+            .map(|label| format!("Solution of {{{{blox-ofref: {label}}}}}"));
+        // Replaced by links to the proofs along with the refs
+        let proof = self
+            .label()
+            .filter(|_| self.proof_elsewhere)
+            .map(|label| format!("{{{{blox-proof: {label}}}}}"));
+
+        let qed = self.qed.then(|| "∎".to_string());
+
+        let parts: Vec<String> = [
+            self.footer().map(str::to_string),
+            solution_of,
+            see_also,
+            proof,
+            qed,
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        (!parts.is_empty()).then(|| parts.join("\n\n"))
+    }
+    #[inline]
+    pub fn see_also(&self) -> &[String] {
+        &self.see_also
+    }
+    #[inline]
+    pub fn class(&self) -> Option<&str> {
+        self.class.as_deref()
+    }
+    #[inline]
+    pub fn lang(&self) -> Option<&str> {
+        self.lang.as_deref()
+    }
+    #[inline]
+    pub fn set(&self) -> Option<&str> {
+        self.set.as_deref()
+    }
+    /// Answers of the `{{ blank: answer }}` blanks of the content
+    pub fn answers(&self) -> Vec<&str> {
+        blanks(&self.content).into_iter().map(|(_, a)| a).collect()
+    }
+    #[inline]
+    pub fn word_count(&self) -> usize {
+        self.content.split_whitespace().count()
+    }
+    #[inline]
+    pub fn header_right(&self) -> Option<&str> {
+        self.header_right.as_deref()
+    }
     #[inline]
     pub fn label(&self) -> Option<&str> {
         self.label.as_deref()
     }
     #[inline]
+    pub fn points(&self) -> Option<f64> {
+        self.points
+    }
+    #[inline]
+    pub fn solution_of(&self) -> Option<&str> {
+        self.solution_of.as_deref()
+    }
+    #[inline]
+    pub fn proof_of(&self) -> Option<&str> {
+        self.proof_of.as_deref()
+    }
+    /// Stable hash of the environment and content, independent of options
+    #[inline]
+    pub fn content_hash(&self) -> u64 {
+        fnv1a(&[self.env().as_bytes(), b"\0", self.content.trim().as_bytes()])
+    }
+    #[inline]
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+    #[inline]
+    pub fn attrs(&self) -> &BTreeMap<String, String> {
+        &self.attrs
+    }
+    #[inline]
+    pub fn hints(&self) -> &[Hint] {
+        &self.hints
+    }
+    #[inline]
+    pub fn part(&self) -> Option<&str> {
+        self.part.as_deref()
+    }
+    #[inline]
+    pub fn updated(&self) -> Option<&Datetime> {
+        self.updated.as_ref()
+    }
+    #[inline]
     pub fn path(&self) -> Option<&PathBuf> {
         self.path.as_ref()
     }
     #[inline]
-    pub fn rel_path(&self, base: &PathBuf) -> Option<String> {
+    pub fn rel_path(&self, base: &PathBuf, config: &Config) -> Option<String> {
         let path = self.path()?;
 
         if path == base {
@@ -174,13 +510,11 @@ impl<'a> Blox<'a> {
 
         let mut base = base.clone();
         base.pop();
-        Some(
-            diff_paths(path, base)
-                .unwrap()
-                .into_os_string()
-                .into_string()
-                .unwrap(),
-        )
+        let rel_path = diff_paths(path, base)?;
+        match config.source_links() {
+            true => Some(source_target(&rel_path)),
+            false => Some(html_target(&rel_path, config.pretty_urls)),
+        }
     }
     #[inline]
     pub fn defer_rendering(&self) -> bool {
@@ -190,28 +524,68 @@ impl<'a> Blox<'a> {
     pub fn number(&self) -> Option<&str> {
         self.number.as_deref()
     }
+    /// Number shown in the header, within the set of the blox if it is in one
     #[inline]
-    pub fn set_number(&mut self, number: usize, section_number: Option<&str>) -> bool {
+    pub fn header_number(&self) -> Option<&str> {
+        let number = self.number()?;
+        let in_set = self
+            .set()
+            .and_then(|set| number.strip_prefix(set)?.strip_prefix('.'));
+        Some(in_set.unwrap_or(number))
+    }
+    #[inline]
+    pub fn set_number(
+        &mut self,
+        number: usize,
+        section_number: Option<&str>,
+        style: NumberStyle,
+    ) -> bool {
         if self.number.is_none() {
             return false;
         }
 
-        let mut s = number.to_string();
+        let mut s = style.format(number);
 
         if let Some(sn) = section_number {
             s.insert_str(0, sn);
         }
 
         self.number = Some(s);
-        return true;
+        true
     }
-    // #[inline]
-    // pub fn hide_name(&self) -> bool {
-    //     self.hide_name.clone()
-    // }
     #[inline]
     pub fn hide_header(&self) -> bool {
-        self.hide_header.clone()
+        !self.header.show
+    }
+    #[inline]
+    pub fn hide_footer(&self) -> bool {
+        self.hide_footer
+    }
+    #[inline]
+    pub fn collapsible(&self) -> bool {
+        self.collapsible
+    }
+    #[inline]
+    pub fn qed(&self) -> bool {
+        self.qed
+    }
+    /// Whether a collapsible blox is expanded initially
+    #[inline]
+    pub fn open(&self) -> bool {
+        !self.closed
+    }
+    #[inline]
+    pub fn statement_only(&self) -> bool {
+        self.statement_only
+    }
+    #[inline]
+    pub fn verbatim(&self) -> bool {
+        self.verbatim
+    }
+    /// Whether the blox is listed in exports and feeds
+    #[inline]
+    pub fn in_index(&self) -> bool {
+        !self.no_index
     }
 
     #[inline]
@@ -221,16 +595,39 @@ impl<'a> Blox<'a> {
     #[inline]
     pub fn id_str(&self, config: &Config) -> Option<String> {
         let group = self.group_str(config)?;
-        self.label().map(|label| format!("{group}-{label}"))
+        match (self.label(), config.id_source) {
+            (Some(label), _) => Some(format!("{group}-{}", label.trim_start_matches('.'))),
+            // Glossary terms are referable without a label
+            (None, _) if let Some(term) = self.glossary_term(config) => {
+                Some(format!("{group}-{}", term_key(term)))
+            }
+            (None, IdSource::ContentHash) => {
+                let hash = self.content_hash() >> 32;
+                Some(match self.id_suffix {
+                    Some(n) => format!("{group}-{hash:08x}-{n}"),
+                    None => format!("{group}-{hash:08x}"),
+                })
+            }
+            // Equations are referable without a label
+            (None, IdSource::Label) => self.equation_id.as_ref().map(|id| format!("{group}-{id}")),
+        }
     }
 }
 
+/// A collapsible hint, e.g. `hints = [{ title = "Hint 1", content = "..." }]`
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Hint {
+    pub title: Option<String>,
+    pub content: String,
+}
+
 #[derive(Deserialize)]
 struct CodeBlockOptionsWrapper {
     options: CodeBlockOptions,
 }
 
-#[derive(Default, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Default, Deserialize, Debug, PartialEq)]
 struct CodeBlockOptions {
     /// A custom title
     #[serde(default)]
@@ -238,37 +635,523 @@ struct CodeBlockOptions {
     /// A custom footer
     #[serde(default)]
     footer: Option<String>,
+    /// Secondary text at the right of the header, like a difficulty or source
+    #[serde(default)]
+    header_right: Option<String>,
     /// A label(reference)
     #[serde(default)]
     label: Option<String>,
     /// If true, will defer the rendering of this block until explicitly stated
     #[serde(default)]
     defer_rendering: bool,
+    /// Points awarded for an exercise, used by exports
+    #[serde(default)]
+    points: Option<f64>,
+    /// Label of the blox this blox is a solution of
+    #[serde(default)]
+    solution_of: Option<String>,
+    /// Label of the blox this blox proves, e.g. `proof_of = "thm-main"`
+    #[serde(default)]
+    proof_of: Option<String>,
+    /// Labels of related blox, e.g. `see_also = ["thm-a", "ex-4"]`
+    #[serde(default)]
+    see_also: Vec<String>,
+    /// Date of the last significant change, e.g. `updated = 2025-10-01`
+    #[serde(default)]
+    updated: Option<Datetime>,
+    /// Tags, e.g. `tags = ["hard", "midterm"]`
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Language of the content, e.g. `lang = "de"`
+    #[serde(default)]
+    lang: Option<String>,
+    /// Extra CSS classes, separated by spaces
+    #[serde(default)]
+    class: Option<String>,
+    /// Exercise set, like `set = "3.2"`, grouping consecutive blox of the set under a shared
+    /// header and numbering them within it
+    #[serde(default)]
+    set: Option<String>,
+    /// Profile of options from the configuration, applied unless given
+    #[serde(default)]
+    profile: Option<String>,
+    /// Extra HTML attributes, e.g. `attrs = { "data-level" = "2" }`
+    #[serde(default)]
+    attrs: BTreeMap<String, String>,
+    /// Collapsible hints shown below the content
+    #[serde(default)]
+    hints: Vec<Hint>,
+    /// Render only the header, e.g. when listing theorem statements in a summary
+    #[serde(default, alias = "hide_content")]
+    statement_only: bool,
+    /// Show the content as preformatted text, without Markdown processing
+    #[serde(default)]
+    verbatim: bool,
+    /// List the blox in exports and feeds, e.g. `index = false` for trivial warm-ups
+    #[serde(default)]
+    index: Option<bool>,
 
     // Defaultable
+    /// Visibility of the header parts, taking precedence over `hide_header` and `hide_name`
+    #[serde(default)]
+    header: HeaderConfig,
     /// Hiding the environment name (if true, forces numbered to be hidden)
     #[serde(default)]
     hide_name: Option<bool>,
     /// Hide the environment header (if true, forces environment name to be hidden)
     #[serde(default)]
     hide_header: Option<bool>,
+    /// Hide the footer
+    #[serde(default)]
+    hide_footer: Option<bool>,
+    /// Render as a `<details>` element that can be collapsed by clicking the header
+    #[serde(default)]
+    collapsible: Option<bool>,
+    /// Expand a collapsible blox initially
+    #[serde(default)]
+    open: Option<bool>,
     /// If true, it will have a number
     #[serde(default)]
     numbered: Option<bool>,
 }
 
+/// Longest fence header accepted, so an unclosed quote can't run on in error messages
+const MAX_HEADER_LEN: usize = 2000;
+
+/// Environment and options of a fence header `blox env [options]`
+#[derive(Debug, PartialEq)]
+struct HeaderTokens<'h> {
+    env: &'h str,
+    options: Option<&'h str>,
+}
+
+impl<'h> HeaderTokens<'h> {
+    /// Splits a trimmed header, `None` if it isn't a blox header.
+    ///
+    /// Fails with a suggestion on headers like `bloxnote` or `blox note,title = "x"`.
+    fn parse(config: &Config, header: &'h str) -> Result<Option<Self>> {
+        let Some(rest) = header.strip_prefix(CODE_BLOCK_KEYWORD) else {
+            return Ok(None);
+        };
+
+        // Another language like `bloxx`, unless it ends in an environment
+        if !rest.starts_with(char::is_whitespace) {
+            let word = &rest[..env_len(rest)];
+            anyhow::ensure!(
+                word.is_empty() || !config.has_environment(word),
+                "Missing space after `{CODE_BLOCK_KEYWORD}`, did you mean `{CODE_BLOCK_KEYWORD} {rest}`?"
+            );
+            return Ok(None);
+        }
+
+        anyhow::ensure!(
+            header.len() <= MAX_HEADER_LEN,
+            "Blox header is too long ({} bytes, at most {MAX_HEADER_LEN})",
+            header.len()
+        );
+
+        let rest = rest.trim_start();
+        let (env, options) = rest.split_at(env_len(rest));
+        let env = match options.chars().next() {
+            // A separator glued to the environment
+            Some(',' | ';') => env,
+            Some(c) if !c.is_whitespace() => rest.split_whitespace().next().unwrap_or(rest),
+            _ => env,
+        };
+
+        anyhow::ensure!(!env.is_empty(), "No blox environment specified");
+        anyhow::ensure!(
+            config.has_environment(env),
+            "Blox environment `{env}` not defined in book.toml"
+        );
+
+        if options.starts_with([',', ';']) {
+            let options = options.trim_start_matches([',', ';']).trim_start();
+            anyhow::bail!(
+                "Missing space after blox environment `{env}`, did you mean `{CODE_BLOCK_KEYWORD} {env} {options}`?"
+            );
+        }
+
+        Ok(Some(Self {
+            env,
+            options: Some(options.trim()).filter(|o| !o.is_empty()),
+        }))
+    }
+}
+
+/// Label transliterated to ASCII, keeping the leading `.` of chapter-scoped labels
+fn label_ascii(label: &str) -> String {
+    match label.strip_prefix('.') {
+        Some(local) => format!(".{}", to_toml_ascii(local)),
+        None => to_toml_ascii(label),
+    }
+}
+
+/// Key of a glossary term, which refs to the term use, like `vector-space` for `Vector space`
+pub(crate) fn term_key(term: &str) -> String {
+    let words: Vec<String> = term.split_whitespace().map(str::to_lowercase).collect();
+    to_toml_ascii(&words.join("-"))
+}
+
+/// Length of the environment key starting `s`
+fn env_len(s: &str) -> usize {
+    s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(s.len())
+}
+
 impl CodeBlockOptions {
+    #[cfg(test)]
     fn from_string(options: &str) -> Result<Self> {
-        let inline_toml = format!("options = {{ {options} }}");
-        let cb_opts: CodeBlockOptions =
-            toml::from_str::<CodeBlockOptionsWrapper>(inline_toml.as_str())
-                .with_context(|| format!("Failed to parse blox options: {options}"))?
-                .options;
+        Self::from_header(options, options, &HashMap::new())
+    }
+
+    /// Parses `options`, which must be a suffix of `header`, applying their profile
+    fn from_header(
+        header: &str,
+        options: &str,
+        profiles: &HashMap<String, Profile>,
+    ) -> Result<Self> {
+        let (options_str, mut wrapper) = match parse_inline_table::<toml::Value>(options) {
+            Ok(wrapper) => (options.to_string(), wrapper),
+            // Retry with text pasted from word processors cleaned up
+            Err(_) => {
+                let normalized = normalize_options(options);
+                let wrapper = parse_inline_table(&normalized)
+                    .map_err(|e| {
+                        let prefix = header.len() - options.len();
+                        let column = inline_table_column(&normalized, &e).map(|c| {
+                            header[..prefix].chars().count() + c.min(options.chars().count())
+                        });
+                        anyhow::anyhow!(
+                            "{}\n{header}\n{}^",
+                            strip_position(&e),
+                            " ".repeat(column.unwrap_or_default())
+                        )
+                    })
+                    .with_context(|| format!("Failed to parse blox options: {options}"))?;
+
+                log::warn!("Normalized quotes or commas of blox options: {options}");
+                (normalized, wrapper)
+            }
+        };
+
+        // The options as written are checked first, so errors point into the header
+        let text = format!("{INLINE_TABLE_PREFIX}{options_str} }}");
+        let empty = toml::value::Table::new();
+        let table = wrapper
+            .get("options")
+            .and_then(|o| o.as_table())
+            .unwrap_or(&empty);
+        let cb_opts = Self::deserialize(&text, table)
+            .with_context(|| format!("Failed to parse blox options: {options}"))?;
+        let Some(name) = cb_opts.profile.as_deref() else {
+            return Ok(cb_opts);
+        };
+
+        let profile = profiles
+            .get(name)
+            .with_context(|| format!("Unknown blox profile `{name}`"))
+            .with_context(|| format!("Failed to parse blox options: {options}"))?;
+        Self::apply_profile(&mut wrapper, profile);
+        Self::deserialize(&toml::to_string(&wrapper)?, &profile.0)
+            .with_context(|| format!("Invalid blox profile `{name}`"))
+    }
+
+    /// Deserializes options from text, as datetimes don't survive `toml::Value::try_into`,
+    /// blaming the first invalid option of `table` on errors
+    fn deserialize(text: &str, table: &toml::value::Table) -> Result<Self> {
+        match toml::from_str::<CodeBlockOptionsWrapper>(text) {
+            Ok(cb_opts) => Ok(cb_opts.options),
+            Err(e) => {
+                Err(Self::key_error(table)
+                    .unwrap_or_else(|| anyhow::anyhow!("{}", strip_position(&e))))
+            }
+        }
+    }
+
+    /// Adds the options of the profile which aren't given
+    fn apply_profile(wrapper: &mut toml::Value, profile: &Profile) {
+        let Some(options) = wrapper.get_mut("options").and_then(|o| o.as_table_mut()) else {
+            return;
+        };
+        for (key, value) in profile.0.iter() {
+            options.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    }
+
+    /// Finds the first option whose value is invalid on its own
+    fn key_error(options: &toml::value::Table) -> Option<anyhow::Error> {
+        options.iter().find_map(|(key, value)| {
+            let mut single = toml::value::Table::new();
+            single.insert(key.clone(), value.clone());
+            let single = toml::to_string(&single).ok()?;
+            toml::from_str::<CodeBlockOptions>(&single).err().map(|e| {
+                anyhow::anyhow!("Invalid value for option `{key}`: {}", strip_position(&e))
+            })
+        })
+    }
+}
+
+const INLINE_TABLE_PREFIX: &str = "options = { ";
+
+fn parse_inline_table<T: DeserializeOwned>(
+    options: &str,
+) -> std::result::Result<T, toml::de::Error> {
+    toml::from_str(&format!("{INLINE_TABLE_PREFIX}{options} }}"))
+}
 
-        Ok(cb_opts)
+/// Character column of a TOML error within the options
+fn inline_table_column(options: &str, error: &toml::de::Error) -> Option<usize> {
+    let (_, column) = error.line_col()?;
+    let offset = column.saturating_sub(INLINE_TABLE_PREFIX.len());
+    Some(match options.get(..offset) {
+        Some(s) => s.chars().count(),
+        None => options.chars().count(),
+    })
+}
+
+/// Replaces curly quotes by straight ones and drops trailing commas.
+///
+/// Characters are replaced one by one, so columns stay the same.
+fn normalize_options(options: &str) -> String {
+    options
+        .chars()
+        .map(|c| match c {
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => '"',
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => '\'',
+            c => c,
+        })
+        .collect::<String>()
+        .trim_end()
+        .trim_end_matches(',')
+        .to_string()
+}
+
+/// Removes the position of a TOML error, which refers to the wrapped options
+fn strip_position(error: &toml::de::Error) -> String {
+    let message = error.to_string();
+    match message.split_once(" at line ") {
+        Some((message, _)) => message.to_string(),
+        None => message,
+    }
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is stable across builds
+pub(crate) fn fnv1a(parts: &[&[u8]]) -> u64 {
+    parts
+        .iter()
+        .flat_map(|p| p.iter())
+        .fold(0xcbf29ce484222325, |hash, b| {
+            (hash ^ u64::from(*b)).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// A closing fence with options, which CommonMark reads as a line of content
+#[derive(Debug, PartialEq)]
+pub(crate) struct ClosingOptions<'s> {
+    /// Offset of the closing fence line in the fenced block
+    pub start: usize,
+    /// Offset of the end of the closing fence line, before the newline
+    pub end: usize,
+    pub options: &'s str,
+}
+
+/// Finds the first line closing the fenced block `source` with options, like
+/// ```` ``` footer = "Adapted from Rudin" ````
+pub(crate) fn closing_options(source: &str) -> Option<ClosingOptions<'_>> {
+    let fence_character = source.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let fence_length = source.len() - source.trim_start_matches(fence_character).len();
+    let mut start = source.find('\n')? + 1;
+
+    for line in source[start..].split_inclusive('\n') {
+        let end = start + line.len();
+        let fence = line.trim_start_matches(' ');
+        let rest = fence.trim_start_matches(fence_character);
+        let options = rest.trim();
+
+        // Options rather than the info string of an inner fence, like ```` ```rust ````
+        if line.len() - fence.len() < 4
+            && fence.len() - rest.len() >= fence_length
+            && options.contains('=')
+        {
+            return Some(ClosingOptions {
+                start,
+                end: start + line.trim_end_matches(['\n', '\r']).len(),
+                options,
+            });
+        }
+        start = end;
+    }
+
+    None
+}
+
+/// Query of a `{{ blox-list: exercise tags = "midterm" }}` placeholder
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ListQuery {
+    pub environment: String,
+    /// Tags each listed blox has
+    pub tags: Vec<String>,
+    pub scope: ListScope,
+}
+
+/// Chapters a blox list is made from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ListScope {
+    #[default]
+    Book,
+    /// Only the chapter of the placeholder
+    Chapter,
+}
+
+#[derive(Deserialize)]
+struct ListOptionsWrapper {
+    options: ListOptions,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListOptions {
+    /// A tag or tags, e.g. `tags = "midterm"` or `tags = ["hard", "midterm"]`
+    #[serde(default)]
+    tags: Option<toml::Value>,
+    #[serde(default)]
+    scope: ListScope,
+}
+
+impl ListQuery {
+    /// Parses the environment and options of a placeholder, like `exercise tags = "midterm"`
+    pub(crate) fn parse(query: &str) -> Result<Self> {
+        let query = query.trim();
+        let (environment, options) = query.split_at(env_len(query));
+        anyhow::ensure!(!environment.is_empty(), "Missing environment of blox list");
+
+        let options = normalize_options(options);
+        let options = match options.trim().is_empty() {
+            true => ListOptions::default(),
+            false => {
+                parse_inline_table::<ListOptionsWrapper>(&options)
+                    .map_err(|e| {
+                        anyhow::anyhow!("Invalid blox list options: {}", strip_position(&e))
+                    })?
+                    .options
+            }
+        };
+        let tags = match options.tags {
+            None => Vec::new(),
+            Some(toml::Value::String(tag)) => vec![tag],
+            Some(toml::Value::Array(tags)) => tags
+                .into_iter()
+                .map(|t| match t {
+                    toml::Value::String(tag) => Ok(tag),
+                    _ => Err(anyhow::anyhow!("Tags of blox lists must be strings")),
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => anyhow::bail!("Tags of blox lists must be strings"),
+        };
+
+        Ok(Self {
+            environment: environment.to_string(),
+            tags,
+            scope: options.scope,
+        })
+    }
+
+    pub(crate) fn matches(&self, blox: &Blox) -> bool {
+        blox.env() == self.environment && self.tags.iter().all(|tag| blox.tags().contains(tag))
     }
 }
 
+/// Answer blanks like `{{ blank: 42 }}` in content, with their spans
+pub(crate) fn blanks(content: &str) -> Vec<(Range<usize>, &str)> {
+    let mut blanks = Vec::new();
+    let mut start = 0;
+
+    while let Some(open) = content[start..].find("{{").map(|i| start + i) {
+        let Some(close) = content[open..].find("}}").map(|i| open + i) else {
+            break;
+        };
+        if let Some(answer) = content[open + 2..close].trim().strip_prefix("blank:") {
+            blanks.push((open..close + 2, answer.trim()));
+        }
+        start = close + 2;
+    }
+
+    blanks
+}
+
+/// A `:::blox env {options}` … `:::` container, the alternative to a fenced block
+pub(crate) struct Directive<'s> {
+    /// Header like that of a fenced block, without the braces around the options
+    pub header: String,
+    /// Lines between the opening and closing line
+    pub content: &'s str,
+    /// Offset of the end of the closing line, before the newline
+    pub end: usize,
+}
+
+/// Reads the directive starting `source`. Containers opened within it are closed first, and
+/// a directive never closed extends to the end of `source`.
+pub(crate) fn directive(source: &str) -> Option<Directive<'_>> {
+    fn colons(line: &str) -> usize {
+        line.len() - line.trim_start_matches(':').len()
+    }
+
+    let opening_end = source.find('\n').unwrap_or(source.len());
+    let opening = source[..opening_end].trim_end();
+    let n = colons(opening);
+    if n < 3 {
+        return None;
+    }
+    let header = opening[n..].trim();
+    if !header.starts_with(CODE_BLOCK_KEYWORD) {
+        return None;
+    }
+
+    // Braces around the options, not an inline table of an option
+    let header = match header.split_once('{') {
+        Some((head, options)) if !head.contains('=') => match options.strip_suffix('}') {
+            Some(options) => format!("{} {}", head.trim(), options.trim()),
+            None => header.to_string(),
+        },
+        _ => header.to_string(),
+    };
+
+    let mut depth = 1;
+    let mut start = opening_end;
+    while start < source.len() {
+        let line_start = start + 1;
+        let line_end = source[line_start..]
+            .find('\n')
+            .map_or(source.len(), |i| line_start + i);
+        let line = source[line_start..line_end].trim();
+        let n = colons(line);
+
+        if n >= 3 {
+            match line[n..].trim().is_empty() {
+                true => depth -= 1,
+                false => depth += 1,
+            }
+            if depth == 0 {
+                return Some(Directive {
+                    header,
+                    content: &source[opening_end..line_start],
+                    end: line_end,
+                });
+            }
+        }
+        start = line_end;
+    }
+
+    log::warn!("Blox directive is never closed: {opening}");
+    Some(Directive {
+        header,
+        content: &source[opening_end..],
+        end: source.len(),
+    })
+}
+
 fn extract_content<'a>(content: &'a str) -> Result<Cow<'a, str>> {
     let fence_character = content
         .chars()
@@ -293,7 +1176,7 @@ mod test {
     use crate::config::test::default_test_config;
     use pretty_assertions::assert_eq;
 
-    const CONTENT_STR: &'static str = "\nCONTENT\n";
+    const CONTENT_STR: &str = "\nCONTENT\n";
 
     fn check_options(options: &str, expected: Option<Blox>) -> Result<()> {
         let block_content = format!(r#"```{options}{CONTENT_STR}```"#);
@@ -340,12 +1223,366 @@ mod test {
             }),
         )?;
 
+        check_options(
+            "blox alert hide_content = true, hide_footer = true",
+            Some({
+                let mut blox = Blox::new("alert");
+                blox.content = Cow::Borrowed(CONTENT_STR);
+                blox.statement_only = true;
+                blox.hide_footer = true;
+                blox
+            }),
+        )?;
+
         check_options("bloxx alert", None)?;
         check_options("block alert", None)?;
 
         Ok(())
     }
 
+    #[test]
+    fn test_header_tokens() {
+        let config = default_test_config();
+        let tokens = |header| HeaderTokens::parse(&config, header).map_err(|e| e.to_string());
+
+        assert_eq!(
+            tokens(r#"blox  alert	title = "x""#),
+            Ok(Some(HeaderTokens {
+                env: "alert",
+                options: Some(r#"title = "x""#)
+            }))
+        );
+        assert_eq!(
+            tokens("blox alert"),
+            Ok(Some(HeaderTokens {
+                env: "alert",
+                options: None
+            }))
+        );
+        assert_eq!(tokens("blox"), Ok(None));
+        assert_eq!(tokens("bloxx"), Ok(None));
+        assert_eq!(
+            tokens("bloxalert"),
+            Err("Missing space after `blox`, did you mean `blox alert`?".to_string())
+        );
+        assert_eq!(
+            tokens(r#"blox alert,title="x""#),
+            Err(r#"Missing space after blox environment `alert`, did you mean `blox alert title="x"`?"#.to_string())
+        );
+        assert_eq!(
+            tokens("blox alert.x"),
+            Err("Blox environment `alert.x` not defined in book.toml".to_string())
+        );
+        assert!(
+            tokens(&format!(
+                "blox alert title = \"{}",
+                "x".repeat(MAX_HEADER_LEN)
+            ))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_closing_options() -> Result<()> {
+        let config = default_test_config();
+        let source =
+            "```blox alert title = \"Limit\"\nContent\n``` footer = \"Adapted\"\nMore\n```";
+        assert_eq!(
+            closing_options(source),
+            Some(ClosingOptions {
+                start: 38,
+                end: 60,
+                options: r#"footer = "Adapted""#
+            })
+        );
+
+        let blox = Blox::parse(&config, source, r#"blox alert title = "Limit""#)?.unwrap();
+        assert_eq!(blox.content, "\nContent\n");
+        assert_eq!(blox.footer(), Some("Adapted"));
+        let source = "```blox alert\nContent\n``` footer = \"Adapted\"\n```";
+        let blox = Blox::parse(&config, source, "blox alert")?.unwrap();
+        assert_eq!(blox.footer(), Some("Adapted"));
+
+        assert_eq!(
+            closing_options("````blox alert\n```rust\nx\n```\n````"),
+            None
+        );
+        assert_eq!(closing_options("```blox alert\n    ``` a = 1\n```"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directive() -> Result<()> {
+        let config = default_test_config();
+        let source = ":::blox alert {title = \"Limit\", attrs = { a = \"b\" }}\nOuter\n\n::: blox exercise\nInner\n:::\n:::\nAfter";
+        let d = directive(source).unwrap();
+        assert_eq!(
+            d.header,
+            r#"blox alert title = "Limit", attrs = { a = "b" }"#
+        );
+        assert_eq!(d.content, "\nOuter\n\n::: blox exercise\nInner\n:::\n");
+        assert_eq!(&source[..d.end], &source[..source.len() - "\nAfter".len()]);
+
+        let blox = Blox::from_directive(&config, &d)?.unwrap();
+        assert_eq!(blox.env(), "alert");
+        assert_eq!(blox.title(), Some("Limit"));
+        assert_eq!(blox.attrs().get("a").map(String::as_str), Some("b"));
+
+        let d = directive("::: blox alert attrs = { a = \"b\" }\nUnclosed").unwrap();
+        assert_eq!(d.header, r#"blox alert attrs = { a = "b" }"#);
+        assert_eq!(d.content, "\nUnclosed");
+        assert!(directive(":: blox alert\n::").is_none());
+        assert!(directive("::: note\n:::").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let mut a = Blox::new("alert");
+        a.content = Cow::Borrowed("\nCONTENT\n");
+        let mut b = Blox::new("alert");
+        b.content = Cow::Borrowed("CONTENT");
+        b.title = Some("Title".to_string());
+
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert_eq!(fnv1a(&[b"a"]), 0xaf63dc4c8601ec8c);
+
+        b.environment = "quote".to_string();
+        assert_ne!(a.content_hash(), b.content_hash());
+    }
+
+    #[test]
+    fn test_serialize() -> Result<()> {
+        let config = default_test_config();
+        let source = String::from("```\nContent\n```");
+        let blox = Blox::parse(
+            &config,
+            &source,
+            "blox exercise label = \"x\", updated = 2025-10-01, tags = [\"hard\"]",
+        )?
+        .unwrap();
+
+        let json = serde_json::to_string(&blox)?;
+        assert!(json.contains(r#""updated":"2025-10-01""#));
+        assert!(!json.contains("outline_level"));
+
+        let owned: BloxOwned = blox.clone().into_owned();
+        drop(source);
+        let parsed: BloxOwned = serde_json::from_str(&json)?;
+        assert_eq!(parsed, owned);
+        assert_eq!(parsed.content, owned.content);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_structured_options() -> Result<()> {
+        let options = CodeBlockOptions::from_string(r#"updated = 2025-10-01, points = 2"#)?;
+        assert_eq!(
+            options.updated.map(|d| d.to_string()).as_deref(),
+            Some("2025-10-01")
+        );
+        assert_eq!(options.points, Some(2.0));
+
+        let options = CodeBlockOptions::from_string(
+            r#"tags = ["a", "b"], attrs = { lang = "de" }, hints = [{ content = "Try 1" }, { title = "More", content = "Try 2" }]"#,
+        )?;
+        assert_eq!(options.tags, vec!["a", "b"]);
+        assert_eq!(options.attrs.get("lang").map(|s| s.as_str()), Some("de"));
+        assert_eq!(
+            options.hints,
+            vec![
+                Hint {
+                    title: None,
+                    content: "Try 1".to_string()
+                },
+                Hint {
+                    title: Some("More".to_string()),
+                    content: "Try 2".to_string()
+                }
+            ]
+        );
+
+        let error = |o: &str| format!("{:#}", CodeBlockOptions::from_string(o).unwrap_err());
+        assert!(error(r#"title = "t", tags = "a""#).contains("Invalid value for option `tags`"));
+        assert!(
+            error(r#"hints = [{ text = "x" }]"#).contains("option `hints`: unknown field `text`")
+        );
+        assert_eq!(
+            error(r#"tags = ["#),
+            "Failed to parse blox options: tags = [: expected a value, found a right brace\ntags = [\n        ^"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalized_options() -> Result<()> {
+        let options = CodeBlockOptions::from_string(
+            "title = \u{201C}Euler\u{2019}s theorem\u{201D}, label = \u{2018}euler\u{2019},",
+        )?;
+        assert_eq!(options.title.as_deref(), Some("Euler's theorem"));
+        assert_eq!(options.label.as_deref(), Some("euler"));
+
+        // Curly quotes within straight quotes are kept
+        let options = CodeBlockOptions::from_string("title = \"\u{201C}Quoted\u{201D}\"")?;
+        assert_eq!(options.title.as_deref(), Some("\u{201C}Quoted\u{201D}"));
+
+        let header = "blox alert title = \u{201C}T\u{201D} label = \"l\"";
+        let error =
+            CodeBlockOptions::from_header(header, &header[11..], &HashMap::new()).unwrap_err();
+        assert!(
+            format!("{error:#}").ends_with(&format!("\n{header}\n{}^", " ".repeat(23))),
+            "{error:#}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_text() -> Result<()> {
+        let config = default_test_config();
+
+        for i in 0..16 {
+            let (show, name, number, title) = (i & 1 != 0, i & 2 != 0, i & 4 != 0, i & 8 != 0);
+            let header = format!(
+                r#"blox exercise title = "T", header = {{ show = {show}, name = {name}, number = {number}, title = {title} }}"#
+            );
+            let mut blox = Blox::parse(&config, "```\n```", &header)?.unwrap();
+            blox.set_number(1, None, NumberStyle::Arabic);
+
+            let mut parts = Vec::new();
+            if name {
+                parts.push("Exercise");
+            }
+            // Nameless blox are not numbered
+            if name && number {
+                parts.push("1");
+            }
+            let head = parts.join(" ");
+            let expected = match (show, head.is_empty(), title) {
+                (false, _, _) | (true, true, false) => None,
+                (true, true, true) => Some("T".to_string()),
+                (true, false, false) => Some(head),
+                (true, false, true) => Some(format!("{head}: T")),
+            };
+
+            assert_eq!(blox.header_text(&config), expected, "{header}");
+        }
+
+        // The header table takes precedence over the shorthands
+        let blox = Blox::parse(
+            &config,
+            "```\n```",
+            "blox alert hide_header = true, header = { show = true }",
+        )?
+        .unwrap();
+        assert_eq!(blox.header_text(&config).as_deref(), Some("Alert"));
+        let blox =
+            Blox::parse(&config, "```\n```", "blox quote header = { name = true }")?.unwrap();
+        assert_eq!(blox.header_text(&config).as_deref(), Some("Quote"));
+
+        // The number links to the blox
+        let mut config = config;
+        config.link_number = true;
+        let mut blox = Blox::parse(&config, "```\n```", "blox exercise label = \"x\"")?.unwrap();
+        blox.number = Some("2.3".to_string());
+        assert_eq!(
+            blox.header_text(&config).as_deref(),
+            Some(r##"Exercise <a class="blox-number" href="#blox-exercise-x">2.3</a>"##)
+        );
+
+        // The number is in an element of its own, with the full number within a set
+        config.number_spans = true;
+        assert_eq!(
+            blox.header_text(&config).as_deref(),
+            Some(
+                r##"Exercise <a class="blox-number" href="#blox-exercise-x" data-number="2.3">2.3</a>"##
+            )
+        );
+        config.link_number = false;
+        blox.set = Some("2".to_string());
+        blox.title = Some("Sum".to_string());
+        assert_eq!(
+            blox.header_text(&config).as_deref(),
+            Some(r#"Exercise <span class="blox-number" data-number="2.3">3</span>: Sum"#)
+        );
+        assert_eq!(blox.title_full(&config), "Exercise 2.3: Sum");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nameless_numbering() -> Result<()> {
+        let config_with = |mode: &str| -> Result<Config> {
+            Ok(toml::from_str(&format!(
+                r#"
+environments.exercise = {{ name = "Exercise" }}
+defaults.nameless_numbering = "{mode}"
+"#
+            ))?)
+        };
+        let parse = |config: &Config| -> Result<Blox> {
+            let mut blox = Blox::parse(
+                config,
+                "```\n```",
+                "blox exercise header = { name = false }",
+            )?
+            .unwrap();
+            blox.set_number(4, Some("2."), NumberStyle::Arabic);
+            Ok(blox)
+        };
+
+        let config = config_with("off")?;
+        let blox = parse(&config)?;
+        assert_eq!(blox.number(), None);
+        assert_eq!(blox.header_text(&config), None);
+
+        let config = config_with("header")?;
+        let blox = parse(&config)?;
+        assert_eq!(blox.header_text(&config).as_deref(), Some("2.4"));
+
+        let config = config_with("refs")?;
+        let blox = parse(&config)?;
+        assert_eq!(blox.number(), Some("2.4"));
+        assert_eq!(blox.header_text(&config), None);
+        assert_eq!(blox.title_auto(&config).as_deref(), Some("2.4"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ref_text_when_hidden() -> Result<()> {
+        for (mode, titled, untitled) in [
+            ("full", "Exercise 3: T", "Exercise 3"),
+            ("title", "T", "Exercise 3"),
+            ("number", "Exercise 3", "Exercise 3"),
+        ] {
+            let config: Config = toml::from_str(&format!(
+                r#"
+environments.exercise = {{ name = "Exercise", nameless_numbering = "refs" }}
+defaults.ref_text_when_hidden = "{mode}"
+"#
+            ))?;
+
+            let mut blox =
+                Blox::parse(&config, "```\n```", "blox exercise hide_header = true")?.unwrap();
+            blox.set_number(3, None, NumberStyle::Arabic);
+            assert_eq!(
+                blox.title_auto(&config).as_deref(),
+                Some(untitled),
+                "{mode}"
+            );
+            blox.title = Some("T".to_string());
+            assert_eq!(blox.title_auto(&config).as_deref(), Some(titled), "{mode}");
+            assert_eq!(blox.header_text(&config), None);
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_method() -> Result<()> {
         let config = default_test_config();
@@ -355,9 +1592,51 @@ mod test {
 
         assert_eq!(blox.title_auto(&config).as_deref(), Some("Alert: Title"));
 
-        blox.hide_name = true;
+        blox.header.name = false;
         assert_eq!(blox.title_auto(&config).as_deref(), Some("Title"));
 
+        let mut config = config;
+        assert_eq!(blox.id_str(&config), None);
+        config.id_source = IdSource::ContentHash;
+        assert_eq!(blox.id_str(&config).as_deref(), Some("blox-alert-4f4c884a"));
+        blox.label = Some("label".to_string());
+        assert_eq!(blox.id_str(&config).as_deref(), Some("blox-alert-label"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile() -> Result<()> {
+        let mut config = default_test_config();
+        let profile: toml::value::Table =
+            toml::from_str("hide_header = true\nnumbered = false\nclass = \"exam\"")?;
+        config.profiles.insert("exam".to_string(), Profile(profile));
+
+        let blox = Blox::from_header(&config, r#"blox alert profile = "exam""#)?.unwrap();
+        assert!(blox.hide_header());
+        assert_eq!(blox.number(), None);
+        assert_eq!(blox.class(), Some("exam"));
+
+        // Options of the block take precedence
+        let blox =
+            Blox::from_header(&config, r#"blox alert profile = "exam", class = "quiz""#)?.unwrap();
+        assert_eq!(blox.class(), Some("quiz"));
+
+        let err = Blox::from_header(&config, r#"blox alert profile = "test""#).unwrap_err();
+        assert!(format!("{err:#}").contains("Unknown blox profile `test`"));
+
+        // Errors are blamed on the block or on the profile
+        let err =
+            Blox::from_header(&config, r#"blox alert profile = "exam", points = "x""#).unwrap_err();
+        assert!(format!("{err:#}").contains("Invalid value for option `points`"));
+        let profile: toml::value::Table = toml::from_str("points = \"x\"")?;
+        config.profiles.insert("bad".to_string(), Profile(profile));
+        let err = Blox::from_header(&config, r#"blox alert profile = "bad""#).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Invalid blox profile `bad`: Invalid value for option `points`: invalid type: string \"x\", expected f64 for key `points`"
+        );
+
         Ok(())
     }
 }
@@ -0,0 +1,388 @@
+use crate::config::{Config, PREPROCESSOR_NAME};
+use crate::manifest::{read_fragment_manifest, write_fragment_manifest};
+use crate::mdbook_api::{
+    self, Book, BookItem, Chapter, MDBOOK_VERSION, Preprocessor, PreprocessorContext, book_items,
+};
+use crate::process::{GeneratedChapter, SourceChapter};
+use crate::session::Session;
+use anyhow::Result;
+use semver::{Version, VersionReq};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// The mdBook preprocessor rendering the blox of a book.
+#[derive(Default)]
+pub struct BloxPreProcessor {
+    /// Session of the previous run, reused while the configuration is unchanged
+    session: Mutex<Option<Arc<Session>>>,
+}
+
+impl BloxPreProcessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the output of the renderer can be preprocessed
+    pub fn supports(&self, renderer: &str) -> bool {
+        renderer != "not-supported"
+    }
+
+    fn session(&self, config: Config) -> Result<Arc<Session>> {
+        let mut cached = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(session) = cached.as_ref().filter(|s| *s.config() == config) {
+            return Ok(session.clone());
+        }
+
+        let session = Arc::new(Session::new(config)?);
+        *cached = Some(session.clone());
+        Ok(session)
+    }
+}
+
+impl Preprocessor for BloxPreProcessor {
+    fn name(&self) -> &str {
+        PREPROCESSOR_NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let session = self.session(Config::from_context(ctx)?)?;
+        let processed = process_book(&book, &session)?;
+        if session.config().dry_run {
+            return Ok(book);
+        }
+        let contents = &mut processed.contents.into_iter();
+        set_chapter_contents(mdbook_api::book_items_mut(&mut book), contents);
+        for chapter in processed.new_chapters {
+            mdbook_api::push_chapter(
+                &mut book,
+                Chapter::new(&chapter.name, chapter.content, chapter.path, Vec::new()),
+            );
+        }
+
+        Ok(book)
+    }
+
+    #[cfg(all(feature = "mdbook-0_4", not(feature = "mdbook-0_5")))]
+    fn supports_renderer(&self, renderer: &str) -> bool {
+        self.supports(renderer)
+    }
+
+    #[cfg(feature = "mdbook-0_5")]
+    fn supports_renderer(&self, renderer: &str) -> Result<bool> {
+        Ok(self.supports(renderer))
+    }
+}
+
+/// Runs the preprocessor on the book mdBook passes on stdin, writing the processed book to
+/// stdout
+pub fn run_stdio() -> Result<()> {
+    let (ctx, book) = mdbook_api::parse_input(io::stdin())?;
+
+    let book_version = Version::parse(mdbook_api::mdbook_version(&ctx))?;
+    let version_req = VersionReq::parse(MDBOOK_VERSION)?;
+
+    if !version_req.matches(&book_version) {
+        eprintln!(
+            "Warning: The {} plugin was built against version {} of mdbook,\
+             but we're being called from version {}",
+            PREPROCESSOR_NAME,
+            MDBOOK_VERSION,
+            mdbook_api::mdbook_version(&ctx)
+        );
+    }
+
+    let processed_book = BloxPreProcessor::new().run(&ctx, book)?;
+    serde_json::to_writer(io::stdout(), &processed_book)?;
+
+    Ok(())
+}
+
+/// Runs the preprocessor on the JSON mdBook passes to preprocessors, `[context, book]`,
+/// returning the processed book as JSON, e.g. to smoke-test a book configuration.
+pub fn run_on_json(input: &str) -> Result<String> {
+    let (ctx, book) = mdbook_api::parse_input(input.as_bytes())?;
+    let processed = BloxPreProcessor::new().run(&ctx, book)?;
+    Ok(serde_json::to_string(&processed)?)
+}
+
+/// Sets the content of the chapters in `items` and their sub-chapters, in the order of
+/// `source_chapters`
+pub fn set_chapter_contents(items: &mut [BookItem], contents: &mut impl Iterator<Item = String>) {
+    for item in items {
+        if let BookItem::Chapter(chapter) = item {
+            if let Some(content) = contents.next() {
+                chapter.content = content;
+            }
+            set_chapter_contents(&mut chapter.sub_items, contents);
+        }
+    }
+}
+
+/// Chapters of the book, each followed by its sub-chapters, tracking the part each chapter
+/// belongs to. The position of a chapter is its section id.
+pub fn source_chapters(book: &Book) -> Vec<SourceChapter<'_>> {
+    let mut part: Option<&str> = None;
+    let mut chapters = Vec::new();
+
+    for item in book_items(book) {
+        match item {
+            BookItem::PartTitle(title) => part = Some(title),
+            BookItem::Chapter(chapter) => push_chapter(chapter, part, &mut chapters),
+            BookItem::Separator => {}
+        }
+    }
+
+    chapters
+}
+
+fn push_chapter<'b>(
+    chapter: &'b Chapter,
+    part: Option<&'b str>,
+    chapters: &mut Vec<SourceChapter<'b>>,
+) {
+    chapters.push(SourceChapter {
+        content: &chapter.content,
+        path: chapter.path.clone(),
+        number: chapter.number.as_ref().map(|n| n.to_string()),
+        name: Some(&chapter.name),
+        part,
+    });
+
+    for item in chapter.sub_items.iter() {
+        if let BookItem::Chapter(sub_chapter) = item {
+            push_chapter(sub_chapter, part, chapters);
+        }
+    }
+}
+
+/// Output of processing a book
+#[derive(Debug, Clone, Default)]
+pub struct ProcessedBook {
+    /// New content of each chapter, in the order of `source_chapters`
+    pub contents: Vec<String>,
+    /// Generated chapters without a chapter of the same path in the book
+    pub new_chapters: Vec<GeneratedChapter>,
+}
+
+/// Renders the blox of the book and the configured lists. Reads and writes the manifests
+/// configured in `config`, only reading them in a dry run.
+pub fn process_book(book: &Book, session: &Session) -> Result<ProcessedBook> {
+    let config = session.config();
+    let chapters = source_chapters(book);
+    let mut processor = session.processor();
+    processor.parse(&chapters)?;
+
+    if let Some(file) = config.refs_fallback.as_deref() {
+        processor.add_fallback(read_fragment_manifest(&config.book_path(file))?);
+    }
+
+    let mut processed = ProcessedBook {
+        contents: processor.render(&chapters)?,
+        new_chapters: Vec::new(),
+    };
+    for list in processor.list_chapters(&chapters) {
+        match chapters
+            .iter()
+            .position(|c| c.path.as_ref() == Some(&list.path))
+        {
+            Some(i) => processed.contents[i] = list.content,
+            None => processed.new_chapters.push(list),
+        }
+    }
+
+    if config.dry_run {
+        let blox: usize = (0..chapters.len())
+            .map(|i| processor.chapter_blox(i).len())
+            .sum();
+        log::info!(
+            "Dry run: {blox} blox in {} chapters processed, book left unchanged",
+            chapters.len()
+        );
+        return Ok(processed);
+    }
+
+    if let Some(file) = config.fragment_manifest.as_deref() {
+        let entries = processor.fragment_entries(&chapters);
+        write_fragment_manifest(&config.book_path(file), &entries)?;
+    }
+
+    Ok(processed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ListConfig;
+    use crate::config::test::default_test_config;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    fn chapter(name: &str, content: &str, number: Vec<u32>, sub_items: Vec<BookItem>) -> BookItem {
+        let mut chapter = Chapter::new(name, content.to_string(), format!("{name}.md"), Vec::new());
+        chapter.number = Some(mdbook_api::section_number(number));
+        chapter.sub_items = sub_items;
+        BookItem::Chapter(chapter)
+    }
+
+    fn new_book(items: Vec<BookItem>) -> Book {
+        let mut book = Book::new();
+        *mdbook_api::book_items_mut(&mut book) = items;
+        book
+    }
+
+    #[test]
+    fn test_sub_chapters() -> Result<()> {
+        let exercise =
+            |label: &str| format!("```blox exercise label = \"{label}\"\nContent\n```\n");
+        let mut book = new_book(vec![
+            chapter(
+                "a",
+                &exercise("a"),
+                vec![1],
+                vec![chapter(
+                    "b",
+                    &exercise("b"),
+                    vec![1, 1],
+                    vec![chapter(
+                        "c",
+                        "See {{ blox-nref: a }}.\n",
+                        vec![1, 1, 1],
+                        Vec::new(),
+                    )],
+                )],
+            ),
+            BookItem::PartTitle("Part".to_string()),
+            chapter("d", &exercise("d"), vec![2], Vec::new()),
+        ]);
+
+        let chapters = source_chapters(&book);
+        let paths: Vec<_> = chapters.iter().map(|c| c.path.clone().unwrap()).collect();
+        assert_eq!(paths, ["a.md", "b.md", "c.md", "d.md"].map(PathBuf::from));
+        assert_eq!(chapters[3].part, Some("Part"));
+
+        let session = Session::new(default_test_config())?;
+        let new_content = process_book(&book, &session)?.contents;
+        set_chapter_contents(
+            mdbook_api::book_items_mut(&mut book),
+            &mut new_content.into_iter(),
+        );
+
+        let BookItem::Chapter(a) = &book_items(&book)[0] else {
+            unreachable!()
+        };
+        let BookItem::Chapter(b) = &a.sub_items[0] else {
+            unreachable!()
+        };
+        let BookItem::Chapter(c) = &b.sub_items[0] else {
+            unreachable!()
+        };
+        assert!(b.content.contains(r#"<div id="blox-exercise-b""#));
+        assert!(b.content.contains("Exercise 1.1.1"));
+        assert_eq!(c.content, "See [Exercise 1.1](a.html#blox-exercise-a).\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lists() -> Result<()> {
+        let mut config = default_test_config();
+        config.lists.insert(
+            "exercise".to_string(),
+            ListConfig {
+                path: PathBuf::from("lists/exercises.md"),
+                ..Default::default()
+            },
+        );
+        config.lists.insert(
+            "alerts".to_string(),
+            ListConfig {
+                path: PathBuf::from("z.md"),
+                title: Some("Warnings".to_string()),
+                environments: vec!["alert".to_string()],
+            },
+        );
+
+        let book = new_book(vec![
+            chapter(
+                "a",
+                "```blox exercise label = \"sum\", title = \"Sum\"\nA\n```\n\n```blox exercise index = false\nB\n```\n\n```blox alert\nC\n```\n",
+                vec![1],
+                Vec::new(),
+            ),
+            chapter("z", "", vec![2], Vec::new()),
+        ]);
+        let processed = process_book(&book, &Session::new(config)?)?;
+
+        assert_eq!(processed.contents[1], "# Warnings\n\n- [Alert](a.html)\n");
+        assert_eq!(
+            processed.new_chapters,
+            [GeneratedChapter {
+                name: "List of Exercises".to_string(),
+                path: PathBuf::from("lists/exercises.md"),
+                content:
+                    "# List of Exercises\n\n- [Exercise 1.1: Sum](../a.html#blox-exercise-sum)\n"
+                        .to_string(),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_on_json() -> Result<()> {
+        #[cfg(all(feature = "mdbook-0_4", not(feature = "mdbook-0_5")))]
+        let input = include_str!("../fixtures/preprocessor-input.json");
+        #[cfg(feature = "mdbook-0_5")]
+        let input = include_str!("../fixtures/preprocessor-input-0_5.json");
+        let output = run_on_json(input)?;
+        let book: Book = serde_json::from_str(&output)?;
+
+        let BookItem::Chapter(chapter) = &book_items(&book)[0] else {
+            unreachable!()
+        };
+        let BookItem::Chapter(section) = &chapter.sub_items[0] else {
+            unreachable!()
+        };
+        assert!(
+            chapter
+                .content
+                .contains(r#"<div id="blox-exercise-sum" class="blox blox-exercise">"#)
+        );
+        assert!(chapter.content.contains("Exercise 1.1: Sum"));
+        assert!(chapter.content.contains(r#"<div class="blox blox-alert">"#));
+        assert_eq!(
+            section.content,
+            "# Section 1.1\n\nSee [Exercise 1.1](../chapter_1.html#blox-exercise-sum).\n"
+        );
+
+        assert!(run_on_json("[]").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let manifest = dir.path().join("manifest.json");
+        let mut config = default_test_config();
+        config.dry_run = true;
+        config.fragment_manifest = Some(manifest.clone());
+
+        let book = new_book(vec![chapter(
+            "a",
+            "```blox alert\nContent\n```\n",
+            vec![1],
+            Vec::new(),
+        )]);
+        let new_content = process_book(&book, &Session::new(config)?)?.contents;
+        assert!(new_content[0].contains("blox-alert"));
+        assert!(!manifest.exists());
+
+        assert!(crate::config::is_enabled("1"));
+        assert!(crate::config::is_enabled("yes"));
+        assert!(!crate::config::is_enabled("0"));
+        assert!(!crate::config::is_enabled(" False "));
+
+        Ok(())
+    }
+}
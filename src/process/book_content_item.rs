@@ -1,14 +1,19 @@
-use crate::config::Config;
-use crate::parse::Blox;
+use crate::config::{CODE_BLOCK_KEYWORD, Config, OutputFormat};
+use crate::parse::{Blox, fnv1a};
 use crate::render::BloxRender;
 use std::borrow::Cow;
 use std::collections::HashMap;
 
-#[derive(Debug)]
+/// Start of the placeholder of a nested blox in the content of the blox containing it
+const NESTED_MARKER: &str = "<!-- blox-nested: ";
+
+#[derive(Debug, Clone)]
 pub enum BookContentItem<'a> {
     AnonymousBlox(usize),
     LabelledBlox(String),
     Other(Cow<'a, str>),
+    /// Blox rendered within the content of another blox, listed for numbering only
+    Nested(Box<BookContentItem<'a>>),
 }
 
 impl<'a> BookContentItem<'a> {
@@ -23,12 +28,29 @@ impl<'a> BookContentItem<'a> {
             return None;
         }
 
-        Some(Self::Other(Cow::Borrowed(&content)))
+        Some(Self::Other(Cow::Borrowed(content)))
     }
     pub fn new_other_empty() -> Self {
         Self::Other(Cow::default())
     }
 
+    /// The item of a nested blox, or the item itself
+    pub fn inner(&self) -> &Self {
+        match self {
+            Self::Nested(item) => item,
+            item => item,
+        }
+    }
+
+    /// Placeholder of a nested blox in the content of the blox containing it
+    pub fn nested_marker(&self) -> String {
+        match self {
+            Self::AnonymousBlox(id) => format!("{NESTED_MARKER}#{id} -->"),
+            Self::LabelledBlox(label) => format!("{NESTED_MARKER}{label} -->"),
+            Self::Other(_) | Self::Nested(_) => String::new(),
+        }
+    }
+
     pub fn to_html(
         &self,
         config: &Config,
@@ -39,15 +61,50 @@ impl<'a> BookContentItem<'a> {
             Self::AnonymousBlox(id) => {
                 let s: Cow<'a, str> = anon_list
                     .get(*id)
-                    .map(|b| Cow::Owned(BloxRender::html(config, b)))
+                    .map(|b| Cow::Owned(render_blox(config, b, anon_list, label_list)))
                     .unwrap_or_default();
                 s
             }
             Self::LabelledBlox(label) => label_list
                 .get(label)
-                .map(|b| Cow::Owned(BloxRender::html(config, b)))
+                .map(|b| Cow::Owned(render_blox(config, b, anon_list, label_list)))
                 .unwrap_or_default(),
             Self::Other(content) => content.clone(),
+            // Rendered in place of its marker
+            Self::Nested(_) => Cow::default(),
         }
     }
 }
+
+/// Renders a blox, with the blox nested in it in place of their markers
+pub fn render_blox(
+    config: &Config,
+    blox: &Blox,
+    anon_list: &Vec<Blox>,
+    label_list: &HashMap<String, Blox>,
+) -> String {
+    let mut html = BloxRender::render(config, blox);
+    let mut cursor = 0;
+
+    while let Some(start) = html[cursor..].find(NESTED_MARKER).map(|i| cursor + i) {
+        let Some(len) = html[start..].find(" -->") else {
+            break;
+        };
+        let key = &html[start + NESTED_MARKER.len()..start + len];
+        let item = match key.strip_prefix('#').and_then(|id| id.parse().ok()) {
+            Some(id) => BookContentItem::AnonymousBlox(id),
+            None => BookContentItem::LabelledBlox(key.to_string()),
+        };
+
+        let nested = item.to_html(config, anon_list, label_list);
+        html.replace_range(start..start + len + " -->".len(), &nested);
+        cursor = start + nested.len();
+    }
+
+    if config.fingerprint && config.format() == OutputFormat::Html {
+        let hash = fnv1a(&[html.as_bytes()]) >> 32;
+        html.insert_str(0, &format!("<!-- {CODE_BLOCK_KEYWORD}:{hash:08x} -->"));
+    }
+
+    html
+}
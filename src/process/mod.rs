@@ -1,116 +1,574 @@
 mod book_content_item;
 mod number_map;
 
-use crate::config::Config;
-use crate::parse::Blox;
-use anyhow::{Context, Result};
+use crate::config::{
+    CODE_BLOCK_KEYWORD, Config, EnvironmentKind, OutputFormat, to_toml_ascii, transliterate,
+};
+use crate::css::{self, BloxCss};
+use crate::link::html_target;
+use crate::manifest::FragmentEntry;
+use crate::parse::{Blox, Directive, ListQuery, ListScope, closing_options, directive, term_key};
+use crate::render::BloxRender;
+use anyhow::{Context, Result, bail};
 use book_content_item::BookContentItem;
-use mdbook::book::{Book, BookItem, Chapter};
 use number_map::NumberMap;
-use pulldown_cmark::{CodeBlockKind::*, Event, Parser, Tag};
+use pulldown_cmark::{BlockQuoteKind, CodeBlockKind::*, Event, HeadingLevel, Parser, Tag};
 use regex::{Captures, Regex};
-use std::{collections::HashMap, ops::Range};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
-pub fn book_filter_iter(book: &Book) -> impl Iterator<Item = (usize, &Chapter)> {
-    book.sections
-        .iter()
-        .enumerate()
-        .filter_map(|(sec_id, item)| match item {
-            BookItem::Chapter(chapter) => Some((sec_id, chapter)),
-            _ => None,
-        })
+/// Compiled patterns of blox placeholders
+#[derive(Debug, Clone)]
+pub struct Patterns {
+    render: Regex,
+    refs: Regex,
+    /// `<!-- blox begin env [options] -->`
+    begin: Regex,
+    /// `<!-- blox: env [options] -->`
+    open: Regex,
+    /// `<!-- /blox -->`
+    close: Regex,
+    /// `\label{eq:name}` of a LaTeX equation
+    equation: Regex,
+    /// `{{ blox-answers }}`, replaced by the answer key
+    answers: Regex,
+    /// `{{ blox-solutions }}`, replaced by the solutions with `collect_solutions`
+    solutions: Regex,
+    /// `{{ blox-glossary }}`, replaced by the terms of the glossary blox with links to them
+    glossary: Regex,
+    /// `{{ blox-list: env tags = "midterm" }}`, replaced by a list of links to matching blox
+    list: Regex,
+    /// `{{ blox-proof: label }}`, replaced by links to the proofs of a blox in other chapters
+    proof: Regex,
 }
 
-pub fn book_filter_iter_mut(book: &mut Book) -> impl Iterator<Item = (usize, &mut Chapter)> {
-    book.sections
-        .iter_mut()
-        .enumerate()
-        .filter_map(|(sec_id, item)| match item {
-            BookItem::Chapter(chapter) => Some((sec_id, chapter)),
-            _ => None,
+impl Patterns {
+    pub fn new() -> Result<Self> {
+        let render_pattern = r#"\{\{[[:space:]]*blox-render:[[:space:]]*(?P<label>\.?[[:alnum:]_-]+)[[:space:]]*\}\}"#;
+        // Can match "ref" here with, say, "tref" or similar, if multiple ref types is wanted
+        let begin_pattern =
+            r#"^<!--[[:space:]]*blox[[:space:]]+begin[[:space:]]+(?P<header>.*?)[[:space:]]*-->$"#;
+        let open_pattern = r#"^<!--[[:space:]]*blox:[[:space:]]*(?P<header>.*?)[[:space:]]*-->$"#;
+        let close_pattern = r#"^<!--[[:space:]]*/blox[[:space:]]*-->$"#;
+        let refs_pattern = r#"\{\{[[:space:]]*blox-(?P<ref>[ltnfTN]?ref|ofref|eqref):[[:space:]]*(?P<label>[^[:space:]{}]+)[[:space:]]*\}\}"#;
+        let equation_pattern = r#"\\label\{[[:space:]]*(?P<label>[\w:-]+)[[:space:]]*\}"#;
+        let answers_pattern = r#"\{\{[[:space:]]*blox-answers[[:space:]]*\}\}"#;
+        let solutions_pattern = r#"\{\{[[:space:]]*blox-solutions[[:space:]]*\}\}"#;
+        let glossary_pattern = r#"\{\{[[:space:]]*blox-glossary[[:space:]]*\}\}"#;
+        let list_pattern = r#"\{\{[[:space:]]*blox-list:(?P<query>[^{}]*)\}\}"#;
+        let proof_pattern =
+            r#"\{\{[[:space:]]*blox-proof:[[:space:]]*(?P<label>[^[:space:]{}]+)[[:space:]]*\}\}"#;
+
+        Ok(Self {
+            render: Regex::new(render_pattern).context("Could not create regex")?,
+            refs: Regex::new(refs_pattern).context("Could not create regex")?,
+            begin: Regex::new(begin_pattern).context("Could not create regex")?,
+            open: Regex::new(open_pattern).context("Could not create regex")?,
+            close: Regex::new(close_pattern).context("Could not create regex")?,
+            equation: Regex::new(equation_pattern).context("Could not create regex")?,
+            answers: Regex::new(answers_pattern).context("Could not create regex")?,
+            solutions: Regex::new(solutions_pattern).context("Could not create regex")?,
+            glossary: Regex::new(glossary_pattern).context("Could not create regex")?,
+            list: Regex::new(list_pattern).context("Could not create regex")?,
+            proof: Regex::new(proof_pattern).context("Could not create regex")?,
         })
+    }
+}
+
+/// A blox capturing the content after its opening marker
+struct Capture {
+    /// Span of the opening marker
+    span: Range<usize>,
+    header: String,
+    end: CaptureEnd,
+    /// Level of the section the marker is in
+    section_level: Option<HeadingLevel>,
+    /// Position among the blox of the chapter, from 1
+    ordinal: usize,
+}
+
+#[derive(PartialEq, Eq)]
+enum CaptureEnd {
+    /// The next heading of the same or a higher level than the one the marker is under
+    Heading(Option<HeadingLevel>),
+    /// A closing `<!-- /blox -->` marker
+    Marker,
+}
+
+impl Capture {
+    #[inline]
+    fn ends_at(&self, level: HeadingLevel) -> bool {
+        match self.end {
+            CaptureEnd::Heading(l) => l.is_none_or(|l| level <= l),
+            CaptureEnd::Marker => false,
+        }
+    }
+
+    /// The blox of the content up to `end`
+    fn close<'a>(self, config: &Config, chapter: &'a str, end: usize) -> Result<Blox<'a>> {
+        let first_line = chapter[self.span.clone()]
+            .lines()
+            .next()
+            .unwrap_or_default();
+        let mut blox = Blox::from_header(config, &self.header)
+            .and_then(|b| b.with_context(|| format!("Invalid blox marker: {}", self.header)))
+            .with_context(|| blox_context(self.ordinal, first_line))?;
+        blox.content = Cow::Borrowed(chapter[self.span.end..end].trim_end());
+        blox.outline_level = outline_level(self.section_level);
+
+        Ok(blox)
+    }
+}
+
+/// A chapter as input to the processor, independent of mdBook types
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceChapter<'a> {
+    pub content: &'a str,
+    /// Path relative to the book source directory
+    pub path: Option<PathBuf>,
+    /// Section number, like `1.2.`
+    pub number: Option<String>,
+    /// Title of the book part the chapter is in
+    pub part: Option<&'a str>,
+    /// Title of the chapter
+    pub name: Option<&'a str>,
+}
+
+/// A broken cross-reference, with the chapter it is in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefProblem {
+    /// Ref to a label no blox defines
+    UnknownLabel { label: String, location: String },
+    /// Label defined by more than one blox
+    DuplicateLabel { label: String, location: String },
+    /// Ref showing the number of a blox without number
+    Unnumbered { label: String, location: String },
+}
+
+impl fmt::Display for RefProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownLabel { label, location } => {
+                write!(f, "{location}: unknown label `{label}`")
+            }
+            Self::DuplicateLabel { label, location } => {
+                write!(f, "{location}: duplicate label `{label}`")
+            }
+            Self::Unnumbered { label, location } => {
+                write!(f, "{location}: ref to unnumbered blox `{label}`")
+            }
+        }
+    }
+}
+
+/// A chapter generated by the processor, like a list of theorems
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedChapter {
+    pub name: String,
+    /// Path relative to the book source directory
+    pub path: PathBuf,
+    pub content: String,
 }
 
 pub struct BloxProcessor<'a> {
     config: &'a Config,
+    patterns: Cow<'a, Patterns>,
     anonymous_blox: Vec<Blox<'a>>,
     labelled_blox: HashMap<String, Blox<'a>>,
     section_items: HashMap<usize, Vec<BookContentItem<'a>>>,
+    /// Fragment ids of rendered blox, per chapter path
+    fragments: HashMap<PathBuf, HashSet<String>>,
+    /// Blox from `refs_fallback`, with their link relative to the book root
+    fallback_blox: HashMap<String, (Blox<'a>, String)>,
+    /// Blox containing each labelled equation, if `equation_labels`
+    equations: HashMap<String, BookContentItem<'a>>,
+    /// Parse errors of the section being processed, by offset in the chapter
+    errors: Vec<(usize, anyhow::Error)>,
+    /// Path of the section being processed, namespacing its `.local` labels
+    section_path: Option<PathBuf>,
+    /// Labels defined more than once, with the path of the chapter redefining them
+    duplicate_labels: Vec<(String, Option<PathBuf>)>,
+    /// Number of `##` headings so far in the section being processed
+    section_heading: usize,
+    /// Level of the heading of the current section of the chapter being processed
+    section_level: Option<HeadingLevel>,
+    /// Classes of the part and directory scopes of the section being processed
+    section_part: Option<String>,
+    /// Proofs rendered in another chapter than the blox they prove, as the environment name,
+    /// chapter title and link relative to the book root, by label of the proven blox
+    proof_notes: HashMap<String, Vec<(String, String, String)>>,
+    /// Path of the chapter the solutions are rendered in, with `collect_solutions`
+    solutions_path: Option<PathBuf>,
+    /// Blox of the `glossary` kind defining each term, in book order, by key of the term
+    glossary: BTreeMap<String, Vec<BookContentItem<'a>>>,
 }
 
 impl<'a> BloxProcessor<'a> {
-    fn new(config: &'a Config) -> Self {
+    pub fn new(config: &'a Config) -> Result<Self> {
+        Ok(Self::with_patterns(config, Cow::Owned(Patterns::new()?)))
+    }
+
+    pub fn with_patterns(config: &'a Config, patterns: Cow<'a, Patterns>) -> Self {
         Self {
             config,
+            patterns,
             anonymous_blox: Vec::new(),
             labelled_blox: HashMap::new(),
             section_items: HashMap::new(),
+            fragments: HashMap::new(),
+            fallback_blox: HashMap::new(),
+            equations: HashMap::new(),
+            errors: Vec::new(),
+            section_path: None,
+            duplicate_labels: Vec::new(),
+            section_heading: 0,
+            section_level: None,
+            section_part: None,
+            proof_notes: HashMap::new(),
+            solutions_path: None,
+            glossary: BTreeMap::new(),
+        }
+    }
+
+    /// Renders the blox of all chapters, returning the new content of each chapter
+    pub fn process(chapters: &[SourceChapter<'a>], config: &'a Config) -> Result<Vec<String>> {
+        let mut processor = Self::new(config)?;
+        processor.parse(chapters)?;
+        processor.render(chapters)
+    }
+
+    /// Parses and numbers all blox of the book, in rendering order.
+    ///
+    /// Deferred blox which are never rendered are appended last, ordered by label.
+    pub fn collect(chapters: &[SourceChapter<'a>], config: &'a Config) -> Result<Vec<Blox<'a>>> {
+        let mut processor = Self::new(config)?;
+        processor.parse(chapters)?;
+
+        let mut anonymous: Vec<Option<Blox>> =
+            processor.anonymous_blox.into_iter().map(Some).collect();
+        let mut labelled = processor.labelled_blox;
+        let mut blox_list = Vec::new();
+
+        for section_id in 0..chapters.len() {
+            let Some(items) = processor.section_items.get(&section_id) else {
+                continue;
+            };
+
+            for item in items {
+                let blox = match item.inner() {
+                    BookContentItem::AnonymousBlox(id) => {
+                        anonymous.get_mut(*id).and_then(|b| b.take())
+                    }
+                    BookContentItem::LabelledBlox(label) => labelled.remove(label),
+                    _ => None,
+                };
+                blox_list.extend(blox);
+            }
         }
+
+        let mut deferred: Vec<Blox> = labelled.into_values().collect();
+        deferred.sort_by(|a, b| a.label().cmp(&b.label()));
+        blox_list.append(&mut deferred);
+
+        Ok(blox_list)
     }
 
-    pub fn process(book: &mut Book, config: &'a Config) -> Result<HashMap<usize, String>> {
-        let mut processor = Self::new(config);
-        for (sec_id, chapter) in book_filter_iter(book) {
-            processor.process_section(sec_id, &chapter.content)?;
+    /// Parses and numbers the blox of all chapters.
+    ///
+    /// Blox which fail to parse are reported together, with their locations.
+    pub fn parse(&mut self, chapters: &[SourceChapter<'a>]) -> Result<()> {
+        let mut errors = Vec::new();
+        for (section_id, chapter) in chapters.iter().enumerate() {
+            self.section_path = chapter.path.clone();
+            self.process_section(section_id, chapter.content, chapter.part)
+                .with_context(|| format!("Failed to process {}", location(chapter, section_id)))?;
+            errors.extend(
+                self.errors
+                    .drain(..)
+                    .map(|(offset, err)| parse_error(chapter, section_id, offset, &err)),
+            );
+        }
+
+        if !errors.is_empty() {
+            bail!(
+                "{} blox could not be parsed:\n{}",
+                errors.len(),
+                errors.join("\n")
+            );
+        }
+
+        self.number_items(chapters)?;
+        if self.config.reciprocal_see_also {
+            self.add_reciprocal_see_also();
         }
+        self.add_proof_notes(chapters);
+        self.add_glossary(chapters);
+        self.add_navigation(chapters);
+        self.lint_titles();
+
+        Ok(())
+    }
 
-        processor.number_items(book)?;
+    /// Returns the new content of each parsed chapter
+    pub fn render(&self, chapters: &[SourceChapter<'a>]) -> Result<Vec<String>> {
+        let mut new_content = Vec::with_capacity(chapters.len());
+        // Labels referenced but defined nowhere, with the chapters referencing them
+        let mut unbound: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut placeholders = PlaceholderNumbers::default();
 
-        let mut new_content: HashMap<usize, String> = HashMap::new();
+        for (section_id, chapter) in chapters.iter().enumerate() {
+            let mut content_string = self.stringify_section(section_id)?;
+            if self.patterns.answers.is_match(&content_string) {
+                let key = self.answer_key(chapters, chapter);
+                content_string = self
+                    .patterns
+                    .answers
+                    .replace_all(&content_string, regex::NoExpand(&key))
+                    .into_owned();
+            }
+            if self.patterns.list.is_match(&content_string) {
+                content_string = self
+                    .patterns
+                    .list
+                    .replace_all(&content_string, |caps: &Captures| {
+                        match ListQuery::parse(&caps["query"]) {
+                            Ok(query) => self.blox_list(chapters, section_id, &query),
+                            Err(err) => {
+                                log::warn!("{err:#}");
+                                format!("**[??blox-list:{}??]**", &caps["query"])
+                            }
+                        }
+                    })
+                    .into_owned();
+            }
+            if self.patterns.glossary.is_match(&content_string) {
+                let glossary = self.glossary_list(chapter);
+                content_string = self
+                    .patterns
+                    .glossary
+                    .replace_all(&content_string, regex::NoExpand(&glossary))
+                    .into_owned();
+            }
+            let lists_solutions =
+                self.solutions_path.is_some() && self.patterns.solutions.is_match(&content_string);
+            if lists_solutions {
+                let solutions = self.collected_solutions(chapters);
+                content_string = self
+                    .patterns
+                    .solutions
+                    .replace_all(&content_string, regex::NoExpand(&solutions))
+                    .into_owned();
+            }
+            // Would shift the lines of the chapter
+            let critical_css = self.config.inline_critical_css
+                && !self.config.preserve_lines
+                && self.config.format() == OutputFormat::Html;
+            if critical_css && !self.chapter_blox(section_id).is_empty() {
+                content_string.insert_str(0, &BloxCss::critical_css());
+            }
+            // At the end, not to shift the lines of the chapter
+            if self.config.chapter_css && self.config.format() == OutputFormat::Html {
+                let envs = self.rendered_envs(chapters.len(), section_id, lists_solutions);
+                content_string.push_str(&css::chapter_css(self.config, envs)?);
+            }
+            if self.config.ref_placeholders {
+                for label in self.unbound_labels(&content_string, chapter.path.as_deref()) {
+                    unbound
+                        .entry(label.to_string())
+                        .or_default()
+                        .insert(location(chapter, section_id));
+                }
+                content_string =
+                    self.number_placeholders(&content_string, chapter, &mut placeholders);
+            }
+            new_content.push(self.replace_refs(content_string, chapter)?);
+        }
 
-        for (sec_id, chapter) in book_filter_iter(book) {
-            let content_string = processor.stringify_section(sec_id)?;
-            let content_string = processor.replace_refs(content_string, chapter)?;
-            new_content.insert(sec_id, content_string);
+        if let Some(report) = unbound_report(&unbound) {
+            log::warn!("{report}");
         }
 
         Ok(new_content)
     }
 
-    fn process_section(&mut self, section_id: usize, chapter: &'a str) -> Result<()> {
-        let cmark_opts = pulldown_cmark::Options::empty();
+    fn process_section(
+        &mut self,
+        section_id: usize,
+        chapter: &'a str,
+        part: Option<&str>,
+    ) -> Result<()> {
+        // Part and directory scoped defaults only apply while parsing
+        let path = self.section_path.clone();
+        let scoped_config = self.config.scoped_to(part, path.as_deref());
+        let config = scoped_config.as_ref().unwrap_or(self.config);
+        self.section_part = self.config.scope_classes(part, path.as_deref());
+
+        let mut cmark_opts = pulldown_cmark::Options::empty();
+        if self.config.github_alerts {
+            cmark_opts.insert(pulldown_cmark::Options::ENABLE_GFM);
+        }
         // opts.insert(Options::ENABLE_TABLES);
         // opts.insert(Options::ENABLE_FOOTNOTES);
         // opts.insert(Options::ENABLE_STRIKETHROUGH);
         // opts.insert(Options::ENABLE_TASKLISTS);
 
         let mut items: Vec<(Range<usize>, BookContentItem)> = Vec::new();
-        let events = Parser::new_ext(&chapter, cmark_opts);
+        let mut events = Parser::new_ext(chapter, cmark_opts).into_offset_iter();
+        // Offset the events are parsed from, moved past blox closed by a fence with options
+        let mut base = 0;
 
-        for (event, span) in events.into_offset_iter() {
-            if let Event::Start(Tag::CodeBlock(Fenced(header))) = event.clone() {
-                // If so, check if it is a blox-block
-                let Some(blox) = Blox::parse(self.config, &chapter[span.clone()], header.as_ref())?
-                else {
-                    // Otherwise, store the content and move on
-                    if let Some(bc) = BookContentItem::new_other(&chapter[span.clone()]) {
-                        items.push((span, bc));
+        // Open comment-delimited blox, and the level of the current section
+        let mut capture: Option<Capture> = None;
+        self.section_level = None;
+        // Number of blox so far, for errors
+        let mut ordinal = 0;
+        self.section_heading = 0;
+
+        while let Some((event, span)) = events.next() {
+            let span = base + span.start..base + span.end;
+            match event {
+                Event::Start(Tag::Heading { level, .. }) => {
+                    if let Some(c) = capture.take_if(|c| c.ends_at(level)) {
+                        let end = span.start..span.start;
+                        self.add_capture(c, config, chapter, end, &mut items);
                     }
-                    continue;
-                };
+                    self.section_level = Some(level);
+                    if level == HeadingLevel::H2 {
+                        self.section_heading += 1;
+                    }
+                }
+                Event::Html(html) => {
+                    let html = html.trim();
+
+                    if self.patterns.close.is_match(html) {
+                        match capture.take_if(|c| c.end == CaptureEnd::Marker) {
+                            Some(c) => self.add_capture(c, config, chapter, span, &mut items),
+                            None => log::warn!("Closing blox marker without opening marker"),
+                        }
+                        continue;
+                    }
+
+                    let (caps, end) = match self.patterns.begin.captures(html) {
+                        Some(caps) => (caps, CaptureEnd::Heading(self.section_level)),
+                        None => match self.patterns.open.captures(html) {
+                            Some(caps) => (caps, CaptureEnd::Marker),
+                            None => continue,
+                        },
+                    };
+                    let header = format!("{CODE_BLOCK_KEYWORD} {}", &caps["header"]);
+
+                    match capture.take() {
+                        Some(c) if c.end == CaptureEnd::Marker => {
+                            log::warn!("Nested blox markers are not supported: {header}");
+                            capture = Some(c);
+                            continue;
+                        }
+                        Some(c) => {
+                            let end = span.start..span.start;
+                            self.add_capture(c, config, chapter, end, &mut items);
+                        }
+                        None => {}
+                    }
+
+                    ordinal += 1;
+                    capture = Some(Capture {
+                        span: span.clone(),
+                        header,
+                        end,
+                        section_level: self.section_level,
+                        ordinal,
+                    });
+                }
+                // A directive is read as a paragraph, up to its first blank line
+                Event::Start(Tag::Paragraph) if capture.is_none() && config.directives => {
+                    let Some((start, directive)) = paragraph_directive(chapter, span) else {
+                        continue;
+                    };
+                    let Some(blox) = Blox::from_directive(config, &directive).transpose() else {
+                        continue;
+                    };
+                    let first_line = chapter[start..].lines().next().unwrap_or_default();
 
-                // Store labelled and anonymous blox separately
-                if let Some(label) = blox.label.clone() {
-                    // Deferred blox is not pushed
-                    if !blox.defer_rendering() {
-                        let content = BookContentItem::new_labelled(&label);
-                        items.push((span, content));
-                    } else {
-                        items.push((span, BookContentItem::new_other_empty()));
+                    // The content is parsed again for nested blox only
+                    let span = start..start + directive.end;
+                    base = span.end;
+                    events = Parser::new_ext(&chapter[base..], cmark_opts).into_offset_iter();
+
+                    ordinal += 1;
+                    let context = blox_context(ordinal, first_line);
+                    self.add_parsed(config, chapter, blox, context, span, &mut items);
+                }
+                // GitHub alerts of configured environments, like `> [!NOTE]`
+                Event::Start(Tag::BlockQuote(Some(kind))) if capture.is_none() => {
+                    let env = alert_environment(kind);
+                    if !config.environments.contains_key(env) {
+                        continue;
                     }
 
-                    self.labelled_blox.insert(label, blox);
-                } else {
-                    let content = BookContentItem::new_anonymous(self.anonymous_blox.len());
-                    items.push((span, content));
-                    self.anonymous_blox.push(blox);
+                    // The blockquote is replaced as a whole, up to the whitespace after it
+                    let span = span.start..span.start + chapter[span.clone()].trim_end().len();
+                    base = span.end;
+                    events = Parser::new_ext(&chapter[base..], cmark_opts).into_offset_iter();
+
+                    ordinal += 1;
+                    let first_line = chapter[span.clone()].lines().next().unwrap_or_default();
+                    let blox = Blox::from_header(config, &format!("{CODE_BLOCK_KEYWORD} {env}"))
+                        .and_then(|b| b.context("Invalid alert environment"))
+                        .map(|blox| Blox {
+                            content: Cow::Owned(alert_content(&chapter[span.clone()])),
+                            ..blox
+                        });
+                    let context = blox_context(ordinal, first_line);
+                    self.add_parsed(config, chapter, blox, context, span, &mut items);
+                }
+                // Fenced blocks within captured content are kept as they are
+                Event::Start(Tag::CodeBlock(Fenced(header))) if capture.is_none() => {
+                    // If so, check if it is a blox-block
+                    let source = &chapter[span.clone()];
+                    // CommonMark reads a closing fence with options as content, so the rest
+                    // of the fenced block is parsed again
+                    let blox = Blox::parse(config, source, header.as_ref());
+                    let closed_at = closing_options(source)
+                        .filter(|_| !matches!(blox, Ok(None)))
+                        .map(|c| span.start + c.end);
+                    let span = match closed_at {
+                        Some(end) => {
+                            base = end;
+                            events =
+                                Parser::new_ext(&chapter[base..], cmark_opts).into_offset_iter();
+                            span.start..end
+                        }
+                        None => span,
+                    };
+                    let Some(blox) = blox.transpose() else {
+                        // Otherwise, store the content and move on
+                        if let Some(bc) = BookContentItem::new_other(&chapter[span.clone()]) {
+                            items.push((span, bc));
+                        }
+                        continue;
+                    };
+
+                    ordinal += 1;
+                    let first_line = source.lines().next().unwrap_or_default();
+                    let context = blox_context(ordinal, first_line);
+                    self.add_parsed(config, chapter, blox, context, span, &mut items);
                 }
+                _ => {}
+            }
+        }
+
+        if let Some(c) = capture.take() {
+            if c.end == CaptureEnd::Marker {
+                log::warn!("Blox marker is never closed: {}", c.header);
             }
+            let end = chapter.len()..chapter.len();
+            self.add_capture(c, config, chapter, end, &mut items);
         }
 
-        let render_regex_pattern =
-            r#"\{\{[[:space:]]*blox-render:[[:space:]]*(?P<label>[[:alnum:]_-]+)[[:space:]]*\}\}"#;
-        let render_regex = Regex::new(render_regex_pattern).unwrap();
+        let render_regex = &self.patterns.render;
         let mut other_items: Vec<(Range<usize>, BookContentItem)> = Vec::new();
         let mut last = 0;
 
@@ -120,7 +578,12 @@ impl<'a> BloxProcessor<'a> {
             BookContentItem::new_other_empty(),
         ));
 
-        for (span, _) in items.iter() {
+        for (span, item) in items.iter() {
+            // Within the blox containing it
+            if matches!(item, BookContentItem::Nested(_)) {
+                continue;
+            }
+
             // Any other type of content might be a deferred blox-block
             for caps in render_regex.captures_iter(&chapter[last..span.start]) {
                 let c_start = caps.get_match().start() + last;
@@ -130,7 +593,8 @@ impl<'a> BloxProcessor<'a> {
 
                 let c_end = caps.get_match().end() + last;
                 if let Some(l) = caps.name("label") {
-                    other_items.push((c_start..c_end, BookContentItem::new_labelled(l.as_str())));
+                    let label = label_key(l.as_str(), self.section_path.as_deref());
+                    other_items.push((c_start..c_end, BookContentItem::new_labelled(&label)));
                 }
 
                 last = c_end;
@@ -144,7 +608,7 @@ impl<'a> BloxProcessor<'a> {
         }
 
         items.append(&mut other_items);
-        items.sort_by(|a, b| a.0.start.cmp(&b.0.start));
+        items.sort_by_key(|a| a.0.start);
 
         let items: Vec<BookContentItem> = items
             .into_iter()
@@ -157,19 +621,221 @@ impl<'a> BloxProcessor<'a> {
         Ok(())
     }
 
-    fn number_items(&mut self, book: &Book) -> Result<()> {
+    /// Stores a blox parsed in the current section with the blox nested in it, or records the
+    /// failure to parse either, in the `context` of the blox
+    fn add_parsed(
+        &mut self,
+        config: &Config,
+        chapter: &'a str,
+        blox: Result<Blox<'a>>,
+        context: String,
+        span: Range<usize>,
+        items: &mut Vec<(Range<usize>, BookContentItem<'a>)>,
+    ) {
+        let parsed = blox.and_then(|mut blox| {
+            blox.outline_level = outline_level(self.section_level);
+            let nested = parse_nested(config, &blox)?;
+            Ok((blox, nested))
+        });
+        match parsed {
+            Ok((blox, nested)) => self.add_blox(blox, nested, span, items),
+            Err(err) => self.parse_failed(chapter, span, err.context(context), items),
+        }
+    }
+
+    /// Stores a parsed blox, and its position in the chapter
+    fn add_blox(
+        &mut self,
+        mut blox: Blox<'a>,
+        nested: Vec<NestedBlox<'a>>,
+        span: Range<usize>,
+        items: &mut Vec<(Range<usize>, BookContentItem<'a>)>,
+    ) {
+        blox.part = self.section_part.clone();
+        blox.heading = self.section_heading;
+        let nested = self.store_nested(&mut blox, nested);
+        let item = self.store_blox(blox);
+        items.push((span.clone(), item));
+
+        // Listed after the blox containing them, for numbering
+        let first = span.start..span.start + 1;
+        items.extend(
+            nested
+                .into_iter()
+                .map(|item| (first.clone(), BookContentItem::Nested(Box::new(item)))),
+        );
+    }
+
+    /// Stores a blox, returning the item rendering it
+    fn store_blox(&mut self, mut blox: Blox<'a>) -> BookContentItem<'a> {
+        if self.config.equation_labels {
+            self.add_equations(&mut blox);
+        }
+
+        // Store labelled and anonymous blox separately
+        if let Some(label) = blox.label() {
+            let label = label_key(label, self.section_path.as_deref()).into_owned();
+            // Deferred blox is not pushed
+            let item = match blox.defer_rendering() {
+                false => BookContentItem::new_labelled(&label),
+                true => BookContentItem::new_other_empty(),
+            };
+
+            if self.labelled_blox.contains_key(&label) {
+                log::warn!("Duplicate blox label: {label}");
+                self.duplicate_labels
+                    .push((label.clone(), self.section_path.clone()));
+            }
+
+            self.labelled_blox.insert(label, blox);
+            item
+        } else {
+            let item = BookContentItem::new_anonymous(self.anonymous_blox.len());
+            self.anonymous_blox.push(blox);
+            item
+        }
+    }
+
+    /// Stores the blox nested in `blox`, replacing their source in its content by markers.
+    /// Returns their items in order of appearance, each followed by those nested in it.
+    fn store_nested(
+        &mut self,
+        blox: &mut Blox<'a>,
+        nested: Vec<NestedBlox<'a>>,
+    ) -> Vec<BookContentItem<'a>> {
+        if nested.is_empty() {
+            return Vec::new();
+        }
+
+        let mut content = String::with_capacity(blox.content.len());
+        let mut items = Vec::new();
+        let mut last = 0;
+
+        for NestedBlox {
+            span,
+            blox: mut inner,
+            nested,
+        } in nested
+        {
+            inner.part = blox.part.clone();
+            inner.heading = blox.heading;
+            let inner_items = self.store_nested(&mut inner, nested);
+            let item = self.store_blox(inner);
+
+            content.push_str(&blox.content[last..span.start]);
+            content.push_str(&format!("\n\n{}\n\n", item.nested_marker()));
+            last = span.end;
+
+            items.push(item);
+            items.extend(inner_items);
+        }
+
+        content.push_str(&blox.content[last..]);
+        blox.content = Cow::Owned(content);
+
+        items
+    }
+
+    /// Registers the labelled equations of a blox about to be stored. Unlabelled blox get an
+    /// id from their first equation label, for refs to link to.
+    fn add_equations(&mut self, blox: &mut Blox<'a>) {
+        let item = match blox.label() {
+            Some(label) => {
+                BookContentItem::new_labelled(&label_key(label, self.section_path.as_deref()))
+            }
+            None => BookContentItem::new_anonymous(self.anonymous_blox.len()),
+        };
+
+        for caps in self.patterns.equation.captures_iter(&blox.content) {
+            // Refs look up transliterated labels
+            let label = transliterate(&caps["label"]);
+            if self.labelled_blox.contains_key(&label) || self.equations.contains_key(&label) {
+                log::warn!("Duplicate blox label: {label}");
+                self.duplicate_labels
+                    .push((label.clone(), self.section_path.clone()));
+            }
+            if blox.label().is_none() && blox.equation_id.is_none() {
+                blox.equation_id = Some(to_toml_ascii(&label.replace(':', "-")));
+            }
+            self.equations.insert(label, item.clone());
+        }
+    }
+
+    /// Stores the blox of a capture, ending with the closing marker at `end`, or before a
+    /// heading if `end` is empty
+    fn add_capture(
+        &mut self,
+        capture: Capture,
+        config: &Config,
+        chapter: &'a str,
+        end: Range<usize>,
+        items: &mut Vec<(Range<usize>, BookContentItem<'a>)>,
+    ) {
+        let start = capture.span.start;
+        // Whitespace after the marker or content is kept, separating the HTML from what follows
+        let span_end = match end.is_empty() {
+            true => start + chapter[start..end.start].trim_end().len(),
+            false => end.start + chapter[end.clone()].trim_end().len(),
+        };
+
+        // Fenced blocks within captured content are kept as they are
+        match capture.close(config, chapter, end.start) {
+            Ok(blox) => self.add_blox(blox, Vec::new(), start..span_end, items),
+            Err(err) => self.parse_failed(chapter, start..span_end, err, items),
+        }
+
+        // Separate the HTML from a following heading
+        if end.is_empty() && span_end < end.start {
+            items.push((span_end..end.start, BookContentItem::Other("\n\n".into())));
+        }
+    }
+
+    /// Records the error of a blox which failed to parse, or renders the blox as an error box
+    /// if lenient
+    fn parse_failed(
+        &mut self,
+        chapter: &'a str,
+        span: Range<usize>,
+        err: anyhow::Error,
+        items: &mut Vec<(Range<usize>, BookContentItem<'a>)>,
+    ) {
+        if !self.config.lenient {
+            self.errors.push((span.start, err));
+            return;
+        }
+
+        log::warn!("Rendering invalid blox as error box: {err:#}");
+        let html = BloxRender::error_html(&chapter[span.clone()], &err);
+        items.push((span, BookContentItem::Other(html.into())));
+    }
+
+    fn number_items(&mut self, chapters: &[SourceChapter<'a>]) -> Result<()> {
         let mut number_map = NumberMap::new(self.config);
+        // Next number within each exercise set
+        let mut set_numbers: HashMap<String, usize> = HashMap::new();
+
+        if self.config.collect_solutions {
+            self.solutions_path = chapters
+                .iter()
+                .find(|c| self.patterns.solutions.is_match(c.content))
+                .and_then(|c| c.path.clone());
+            if self.solutions_path.is_none() {
+                log::warn!("No chapter to collect the solutions in, rendering them in place");
+            }
+        }
 
-        for (section_id, chapter) in book_filter_iter(book) {
-            let chapter_number = chapter.number.as_ref().map(|n| n.to_string());
+        for (section_id, chapter) in chapters.iter().enumerate() {
+            let chapter_number = self.chapter_number(chapter);
 
             let Some(items) = self.section_items.get_mut(&section_id) else {
                 continue;
             };
+            number_map.start_chapter(self.config, chapter.path.as_deref());
 
             // Fix numbering
+            let mut heading = 0;
             for book_content in items.iter_mut() {
-                let Some(blox) = (match book_content {
+                let Some(blox) = (match book_content.inner() {
                     BookContentItem::AnonymousBlox(id) => self.anonymous_blox.get_mut(*id),
                     BookContentItem::LabelledBlox(s) => self.labelled_blox.get_mut(s),
                     _ => None,
@@ -177,15 +843,47 @@ impl<'a> BloxProcessor<'a> {
                     continue;
                 };
 
-                number_map.set_blox(blox, chapter_number.as_deref())?;
-
-                if blox.label().is_some() {
-                    if blox.path().is_some() {
-                        log::warn!("Multiple paths to blox: {}", blox.label().unwrap());
+                let section_number = match (&chapter_number, self.config.number_within_headings) {
+                    (Some(n), true) => {
+                        if blox.heading != heading {
+                            heading = blox.heading;
+                            number_map.reset(self.config);
+                        }
+                        Some(Cow::Owned(format!("{n}{heading}.")))
+                    }
+                    (n, _) => n.as_deref().map(Cow::Borrowed),
+                };
+                match blox.set().map(str::to_string) {
+                    Some(set) => {
+                        let env = blox.env().to_string();
+                        let n = set_numbers
+                            .entry(set.clone())
+                            .or_insert(self.config.counter_start(&env));
+                        let style = self.config.number_style(&env);
+                        if blox.set_number(*n, Some(&format!("{set}.")), style) {
+                            *n += self.config.counter_step(&env);
+                        }
                     }
+                    None => number_map.set_blox(self.config, blox, section_number.as_deref())?,
+                }
 
-                    blox.path = chapter.path.clone();
+                if let Some(label) = blox.label()
+                    && blox.path().is_some()
+                {
+                    log::warn!("Multiple paths to blox: {label}");
                 }
+
+                // Collected solutions are linked to where they are rendered
+                blox.path = match &self.solutions_path {
+                    Some(path)
+                        if blox.solution_of().is_some()
+                            && !matches!(book_content, BookContentItem::Nested(_)) =>
+                    {
+                        Some(path.clone())
+                    }
+                    _ => chapter.path.clone(),
+                };
+                register_fragment(&mut self.fragments, self.config, blox);
             }
 
             number_map.reset(self.config);
@@ -194,96 +892,2157 @@ impl<'a> BloxProcessor<'a> {
         Ok(())
     }
 
-    fn stringify_section(&self, section_id: usize) -> Result<String> {
-        let items = self
-            .section_items
-            .get(&section_id)
-            .context("Section id not found")?;
-        let new_content: String = items
+    /// Adds each labelled blox to the `see_also` of the blox it lists
+    fn add_reciprocal_see_also(&mut self) {
+        let mut reverse: Vec<(String, String)> = self
+            .labelled_blox
             .iter()
-            .map(|item| item.to_html(self.config, &self.anonymous_blox, &self.labelled_blox))
-            .collect::<Vec<_>>()
-            .concat();
+            .flat_map(|(label, blox)| {
+                blox.see_also()
+                    .iter()
+                    .map(|other| label_key(other, blox.path().map(PathBuf::as_path)).into_owned())
+                    .filter(move |other| other != label)
+                    .map(move |other| (other, label.clone()))
+            })
+            .collect();
+        reverse.sort();
 
-        Ok(new_content)
+        for (other, label) in reverse {
+            let Some(blox) = self.labelled_blox.get_mut(&other) else {
+                continue;
+            };
+            if !blox.see_also.contains(&label) {
+                blox.see_also.push(label);
+            }
+        }
     }
 
-    fn replace_refs(&self, content: String, chapter: &Chapter) -> Result<String> {
-        // Can match "ref" here with, say, "tref" or similar, if multiple ref types is wanted
-        let regex_pattern = r#"\{\{[[:space:]]*blox-(?P<ref>[ltnfTN]?ref):[[:space:]]*(?P<label>[[:alnum:]_-]+)[[:space:]]*\}\}"#;
-        let regex = Regex::new(regex_pattern).context("Could not create regex")?;
-
-        let new_content = regex
-            .replace_all(&content, |caps: &Captures| {
-                let Some(label) = caps.name("label").map(|l| l.as_str()) else {
-                    return replace_refs_error("Regex match error", "ref", "error");
-                };
-                let Some(ref_type) = caps.name("ref").map(|r| r.as_str()) else {
-                    return replace_refs_error("Unknown blox ref", "ref", label);
+    /// Collects the terms defined by blox of the `glossary` kind, which are their titles
+    fn add_glossary(&mut self, chapters: &[SourceChapter<'a>]) {
+        let mut glossary: BTreeMap<String, Vec<BookContentItem<'a>>> = BTreeMap::new();
+        for (section_id, chapter) in chapters.iter().enumerate() {
+            for item in self.section_items.get(&section_id).into_iter().flatten() {
+                let Some(blox) = self.item_blox(item) else {
+                    continue;
                 };
+                if self.config.kind(blox.env()) != EnvironmentKind::Glossary {
+                    continue;
+                }
+                match blox.title() {
+                    Some(term) => glossary
+                        .entry(term_key(term))
+                        .or_default()
+                        .push(item.clone()),
+                    None => log::warn!(
+                        "Glossary blox without a title: {}",
+                        chapter.path.as_deref().unwrap_or(Path::new("")).display()
+                    ),
+                }
+            }
+        }
+        self.glossary = glossary;
+    }
 
-                let Some(blox) = self.labelled_blox.get(label) else {
-                    return replace_refs_error("Unknown blox ref", ref_type, label);
-                };
+    /// Notes the proofs rendered in another chapter than the blox they prove, for its footer
+    fn add_proof_notes(&mut self, chapters: &[SourceChapter<'a>]) {
+        let mut notes: HashMap<String, Vec<(String, String, String)>> = HashMap::new();
+        for proof in self
+            .anonymous_blox
+            .iter()
+            .chain(self.labelled_blox.values())
+        {
+            // Deferred blox are only rendered where a placeholder gives them a path
+            let (Some(label), Some(path)) = (proof.proof_of(), proof.path()) else {
+                continue;
+            };
+            let key = label_key(label, Some(path));
+            let Some(proven) = self.labelled_blox.get(key.as_ref()) else {
+                log::warn!("Proof of unknown blox: {label}");
+                continue;
+            };
+            if proven.path() == Some(path) {
+                continue;
+            }
+
+            let title = chapters
+                .iter()
+                .find(|c| c.path.as_ref() == Some(path))
+                .and_then(chapter_title)
+                .unwrap_or_else(|| path.display().to_string());
+            let mut href = html_target(path, self.config.pretty_urls);
+            if let Some(id) = proof.id_str(self.config) {
+                href.push_str(&format!("#{id}"));
+            }
+            notes.entry(key.into_owned()).or_default().push((
+                self.config.name(proof.env()).to_string(),
+                title,
+                href,
+            ));
+        }
 
-                let Some(mut path) = chapter.path.as_ref().and_then(|p| blox.rel_path(p)) else {
-                    return replace_refs_error("Failed to get path to blox", ref_type, label);
+        for (key, list) in notes.iter_mut() {
+            list.sort();
+            if let Some(blox) = self.labelled_blox.get_mut(key) {
+                blox.proof_elsewhere = true;
+            }
+        }
+        self.proof_notes = notes;
+    }
+
+    /// Links each blox of environments with `navigation` to the previous and next one, by
+    /// attributes read by the navigation script
+    fn add_navigation(&mut self, chapters: &[SourceChapter<'a>]) {
+        // Blox in rendering order, with their chapter and link relative to the book root
+        let mut by_env: HashMap<String, Vec<(usize, BookContentItem<'a>, String)>> = HashMap::new();
+        for (section_id, chapter) in chapters.iter().enumerate() {
+            let (Some(items), Some(path)) = (self.section_items.get(&section_id), &chapter.path)
+            else {
+                continue;
+            };
+
+            for item in items {
+                let Some(blox) = self.item_blox(item) else {
+                    continue;
                 };
+                let Some(id) = blox.id_str(self.config) else {
+                    continue;
+                };
+                if !self.config.navigation(blox.env()) {
+                    continue;
+                }
 
-                path.push_str(
-                    &blox
-                        .id_str(self.config)
-                        .map(|s| format!("#{s}"))
-                        .unwrap_or_default(),
-                );
+                let href = format!("{}#{id}", html_target(path, self.config.pretty_urls));
+                by_env.entry(blox.env().to_string()).or_default().push((
+                    section_id,
+                    item.clone(),
+                    href,
+                ));
+            }
+        }
 
-                match ref_type {
-                    // Give title
-                    "Tref" => blox.title().map(|s| s.to_string()).unwrap_or_else(|| {
-                        replace_refs_error("Blox does not have a title", ref_type, label)
-                    }),
-                    // Give number
-                    "Nref" => blox.number().map(|s| s.to_string()).unwrap_or_else(|| {
-                        replace_refs_error("Blox does not have a number", ref_type, label)
-                    }),
-                    // Give link
-                    "lref" => path,
-                    // Provide linked environment-title
-                    "tref" => blox
-                        .title_env(self.config)
-                        .map(|s| markdown_link(&s, &path))
-                        .unwrap_or_else(|| {
-                            replace_refs_error("Blox does not have a title", ref_type, label)
-                        }),
-                    // Provide linked environment-number
-                    "nref" => blox
-                        .title_numbered(self.config)
-                        .map(|s| markdown_link(&s, &path))
-                        .unwrap_or_else(|| {
-                            replace_refs_error("Blox does not have a number", ref_type, label)
-                        }),
-                    // Provide linked environment-number-title
-                    "fref" => markdown_link(&blox.title_full(self.config), &path),
-                    // Provide environment-number, or environment-title if no number
-                    _ => blox
-                        .title_auto(self.config)
-                        .map(|s| markdown_link(&s, &path))
-                        .unwrap_or_else(|| {
-                            replace_refs_error("Blox does not have a title", ref_type, label)
-                        }),
+        for list in by_env.values() {
+            for (i, (section_id, item, _)) in list.iter().enumerate() {
+                let chapter = &chapters[*section_id];
+                let prev = i.checked_sub(1).and_then(|j| list.get(j));
+                let mut attrs = Vec::new();
+                for (name, neighbour) in [("prev", prev), ("next", list.get(i + 1))] {
+                    let Some((_, other, href)) = neighbour else {
+                        continue;
+                    };
+                    let title = self
+                        .item_blox(other)
+                        .map(|b| b.title_full(self.config))
+                        .unwrap_or_default();
+                    attrs.push((format!("data-blox-{name}"), book_link(chapter, href)));
+                    attrs.push((format!("data-blox-{name}-title"), title));
                 }
-            })
-            .to_string();
 
-        Ok(new_content)
+                let blox = match item.inner() {
+                    BookContentItem::AnonymousBlox(id) => self.anonymous_blox.get_mut(*id),
+                    BookContentItem::LabelledBlox(label) => self.labelled_blox.get_mut(label),
+                    _ => None,
+                };
+                if let Some(blox) = blox {
+                    blox.attrs.extend(attrs);
+                }
+            }
+        }
     }
-}
 
-fn replace_refs_error(label: &str, ref_type: &str, err: &str) -> String {
-    log::warn!("{err}: {label}");
-    format!("**[??blox-{ref_type}: {label}??]**")
-}
+    #[inline]
+    fn has_fragment(&self, blox: &Blox) -> bool {
+        let (Some(path), Some(id)) = (blox.path(), blox.id_str(self.config)) else {
+            return false;
+        };
 
-fn markdown_link(text: &str, link: &str) -> String {
-    format!("[{text}]({link})")
+        self.fragments
+            .get(path)
+            .is_some_and(|fragments| fragments.contains(&id))
+    }
+
+    /// Adds blox of a previously written fragment manifest, for refs to blox outside the build
+    pub fn add_fallback(&mut self, entries: Vec<FragmentEntry>) {
+        for entry in entries {
+            let Some(label) = entry.label else {
+                continue;
+            };
+            if !self.config.has_environment(&entry.environment) {
+                log::warn!("Unknown environment of fallback blox: {label}");
+                continue;
+            }
+
+            let blox = Blox {
+                environment: entry.environment,
+                label: Some(label.clone()),
+                number: entry.number,
+                title: entry.title,
+                ..Default::default()
+            };
+            self.fallback_blox.insert(label, (blox, entry.href));
+        }
+    }
+
+    /// Link to a fallback blox, either absolute or relative to the chapter
+    fn fallback_link(&self, chapter: &SourceChapter, href: &str) -> String {
+        if let Some(url) = self.config.refs_fallback_url.as_deref() {
+            return format!("{}/{href}", url.trim_end_matches('/'));
+        }
+
+        book_link(chapter, href)
+    }
+
+    /// Warns about titles occurring more than once in environments with `unique_titles`
+    fn lint_titles(&self) {
+        for warning in self.duplicate_titles() {
+            log::warn!("{warning}");
+        }
+    }
+
+    fn duplicate_titles(&self) -> Vec<String> {
+        let mut titles: HashMap<(&str, String), usize> = HashMap::new();
+
+        for blox in self
+            .anonymous_blox
+            .iter()
+            .chain(self.labelled_blox.values())
+        {
+            let env = blox.env();
+            let Some(title) = blox.title() else {
+                continue;
+            };
+            if !self.config.unique_titles(env)
+                || self
+                    .config
+                    .unique_titles_ignore(env)
+                    .iter()
+                    .any(|t| t.eq_ignore_ascii_case(title.trim()))
+            {
+                continue;
+            }
+
+            *titles
+                .entry((env, title.trim().to_lowercase()))
+                .or_default() += 1;
+        }
+
+        let mut duplicates: Vec<_> = titles.into_iter().filter(|(_, n)| *n > 1).collect();
+        duplicates.sort();
+        duplicates
+            .into_iter()
+            .map(|((env, title), n)| {
+                format!("Title used by {n} blox in environment {env}: {title}")
+            })
+            .collect()
+    }
+
+    #[inline]
+    fn item_blox(&self, item: &BookContentItem) -> Option<&Blox<'a>> {
+        match item.inner() {
+            BookContentItem::AnonymousBlox(id) => self.anonymous_blox.get(*id),
+            BookContentItem::LabelledBlox(label) => self.labelled_blox.get(label),
+            _ => None,
+        }
+    }
+
+    /// Blox labelled `label`, resolving `.local` labels in the chapter at `path` before the
+    /// global label
+    fn ref_blox(&self, label: &str, path: Option<&Path>) -> Option<&Blox<'a>> {
+        if let Some(global) = label.strip_prefix('.') {
+            return self
+                .labelled_blox
+                .get(label_key(label, path).as_ref())
+                .or_else(|| self.ref_blox(global, path));
+        }
+
+        self.labelled_blox
+            .get(label)
+            .or_else(|| self.item_blox(self.equations.get(label)?))
+            .or_else(|| self.item_blox(self.glossary.get(&term_key(label))?.first()?))
+    }
+
+    /// Renders a parsed blox, including the blox nested in it
+    pub fn blox_html(&self, blox: &Blox) -> String {
+        book_content_item::render_blox(self.config, blox, &self.anonymous_blox, &self.labelled_blox)
+    }
+
+    /// Blox rendered in a parsed chapter, in order of appearance
+    pub fn chapter_blox(&self, section_id: usize) -> Vec<&Blox<'a>> {
+        self.section_items
+            .get(&section_id)
+            .map(|items| items.iter().filter_map(|i| self.item_blox(i)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Lists rendered blox with ids, in book order
+    pub fn fragment_entries(&self, chapters: &[SourceChapter<'a>]) -> Vec<FragmentEntry> {
+        let mut entries = Vec::new();
+
+        for (section_id, chapter) in chapters.iter().enumerate() {
+            let (Some(items), Some(path)) = (self.section_items.get(&section_id), &chapter.path)
+            else {
+                continue;
+            };
+
+            for item in items {
+                let Some(blox) = self.item_blox(item) else {
+                    continue;
+                };
+                let Some(id) = blox.id_str(self.config) else {
+                    continue;
+                };
+
+                entries.push(FragmentEntry {
+                    href: format!("{}#{id}", html_target(path, self.config.pretty_urls)),
+                    id,
+                    environment: blox.env().to_string(),
+                    label: blox.label().map(|s| s.to_string()),
+                    number: blox.number().map(|s| s.to_string()),
+                    title: blox.title().map(|s| s.to_string()),
+                });
+            }
+        }
+
+        entries
+    }
+
+    /// Duplicate labels of the parsed chapters, and refs in them to unknown labels or, for a
+    /// number, to unnumbered blox
+    pub fn check_refs(&self, chapters: &[SourceChapter<'a>]) -> Vec<RefProblem> {
+        let mut problems: Vec<RefProblem> = self
+            .duplicate_labels
+            .iter()
+            .map(|(label, path)| RefProblem::DuplicateLabel {
+                label: label.clone(),
+                location: path
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or("unknown chapter".to_string()),
+            })
+            .collect();
+
+        for (section_id, chapter) in chapters.iter().enumerate() {
+            for caps in self.patterns.refs.captures_iter(chapter.content) {
+                let label = caps["label"].to_string();
+                let location = location(chapter, section_id);
+                let key = transliterate(&label);
+
+                match self.ref_blox(&key, chapter.path.as_deref()) {
+                    Some(blox)
+                        if blox.number().is_none()
+                            && matches!(&caps["ref"], "nref" | "Nref" | "eqref") =>
+                    {
+                        problems.push(RefProblem::Unnumbered { label, location })
+                    }
+                    Some(_) => {}
+                    None if self.fallback_blox.contains_key(&key) => {}
+                    None => problems.push(RefProblem::UnknownLabel { label, location }),
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Chapters of the configured `lists`, linking to the blox of their environments in book
+    /// order. Blox left out of the index are left out of lists.
+    pub fn list_chapters(&self, chapters: &[SourceChapter<'a>]) -> Vec<GeneratedChapter> {
+        let mut lists = Vec::new();
+
+        for (key, list) in self.config.lists.iter() {
+            let environments = match list.environments.is_empty() {
+                true => std::slice::from_ref(key),
+                false => list.environments.as_slice(),
+            };
+            let name = list.title.clone().unwrap_or_else(|| {
+                let env_name = self.config.name(&environments[0]);
+                format!("List of {}", plural(&env_name))
+            });
+
+            let mut content = format!("# {name}\n\n");
+            for blox in (0..chapters.len()).flat_map(|i| self.chapter_blox(i)) {
+                if !blox.in_index() || !environments.iter().any(|e| e == blox.env()) {
+                    continue;
+                }
+                let Some(mut href) = blox.rel_path(&list.path, self.config) else {
+                    continue;
+                };
+                if let Some(id) = blox.id_str(self.config) {
+                    href.push_str(&format!("#{id}"));
+                }
+                content.push_str(&format!("- [{}]({href})\n", blox.title_full(self.config)));
+            }
+
+            lists.push(GeneratedChapter {
+                name,
+                path: list.path.clone(),
+                content,
+            });
+        }
+
+        lists
+    }
+
+    /// Markdown list of the answers of the blanks of all blox, linking to the blox from `chapter`
+    fn answer_key(&self, chapters: &[SourceChapter<'a>], chapter: &SourceChapter) -> String {
+        let mut key = String::new();
+        for blox in (0..chapters.len()).flat_map(|i| self.chapter_blox(i)) {
+            let answers = blox.answers();
+            if answers.is_empty() {
+                continue;
+            }
+
+            let item = self.linked_title(chapter, blox);
+            key.push_str(&format!("- {item}: {}\n", answers.join("; ")));
+        }
+        key
+    }
+
+    /// Markdown list of the blox matching a query, in book order, linking to them from the
+    /// chapter `section_id`
+    fn blox_list(
+        &self,
+        chapters: &[SourceChapter<'a>],
+        section_id: usize,
+        query: &ListQuery,
+    ) -> String {
+        let sections = match query.scope {
+            ListScope::Book => 0..chapters.len(),
+            ListScope::Chapter => section_id..section_id + 1,
+        };
+        let mut list = String::new();
+        for blox in sections.flat_map(|i| self.chapter_blox(i)) {
+            if blox.in_index() && query.matches(blox) {
+                let item = self.linked_title(&chapters[section_id], blox);
+                list.push_str(&format!("- {item}\n"));
+            }
+        }
+        list
+    }
+
+    /// Markdown list of the glossary terms, alphabetized, with links from `chapter` to the blox
+    /// defining them
+    fn glossary_list(&self, chapter: &SourceChapter) -> String {
+        let mut list = String::new();
+        for items in self.glossary.values() {
+            let blox: Vec<&Blox> = items.iter().filter_map(|i| self.item_blox(i)).collect();
+            let Some(term) = blox.first().and_then(|b| b.title()) else {
+                continue;
+            };
+            let links: Vec<String> = blox.iter().map(|b| self.linked_title(chapter, b)).collect();
+            list.push_str(&format!("- **{term}**: {}\n", links.join(", ")));
+        }
+        list
+    }
+
+    /// Full title of a blox as a Markdown link to it from `chapter`
+    fn linked_title(&self, chapter: &SourceChapter, blox: &Blox) -> String {
+        let title = blox.title_full(self.config);
+        let href = chapter
+            .path
+            .as_ref()
+            .and_then(|p| blox.rel_path(p, self.config));
+        match (href, blox.id_str(self.config)) {
+            (Some(href), Some(id)) => format!("[{title}]({href}#{id})"),
+            // A blox without id in the same chapter
+            (Some(href), None) if href.is_empty() => title,
+            (Some(href), None) => format!("[{title}]({href})"),
+            (None, _) => title,
+        }
+    }
+
+    /// Solutions rendered by `{{ blox-solutions }}`, in book order
+    fn collected_solutions(&self, chapters: &[SourceChapter<'a>]) -> String {
+        let solutions: Vec<Cow<str>> = (0..chapters.len())
+            .filter_map(|i| self.section_items.get(&i))
+            .flatten()
+            .filter(|item| {
+                self.item_blox(item)
+                    .is_some_and(|b| self.is_collected(item, b))
+            })
+            .map(|item| item.to_html(self.config, &self.anonymous_blox, &self.labelled_blox))
+            .collect();
+        solutions.join("\n\n")
+    }
+
+    /// Environments of the blox rendered in a chapter, with those of the collected solutions
+    /// if it lists them
+    fn rendered_envs(
+        &self,
+        chapter_count: usize,
+        section_id: usize,
+        lists_solutions: bool,
+    ) -> BTreeSet<&str> {
+        let in_place = self
+            .section_items
+            .get(&section_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|item| self.item_blox(item).filter(|b| !self.is_collected(item, b)));
+        let collected = (0..chapter_count)
+            .filter(|_| lists_solutions)
+            .filter_map(|i| self.section_items.get(&i))
+            .flatten()
+            .filter_map(|item| self.item_blox(item).filter(|b| self.is_collected(item, b)));
+
+        in_place.chain(collected).map(|blox| blox.env()).collect()
+    }
+
+    /// Whether a blox is rendered with the collected solutions instead of in place
+    fn is_collected(&self, item: &BookContentItem, blox: &Blox) -> bool {
+        self.solutions_path.is_some()
+            && blox.solution_of().is_some()
+            && !matches!(item, BookContentItem::Nested(_))
+    }
+
+    fn stringify_section(&self, section_id: usize) -> Result<String> {
+        let items = self
+            .section_items
+            .get(&section_id)
+            .context("Section id not found")?;
+        let mut new_content = String::new();
+        // Set of the last blox, while its container is open, and where the container closes
+        let mut open_set: Option<(&str, usize)> = None;
+
+        for item in items {
+            if self
+                .item_blox(item)
+                .is_some_and(|b| self.is_collected(item, b))
+            {
+                continue;
+            }
+            let html = item.to_html(self.config, &self.anonymous_blox, &self.labelled_blox);
+            let blox = self
+                .item_blox(item)
+                .filter(|_| !matches!(item, BookContentItem::Nested(_)));
+            let set = blox.and_then(|b| b.set());
+            let grouped = self.config.format() == OutputFormat::Html;
+
+            // Text between the blox of a set, other than whitespace, closes its container
+            let closes = match open_set {
+                Some((open, _)) if blox.is_some() => set != Some(open),
+                Some(_) => !html.trim().is_empty(),
+                None => false,
+            };
+            if let Some((_, end)) = open_set.take_if(|_| closes) {
+                new_content.insert_str(end, "</div>");
+            }
+
+            if let (Some(blox), Some(set)) = (blox, set.filter(|_| grouped)) {
+                if open_set.is_none() {
+                    new_content.push_str(&set_opening(self.config, blox.env(), set));
+                }
+                new_content.push_str(&html);
+                open_set = Some((set, new_content.len()));
+                continue;
+            }
+            new_content.push_str(&html);
+        }
+
+        if let Some((_, end)) = open_set {
+            new_content.insert_str(end, "</div>");
+        }
+
+        Ok(new_content)
+    }
+
+    /// Labels of refs in `content` which no blox defines
+    fn unbound_labels<'c>(
+        &self,
+        content: &'c str,
+        path: Option<&Path>,
+    ) -> impl Iterator<Item = &'c str> {
+        self.patterns
+            .refs
+            .captures_iter(content)
+            .filter_map(|caps| caps.name("label").map(|l| l.as_str()))
+            .filter(move |label| self.is_unbound(label, path))
+    }
+
+    /// Whether no blox defines the label of a ref in the chapter at `path`
+    fn is_unbound(&self, label: &str, path: Option<&Path>) -> bool {
+        let key = transliterate(label);
+        self.ref_blox(&key, path).is_none() && !self.fallback_blox.contains_key(&key)
+    }
+
+    /// Replaces the refs in `content` to labels no blox defines by placeholders, numbered by
+    /// first use within their chapter or, with `number_within_headings`, their `##` heading
+    fn number_placeholders(
+        &self,
+        content: &str,
+        chapter: &SourceChapter,
+        placeholders: &mut PlaceholderNumbers,
+    ) -> String {
+        let headings: Vec<usize> = Parser::new(content)
+            .into_offset_iter()
+            .filter_map(|(event, span)| match event {
+                Event::Start(Tag::Heading {
+                    level: HeadingLevel::H2,
+                    ..
+                }) => Some(span.start),
+                _ => None,
+            })
+            .collect();
+        let chapter_number = self.chapter_number(chapter);
+
+        self.patterns
+            .refs
+            .replace_all(content, |caps: &Captures| {
+                let ref_match = caps.get_match();
+                let Some(label) = caps
+                    .name("label")
+                    .map(|l| l.as_str())
+                    .filter(|label| self.is_unbound(label, chapter.path.as_deref()))
+                else {
+                    return ref_match.as_str().to_string();
+                };
+                let prefix = match (&chapter_number, self.config.number_within_headings) {
+                    (Some(n), true) => {
+                        let heading = headings.partition_point(|&h| h < ref_match.start());
+                        format!("{n}{heading}.")
+                    }
+                    (n, _) => n.clone().unwrap_or_default(),
+                };
+                placeholder_html(label, placeholders.number(label, prefix))
+            })
+            .into_owned()
+    }
+
+    /// Number of a chapter, or its configured prefix if unnumbered
+    fn chapter_number(&self, chapter: &SourceChapter) -> Option<String> {
+        chapter.number.clone().or_else(|| {
+            chapter
+                .path
+                .as_ref()
+                .and_then(|p| self.config.chapter_prefix(p))
+        })
+    }
+
+    pub(crate) fn replace_refs(&self, content: String, chapter: &SourceChapter) -> Result<String> {
+        let content = self
+            .patterns
+            .proof
+            .replace_all(&content, |caps: &Captures| {
+                let key = label_key(&caps["label"], chapter.path.as_deref());
+                let Some(notes) = self.proof_notes.get(key.as_ref()) else {
+                    return String::new();
+                };
+                let links: Vec<String> = notes
+                    .iter()
+                    .map(|(name, title, href)| {
+                        format!(
+                            "{name} in {}",
+                            markdown_link(title, &book_link(chapter, href))
+                        )
+                    })
+                    .collect();
+                links.join("; ")
+            })
+            .into_owned();
+
+        let new_content = self
+            .patterns
+            .refs
+            .replace_all(&content, |caps: &Captures| {
+                let Some(label) = caps.name("label").map(|l| l.as_str()) else {
+                    return replace_refs_error("Regex match error", "ref", "error");
+                };
+                let Some(ref_type) = caps.name("ref").map(|r| r.as_str()) else {
+                    return replace_refs_error("Unknown blox ref", "ref", label);
+                };
+
+                // Labels of blox are transliterated when parsed
+                let key = transliterate(label);
+                let (blox, path) = match self.ref_blox(&key, chapter.path.as_deref()) {
+                    Some(blox) => {
+                        let Some(mut path) = chapter
+                            .path
+                            .as_ref()
+                            .and_then(|p| blox.rel_path(p, self.config))
+                        else {
+                            return replace_refs_error(
+                                "Failed to get path to blox",
+                                ref_type,
+                                label,
+                            );
+                        };
+
+                        if !self.has_fragment(blox) {
+                            return replace_refs_error(
+                                "Blox fragment not registered",
+                                ref_type,
+                                label,
+                            );
+                        }
+
+                        path.push_str(
+                            &blox
+                                .id_str(self.config)
+                                .map(|s| format!("#{s}"))
+                                .unwrap_or_default(),
+                        );
+                        (blox, path)
+                    }
+                    // Blox outside of a partial build
+                    None => match self.fallback_blox.get(&key) {
+                        Some((blox, href)) => (blox, self.fallback_link(chapter, href)),
+                        None if self.config.ref_placeholders => {
+                            return placeholder_html(label, "");
+                        }
+                        None => return replace_refs_error("Unknown blox ref", ref_type, label),
+                    },
+                };
+
+                match ref_type {
+                    // Give title
+                    "Tref" => blox.title().map(|s| s.to_string()).unwrap_or_else(|| {
+                        replace_refs_error("Blox does not have a title", ref_type, label)
+                    }),
+                    // Give number
+                    "Nref" => blox.number().map(|s| s.to_string()).unwrap_or_else(|| {
+                        replace_refs_error("Blox does not have a number", ref_type, label)
+                    }),
+                    // Give linked number in parentheses, like `(3.4)`
+                    "eqref" => blox
+                        .number()
+                        .map(|n| markdown_link(&format!("({n})"), &path))
+                        .unwrap_or_else(|| {
+                            replace_refs_error("Blox does not have a number", ref_type, label)
+                        }),
+                    // Give link
+                    "lref" => path,
+                    // Provide linked environment-title
+                    "tref" => blox
+                        .title_env(self.config)
+                        .map(|s| markdown_link(&s, &path))
+                        .unwrap_or_else(|| {
+                            replace_refs_error("Blox does not have a title", ref_type, label)
+                        }),
+                    // Provide linked environment-number
+                    "nref" => blox
+                        .title_numbered(self.config)
+                        .map(|s| markdown_link(&s, &path))
+                        .unwrap_or_else(|| {
+                            replace_refs_error("Blox does not have a number", ref_type, label)
+                        }),
+                    // Provide linked environment-number-title
+                    "fref" => markdown_link(&blox.title_full(self.config), &path),
+                    // Provide linked environment-number, or environment-title if no number, like
+                    // the blox of `Solution of`
+                    "ofref" => {
+                        let text = blox
+                            .title_numbered(self.config)
+                            .unwrap_or_else(|| blox.title_full(self.config));
+                        markdown_link(&text, &path)
+                    }
+                    // Provide environment-number, or environment-title if no number
+                    _ => blox
+                        .title_auto(self.config)
+                        .map(|s| markdown_link(&s, &path))
+                        .unwrap_or_else(|| {
+                            replace_refs_error("Blox does not have a title", ref_type, label)
+                        }),
+                }
+            })
+            .to_string();
+
+        Ok(new_content)
+    }
+}
+
+/// Content hash ids shared by identical blox of a page get a suffix, other duplicates a warning
+fn register_fragment(
+    fragments: &mut HashMap<PathBuf, HashSet<String>>,
+    config: &Config,
+    blox: &mut Blox,
+) {
+    let (Some(path), Some(mut id)) = (blox.path().cloned(), blox.id_str(config)) else {
+        return;
+    };
+    let used = fragments.entry(path.clone()).or_default();
+    let hashed = blox.label().is_none() && blox.glossary_term(config).is_none();
+
+    if hashed {
+        let mut n = 1;
+        while used.contains(&id) {
+            n += 1;
+            blox.id_suffix = Some(n);
+            id = blox.id_str(config).unwrap_or_default();
+        }
+    }
+    if !used.insert(id.clone()) {
+        log::warn!("Duplicate blox fragment in {}: {id}", path.display());
+    }
+}
+
+/// The first directive opening a line of the paragraph at `span`, with its offset
+fn paragraph_directive(source: &str, span: Range<usize>) -> Option<(usize, Directive<'_>)> {
+    let mut start = span.start;
+    for line in source[span].split_inclusive('\n') {
+        let offset = start + line.len() - line.trim_start().len();
+        if let Some(directive) = directive(&source[offset..]) {
+            return Some((offset, directive));
+        }
+        start += line.len();
+    }
+    None
+}
+
+/// Environment key of a GitHub alert kind, like `note` for `> [!NOTE]`
+fn alert_environment(kind: BlockQuoteKind) -> &'static str {
+    match kind {
+        BlockQuoteKind::Note => "note",
+        BlockQuoteKind::Tip => "tip",
+        BlockQuoteKind::Important => "important",
+        BlockQuoteKind::Warning => "warning",
+        BlockQuoteKind::Caution => "caution",
+    }
+}
+
+/// Content of a GitHub alert: its blockquote without the `>` markers and the `[!KIND]` marker
+fn alert_content(source: &str) -> String {
+    let mut lines: Vec<&str> = source
+        .lines()
+        .map(|line| {
+            let line = line.trim_start();
+            match line.strip_prefix('>') {
+                Some(rest) => rest.strip_prefix(' ').unwrap_or(rest),
+                // Lazy continuation
+                None => line,
+            }
+        })
+        .collect();
+    if let Some(first) = lines.first_mut() {
+        *first = first.split_once(']').map_or("", |(_, rest)| rest.trim());
+    }
+    format!("\n{}\n", lines.join("\n").trim_matches('\n'))
+}
+
+/// Heading level of a blox in the PDF outline, one below its section
+fn outline_level(section_level: Option<HeadingLevel>) -> u8 {
+    let level = section_level.map_or(1, |l| l as u8);
+    (level + 1).min(6)
+}
+
+/// Link from a chapter to `href`, relative to the book root
+fn book_link(chapter: &SourceChapter, href: &str) -> String {
+    let depth = chapter
+        .path
+        .as_ref()
+        .and_then(|p| p.parent())
+        .map(|p| p.components().count())
+        .unwrap_or_default();
+    format!("{}{href}", "../".repeat(depth))
+}
+
+/// Context of errors of a blox, by its position in the chapter and the first line of its source
+fn blox_context(ordinal: usize, first_line: &str) -> String {
+    format!("Blox #{ordinal} `{}`", first_line.trim())
+}
+
+/// Number and name of a chapter, like `3. Appendix`
+fn chapter_title(chapter: &SourceChapter) -> Option<String> {
+    match (chapter.number.as_deref(), chapter.name) {
+        (Some(number), Some(name)) => Some(format!("{number} {name}")),
+        (None, Some(name)) => Some(name.to_string()),
+        (Some(number), None) => Some(format!("chapter {number}")),
+        (None, None) => None,
+    }
+}
+
+/// The path of a chapter, or its position if it has none
+fn location(chapter: &SourceChapter, section_id: usize) -> String {
+    match &chapter.path {
+        Some(path) => path.display().to_string(),
+        None => format!("chapter {}", section_id + 1),
+    }
+}
+
+/// Describes a parse error by the chapter and line of the blox
+fn parse_error(
+    chapter: &SourceChapter,
+    section_id: usize,
+    offset: usize,
+    err: &anyhow::Error,
+) -> String {
+    let line = chapter.content[..offset].matches('\n').count() + 1;
+    let location = location(chapter, section_id);
+    let message = format!("{err:#}").replace('\n', "\n    ");
+    format!("  {location}:{line}: {message}")
+}
+
+/// A blox in the content of another blox, with the blox nested in it
+struct NestedBlox<'a> {
+    /// Span in the content of the blox containing it
+    span: Range<usize>,
+    blox: Blox<'a>,
+    nested: Vec<NestedBlox<'a>>,
+}
+
+/// Parses the fenced blox in the content of `blox`, which needs a longer fence than theirs
+fn parse_nested<'a>(config: &Config, blox: &Blox<'a>) -> Result<Vec<NestedBlox<'a>>> {
+    let Cow::Borrowed(content) = blox.content else {
+        return Ok(Vec::new());
+    };
+    if blox.verbatim() {
+        return Ok(Vec::new());
+    }
+
+    let mut nested = Vec::new();
+    // End of the last directive, whose content is parsed with it
+    let mut skip_until = 0;
+    for (event, span) in Parser::new(content).into_offset_iter() {
+        if span.start < skip_until {
+            continue;
+        }
+        let (mut inner, span) = match event {
+            Event::Start(Tag::CodeBlock(Fenced(header))) => {
+                match Blox::parse(config, &content[span.clone()], &header)? {
+                    Some(inner) => (inner, span),
+                    None => continue,
+                }
+            }
+            Event::Start(Tag::Paragraph) if config.directives => {
+                let Some((start, directive)) = paragraph_directive(content, span) else {
+                    continue;
+                };
+                let Some(inner) = Blox::from_directive(config, &directive)? else {
+                    continue;
+                };
+                skip_until = start + directive.end;
+                (inner, start..skip_until)
+            }
+            _ => continue,
+        };
+
+        inner.depth = blox.depth + 1;
+        inner.outline_level = (blox.outline_level + 1).min(6);
+        let inner_nested = parse_nested(config, &inner)?;
+        nested.push(NestedBlox {
+            span,
+            blox: inner,
+            nested: inner_nested,
+        });
+    }
+
+    Ok(nested)
+}
+
+/// Key of a label among the labelled blox, prefixed by the chapter path if it is `.local`
+fn label_key<'l>(label: &'l str, path: Option<&Path>) -> Cow<'l, str> {
+    match (label.starts_with('.'), path) {
+        (true, Some(path)) => Cow::Owned(format!("{}{label}", path.display())),
+        _ => Cow::Borrowed(label),
+    }
+}
+
+/// Lists the labels referenced but defined nowhere, with the chapters referencing them
+fn unbound_report(unbound: &BTreeMap<String, BTreeSet<String>>) -> Option<String> {
+    if unbound.is_empty() {
+        return None;
+    }
+
+    let lines: Vec<String> = unbound
+        .iter()
+        .map(|(label, chapters)| {
+            let chapters: Vec<&str> = chapters.iter().map(|c| c.as_str()).collect();
+            format!("  `{label}`: {}", chapters.join(", "))
+        })
+        .collect();
+    let count = match unbound.len() {
+        1 => "1 blox label is".to_string(),
+        n => format!("{n} blox labels are"),
+    };
+    Some(format!(
+        "{count} referenced but never defined:\n{}",
+        lines.join("\n")
+    ))
+}
+
+/// Placeholder of a ref to a label no blox defines, like LaTeX's `??`
+fn placeholder_html(label: &str, number: &str) -> String {
+    format!(
+        r#"<span class="{}" title="{label}">??{number}</span>"#,
+        BloxCss::unbound_class()
+    )
+}
+
+/// Numbers of the placeholders of labels no blox defines, in order of first use
+#[derive(Debug, Default)]
+struct PlaceholderNumbers {
+    /// Number of each label
+    numbers: HashMap<String, String>,
+    /// Next number by prefix, like `2.` for chapter 2
+    next: HashMap<String, usize>,
+}
+
+impl PlaceholderNumbers {
+    /// Number of a label, the next one after `prefix` on first use
+    fn number(&mut self, label: &str, prefix: String) -> &str {
+        if !self.numbers.contains_key(label) {
+            let next = self.next.entry(prefix.clone()).or_insert(1);
+            self.numbers
+                .insert(label.to_string(), format!("{prefix}{next}"));
+            *next += 1;
+        }
+        &self.numbers[label]
+    }
+}
+
+/// Opening of the container of consecutive blox of an exercise set, with its header like
+/// `Exercises 3.2`, on the line of the first blox
+fn set_opening(config: &Config, env: &str, set: &str) -> String {
+    format!(
+        r#"<div class="{}"><div class="{}">{} {set}</div>"#,
+        BloxCss::set_class(),
+        BloxCss::set_header_class(),
+        plural(&config.name(env))
+    )
+}
+
+/// English plural of an environment name, like `Theorems` or `Corollaries`
+fn plural(name: &str) -> String {
+    let consonant_y = name.ends_with('y')
+        && !matches!(name.chars().rev().nth(1), Some('a' | 'e' | 'i' | 'o' | 'u'));
+    if consonant_y {
+        format!("{}ies", &name[..name.len() - 1])
+    } else if name.ends_with(['s', 'x']) || name.ends_with("ch") || name.ends_with("sh") {
+        format!("{name}es")
+    } else {
+        format!("{name}s")
+    }
+}
+
+fn replace_refs_error(err: &str, ref_type: &str, label: &str) -> String {
+    log::warn!("{err}: {label}");
+    format!("**[??blox-{ref_type}: {label}??]**")
+}
+
+fn markdown_link(text: &str, link: &str) -> String {
+    format!("[{text}]({link})")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::test::default_test_config;
+    use pretty_assertions::assert_eq;
+
+    fn process_one(content: &str) -> Result<String> {
+        process_with(content, &default_test_config())
+    }
+
+    fn process_with(content: &str, config: &Config) -> Result<String> {
+        let chapters = [SourceChapter {
+            content,
+            path: Some(PathBuf::from("chapter.md")),
+            number: Some("1.".to_string()),
+            ..Default::default()
+        }];
+        Ok(BloxProcessor::process(&chapters, config)?.remove(0))
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let config = default_test_config();
+        let chapters = [
+            SourceChapter {
+                content: "# A\n\n```blox exercise title = \n```\n\n```blox exercise\nFine\n```\n",
+                path: Some(PathBuf::from("a.md")),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "<!-- blox: unknown -->\nB\n<!-- /blox -->\n",
+                ..Default::default()
+            },
+        ];
+
+        let err = BloxProcessor::process(&chapters, &config).unwrap_err();
+        let report = err.to_string();
+        assert!(report.starts_with("2 blox could not be parsed:\n"));
+        assert!(report.contains(
+            "\n  a.md:3: Blox #1 ````blox exercise title =`: Failed to parse blox options: title ="
+        ));
+        assert!(report.contains(
+            "\n  chapter 2:1: Blox #1 `<!-- blox: unknown -->`: Blox environment `unknown` not defined"
+        ));
+    }
+
+    #[test]
+    fn test_lenient() -> Result<()> {
+        let mut config = default_test_config();
+        config.lenient = true;
+
+        let content = "```blox exercise title = \n<b>\n\nx\n```\n\n<!-- blox: unknown -->\nB\n<!-- /blox -->\nAfter\n";
+        let html = process_with(content, &config)?;
+
+        assert!(html.starts_with(r#"<div class="blox blox-error"><div class="blox-header">"#));
+        assert!(html.contains("<pre>```blox exercise title = \n&lt;b&gt;\n\nx\n```</pre>"));
+        assert!(html.contains("<pre>&lt;!-- blox: unknown --&gt;\nB\n&lt;!-- /blox --&gt;</pre>"));
+        assert!(html.ends_with("</div></div>\nAfter\n"));
+        assert_eq!(html.matches("blox-error").count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_see_also() -> Result<()> {
+        let content = "```blox exercise label = \"a\", footer = \"Hard\", see_also = [\"b\"]\nA\n```\n\n```blox exercise label = \"b\"\nB\n```\n";
+        let html = process_one(content)?;
+
+        assert!(html.contains(
+            "<div class=\"blox-footer\">\n\nHard\n\nSee also: [Exercise 1.2](#blox-exercise-b)\n\n</div>"
+        ));
+        assert_eq!(html.matches("See also").count(), 1);
+
+        let mut config = default_test_config();
+        config.reciprocal_see_also = true;
+        let html = process_with(content, &config)?;
+        assert!(html.ends_with(
+            "<div class=\"blox-footer\">\n\nSee also: [Exercise 1.1](#blox-exercise-a)\n\n</div></div>\n"
+        ));
+
+        // Chapter-scoped labels
+        let content = "```blox exercise label = \".a\", see_also = [\".b\"]\nA\n```\n\n```blox exercise label = \".b\"\nB\n```\n\n```blox alert label = \".c\", defer_rendering = true\nC\n```\n\n{{ blox-render: .c }}\n";
+        let html = process_with(content, &config)?;
+        assert_eq!(
+            html.matches("See also: [Exercise 1.2](#blox-exercise-b)")
+                .count(),
+            1
+        );
+        assert_eq!(
+            html.matches("See also: [Exercise 1.1](#blox-exercise-a)")
+                .count(),
+            1
+        );
+        assert!(!html.contains("blox-render"));
+        assert!(html.ends_with("<div id=\"blox-alert-c\" class=\"blox blox-alert\"><div class=\"blox-header\">\n\nAlert\n\n</div><div class=\"blox-content\">\n\n\nC\n\n\n</div></div>\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ref_placeholders() -> Result<()> {
+        let mut config = default_test_config();
+        config.ref_placeholders = true;
+        let chapters = [
+            SourceChapter {
+                content: "See {{ blox-nref: later }} and {{ blox-ref: missing }}.\n\n## Next\n\n{{ blox-ref: gone }}\n",
+                path: Some(PathBuf::from("a.md")),
+                number: Some("1.".to_string()),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "```blox exercise label = \"later\"\nA\n```\n\n{{ blox-ref: missing }}\n",
+                path: Some(PathBuf::from("b.md")),
+                number: Some("2.".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let mut processor = BloxProcessor::new(&config)?;
+        processor.parse(&chapters)?;
+        let unbound: Vec<&str> = processor
+            .unbound_labels(chapters[0].content, chapters[0].path.as_deref())
+            .collect();
+        assert_eq!(unbound, ["missing", "gone"]);
+
+        // Numbered by first use, and alike wherever referenced
+        let html = processor.render(&chapters)?;
+        assert_eq!(
+            html[0],
+            "See [Exercise 2.1](b.html#blox-exercise-later) and <span class=\"blox-unbound\" title=\"missing\">??1.1</span>.\n\n## Next\n\n<span class=\"blox-unbound\" title=\"gone\">??1.2</span>\n"
+        );
+        assert!(html[1].ends_with("<span class=\"blox-unbound\" title=\"missing\">??1.1</span>\n"));
+
+        config.number_within_headings = true;
+        let mut processor = BloxProcessor::new(&config)?;
+        processor.parse(&chapters)?;
+        let html = processor.render(&chapters)?;
+        assert!(html[0].contains("title=\"missing\">??1.0.1</span>"));
+        assert!(html[0].contains("title=\"gone\">??1.1.1</span>"));
+
+        let unbound = BTreeMap::from([(
+            "missing".to_string(),
+            BTreeSet::from(["a.md".to_string(), "b.md".to_string()]),
+        )]);
+        assert_eq!(
+            unbound_report(&unbound).as_deref(),
+            Some("1 blox label is referenced but never defined:\n  `missing`: a.md, b.md")
+        );
+        assert_eq!(unbound_report(&BTreeMap::new()), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_closing_options() -> Result<()> {
+        let content =
+            "```blox exercise\nA\n``` footer = \"Rudin\"\n\nText\n\n```blox exercise\nB\n```\n";
+        let html = process_one(content)?;
+
+        assert!(html.contains("<div class=\"blox-footer\">\n\nRudin\n\n</div></div>"));
+        assert!(html.contains("\n\nText\n\n"));
+        assert!(html.contains("Exercise 1.2"));
+        assert!(!html.contains("```"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_labels() -> Result<()> {
+        let config = default_test_config();
+        let chapter = |path: &str, number: &str, content| SourceChapter {
+            content,
+            path: Some(PathBuf::from(path)),
+            number: Some(number.to_string()),
+            ..Default::default()
+        };
+        let chapters = [
+            chapter(
+                "a.md",
+                "1.",
+                "```blox exercise label = \".ex\"\nA\n```\n\n```blox exercise label = \"main\"\nM\n```\n\n{{ blox-nref: .ex }}\n",
+            ),
+            chapter(
+                "b.md",
+                "2.",
+                "```blox exercise label = \".ex\"\nB\n```\n\n{{ blox-nref: .ex }}, {{ blox-nref: .main }}\n",
+            ),
+        ];
+
+        let html = BloxProcessor::process(&chapters, &config)?;
+        assert!(html[0].contains(r#"<div id="blox-exercise-ex" class="blox blox-exercise">"#));
+        assert!(html[0].ends_with("[Exercise 1.1](#blox-exercise-ex)\n"));
+        assert!(html[1].ends_with(
+            "[Exercise 2.1](#blox-exercise-ex), [Exercise 1.2](a.html#blox-exercise-main)\n"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_titles() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+exercise = { name = "Exercise", unique_titles = true, unique_titles_ignore = ["Warm-up"] }
+note = { name = "Note" }
+"#,
+        )?;
+        let content = r#"```blox exercise label = "a", title = "Sum"
+A
+```
+
+```blox exercise title = " sum "
+B
+```
+
+```blox exercise title = "Product"
+C
+```
+
+```blox exercise title = "Warm-up"
+D
+```
+
+```blox exercise title = "warm-up"
+E
+```
+
+```blox note title = "Sum"
+F
+```
+
+```blox note title = "Sum"
+G
+```
+"#;
+        let chapters = [SourceChapter {
+            content,
+            path: Some(PathBuf::from("a.md")),
+            number: Some("1.".to_string()),
+            ..Default::default()
+        }];
+
+        let mut processor = BloxProcessor::new(&config)?;
+        processor.parse(&chapters)?;
+        assert_eq!(
+            processor.duplicate_titles(),
+            ["Title used by 2 blox in environment exercise: sum"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_refs_fallback() -> Result<()> {
+        let entry = |label: &str, environment: &str| FragmentEntry {
+            href: format!("chapter_1.html#blox-{environment}-{label}"),
+            id: format!("blox-{environment}-{label}"),
+            environment: environment.to_string(),
+            label: Some(label.to_string()),
+            number: Some("1.1".to_string()),
+            title: Some("Sum".to_string()),
+        };
+        let entries = vec![
+            entry("sum", "exercise"),
+            entry("odd", "unknown"),
+            FragmentEntry {
+                label: None,
+                ..entry("anonymous", "alert")
+            },
+        ];
+        let chapters = [SourceChapter {
+            content: "{{ blox-ref: sum }} {{ blox-tref: sum }} {{ blox-ref: odd }}\n",
+            path: Some(PathBuf::from("part/b.md")),
+            number: Some("2.".to_string()),
+            ..Default::default()
+        }];
+
+        let mut config = default_test_config();
+        let mut processor = BloxProcessor::new(&config)?;
+        processor.add_fallback(entries.clone());
+        processor.parse(&chapters)?;
+        assert_eq!(
+            processor.render(&chapters)?,
+            [
+                "[Exercise 1.1: Sum](../chapter_1.html#blox-exercise-sum) [Exercise: Sum](../chapter_1.html#blox-exercise-sum) **[??blox-ref: odd??]**\n"
+            ]
+        );
+
+        config.refs_fallback_url = Some("https://example.com/book/".to_string());
+        let mut processor = BloxProcessor::new(&config)?;
+        processor.add_fallback(entries);
+        processor.parse(&chapters)?;
+        assert_eq!(
+            processor.render(&chapters)?,
+            [
+                "[Exercise 1.1: Sum](https://example.com/book/chapter_1.html#blox-exercise-sum) [Exercise: Sum](https://example.com/book/chapter_1.html#blox-exercise-sum) **[??blox-ref: odd??]**\n"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_refs() -> Result<()> {
+        let config = default_test_config();
+        let chapters = [
+            SourceChapter {
+                content: "```blox exercise label = \"ex\"\nA\n```\n\n```blox alert label = \"warn\"\nB\n```\n",
+                path: Some(PathBuf::from("a.md")),
+                number: Some("1.".to_string()),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "```blox exercise label = \"ex\"\nC\n```\n\n{{ blox-nref: ex }} {{ blox-ref: warn }} {{ blox-nref: warn }} {{ blox-ref: missing }}\n",
+                path: Some(PathBuf::from("b.md")),
+                number: Some("2.".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let mut processor = BloxProcessor::new(&config)?;
+        processor.parse(&chapters)?;
+        let problems: Vec<String> = processor
+            .check_refs(&chapters)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                "b.md: duplicate label `ex`",
+                "b.md: ref to unnumbered blox `warn`",
+                "b.md: unknown label `missing`",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_counter() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+theorem = { name = "Theorem", counter = "thmlike" }
+lemma = { name = "Lemma", counter = "thmlike" }
+example = { name = "Example" }
+"#,
+        )?;
+        let content = "```blox theorem\nA\n```\n\n```blox example\nB\n```\n\n```blox lemma\nC\n```\n\n```blox theorem\nD\n```\n";
+        let html = process_with(content, &config)?;
+
+        let headers: Vec<&str> = html
+            .split("<div class=\"blox-header\">\n\n")
+            .skip(1)
+            .map(|s| s.lines().next().unwrap_or_default())
+            .collect();
+        assert_eq!(
+            headers,
+            ["Theorem 1.1", "Example 1.1", "Lemma 1.2", "Theorem 1.3"]
+        );
+
+        // A shared counter starts at the start of its first environment by order
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+theorem = { name = "Theorem", counter = "thmlike", counter_start = 5, order = 2 }
+lemma = { name = "Lemma", counter = "thmlike", counter_start = 0, order = 1 }
+"#,
+        )?;
+        let html = process_with("```blox theorem\nA\n```\n", &config)?;
+        assert!(html.contains("Theorem 1.0"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_hash_ids() -> Result<()> {
+        let mut config = default_test_config();
+        config.id_source = crate::config::IdSource::ContentHash;
+        let content =
+            "```blox alert\nSame\n```\n\n```blox alert\nSame\n```\n\n```blox alert\nSame\n```\n";
+        let html = process_with(content, &config)?;
+
+        let ids: Vec<&str> = html
+            .split(" id=\"")
+            .skip(1)
+            .filter_map(|s| s.split('"').next())
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                "blox-alert-4cf7b4eb",
+                "blox-alert-4cf7b4eb-2",
+                "blox-alert-4cf7b4eb-3"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_number_within_headings() -> Result<()> {
+        let mut config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+theorem = { name = "Theorem" }
+"#,
+        )?;
+        config.number_within_headings = true;
+        let content = "```blox theorem\nA\n```\n\n## One\n\n```blox theorem\nB\n```\n\n### Sub\n\n```blox theorem\nC\n```\n\n## Two\n\n## Three\n\n```blox theorem\nD\n\n```blox theorem\nE\n```\n\n```\n";
+        let html = process_with(content, &config)?;
+
+        let headers: Vec<&str> = html
+            .split("<div class=\"blox-header\">\n\n")
+            .skip(1)
+            .map(|s| s.lines().next().unwrap_or_default())
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                "Theorem 1.0.1",
+                "Theorem 1.1.1",
+                "Theorem 1.1.2",
+                "Theorem 1.3.1",
+                "Theorem 1.3.2"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_counter_arithmetic() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+exercise = { name = "Exercise", counter_start = 0 }
+problem = { name = "Problem", counter_start = 10, counter_step = 10, prefix_number = false }
+
+[preprocessor.blox.chapter_counters."second.md"]
+exercise = 5
+"#,
+        )?;
+        let content =
+            "```blox exercise\nA\n```\n\n```blox problem\nB\n```\n\n```blox exercise\nC\n```\n";
+        let chapters = [
+            SourceChapter {
+                content,
+                path: Some(PathBuf::from("first.md")),
+                number: Some("1.".to_string()),
+                ..Default::default()
+            },
+            SourceChapter {
+                content,
+                path: Some(PathBuf::from("second.md")),
+                number: Some("2.".to_string()),
+                ..Default::default()
+            },
+        ];
+        let html = BloxProcessor::process(&chapters, &config)?.join("");
+
+        let headers: Vec<&str> = html
+            .split("<div class=\"blox-header\">\n\n")
+            .skip(1)
+            .map(|s| s.lines().next().unwrap_or_default())
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                "Exercise 1.0",
+                "Problem 1.10",
+                "Exercise 1.1",
+                "Exercise 2.5",
+                "Problem 2.20",
+                "Exercise 2.6"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exercise_sets() -> Result<()> {
+        let config = default_test_config();
+        let content = "```blox exercise set = \"3.2\"\nA\n```\n\n```blox exercise set = \"3.2\", label = \"b\"\nB\n```\n\nText\n\n```blox exercise\nC\n```\n\n```blox exercise set = \"3.2\"\nD\n```\n\nSee {{ blox-ref: b }}.\n";
+        let html = process_with(content, &config)?;
+
+        assert_eq!(
+            html.matches(r#"<div class="blox-set"><div class="blox-set-header">Exercises 3.2</div><div class="blox blox-exercise">"#)
+                .count(),
+            2
+        );
+        assert!(html.contains("\n\nExercise 1\n\n"));
+        assert!(html.contains("A\n\n\n</div></div>\n\n<div id=\"blox-exercise-b\""));
+        assert!(html.contains("B\n\n\n</div></div></div>\n\nText"));
+        assert!(html.contains("\n\nExercise 1.1\n\n"));
+        assert!(html.contains("\n\nExercise 3\n\n"));
+        assert!(html.contains("D\n\n\n</div></div></div>\n\nSee"));
+        assert!(html.contains("[Exercise 3.2.2](#blox-exercise-b)."));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_github_alerts() -> Result<()> {
+        let mut config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+note = { name = "Note", numbered = false }
+"#,
+        )?;
+        let content = "> [!NOTE]\n> Read *this*.\n>\n> - And\n  this\n\n> [!TIP]\n> Not configured.\n\n> Plain quote\n";
+        assert_eq!(process_with(content, &config)?, content);
+
+        config.github_alerts = true;
+        let html = process_with(content, &config)?;
+        assert!(html.starts_with(r#"<div class="blox blox-note"><div class="blox-header">"#));
+        assert!(html.contains("\n\nRead *this*.\n\n- And\nthis\n\n\n</div></div>"));
+        assert!(html.ends_with("</div></div>\n\n> [!TIP]\n> Not configured.\n\n> Plain quote\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directives() -> Result<()> {
+        let mut config = default_test_config();
+        let content = "::::blox exercise {label = \"sum\"}\nAdd.\n:::blox alert\nCareful\n:::\n::::\n\nSee {{ blox-ref: sum }}.\n\n```blox exercise\nFenced\n```\n";
+        assert!(process_with(content, &config)?.starts_with("::::blox exercise"));
+
+        config.directives = true;
+        let html = process_with(content, &config)?;
+        assert!(html.starts_with(r#"<div id="blox-exercise-sum" class="blox blox-exercise">"#));
+        assert!(html.contains("\nAdd.\n\n\n<div class=\"blox blox-alert\" data-depth=\"1\">"));
+        assert!(html.contains("</div></div>\n\nSee [Exercise 1.1](#blox-exercise-sum)."));
+        assert!(html.contains("Exercise 1.2"));
+        assert!(!html.contains(":::"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_notes() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+theorem = { name = "Theorem" }
+proof = { name = "Proof", numbered = false, qed = true }
+"#,
+        )?;
+        let chapters = [
+            SourceChapter {
+                content: "```blox theorem label = \"main\"\nClaim\n```\n\n```blox theorem label = \"near\"\nOther\n```\n\n```blox proof proof_of = \"near\"\nHere\n```\n",
+                path: Some(PathBuf::from("first.md")),
+                number: Some("1.".to_string()),
+                name: Some("Results"),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "```blox proof proof_of = \"main\"\nBecause\n```\n",
+                path: Some(PathBuf::from("appendix/b.md")),
+                name: Some("Appendix B"),
+                ..Default::default()
+            },
+        ];
+        let html = BloxProcessor::process(&chapters, &config)?;
+
+        assert!(html[0].contains("Proof in [Appendix B](appendix/b.html)"));
+        assert_eq!(html[0].matches("Proof in").count(), 1);
+        assert!(!html[1].contains("Proof in"));
+        assert!(html[0].contains("\n\nProof of [Theorem 1.2](#blox-theorem-near)\n\n"));
+        assert!(
+            html[1].contains("\n\nProof of [Theorem 1.1](../first.html#blox-theorem-main)\n\n")
+        );
+        assert!(html[1].contains("<div class=\"blox-footer\">\n\n∎\n\n</div>"));
+
+        // Unnumbered theorems are named by their title
+        let content = "```blox theorem label = \"zorn\", title = \"Zorn\", numbered = false\nA\n```\n\n```blox proof proof_of = \"zorn\"\nB\n```\n";
+        let html = process_with(content, &config)?;
+        assert!(html.contains("\n\nProof of [Theorem: Zorn](#blox-theorem-zorn)\n\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_solutions() -> Result<()> {
+        let mut config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+exercise = { name = "Exercise" }
+solution = { name = "Solution" }
+"#,
+        )?;
+        let chapters = [
+            SourceChapter {
+                content: "```blox exercise label = \"sum\"\nAdd.\n```\n\n```blox solution solution_of = \"sum\", label = \"sol\"\nTwo.\n```\n\nSee {{ blox-ref: sol }}.\n",
+                path: Some(PathBuf::from("first.md")),
+                number: Some("1.".to_string()),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "# Solutions\n\n{{ blox-solutions }}\n",
+                path: Some(PathBuf::from("solutions.md")),
+                ..Default::default()
+            },
+        ];
+
+        let html = BloxProcessor::process(&chapters, &config)?;
+        assert!(html[0].contains(r#"<details class="blox-reveal"><summary>Show solution</summary><div class="blox-content">"#));
+        assert!(html[0].contains("Solution of [Exercise 1.1](#blox-exercise-sum)"));
+        assert!(html[0].contains("See [Solution 1.1](#blox-solution-sol)."));
+        assert!(html[1].contains("{{ blox-solutions }}"));
+
+        config.collect_solutions = true;
+        let html = BloxProcessor::process(&chapters, &config)?;
+        assert!(!html[0].contains("Two."));
+        assert!(html[0].contains("See [Solution 1.1](solutions.html#blox-solution-sol)."));
+        assert!(html[1].starts_with("# Solutions\n\n<div id=\"blox-solution-sol\""));
+        assert!(html[1].contains("Solution of [Exercise 1.1](first.html#blox-exercise-sum)"));
+
+        // Unnumbered exercises are named by their title
+        let content = "```blox exercise label = \"free\", title = \"Free\", numbered = false\nA\n```\n\n```blox solution solution_of = \"free\"\nB\n```\n";
+        config.collect_solutions = false;
+        let html = process_with(content, &config)?;
+        assert!(html.contains("Solution of [Exercise: Free](#blox-exercise-free)"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_equations() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+equation = { name = "Equation", kind = "equation" }
+"#,
+        )?;
+        let content = "```blox equation label = \"euler\"\ne^{i\\pi} + 1 = 0\n```\n\n```blox equation\n\\[ a_1 * b_2 \\\\ c \\]\n```\n\nBy {{ blox-eqref: euler }}.\n";
+        let html = process_with(content, &config)?;
+
+        // Punctuation is escaped, so mdBook passes the math to MathJax as written
+        assert!(html.starts_with(
+            "<div id=\"blox-equation-euler\" class=\"blox blox-equation\"><div class=\"blox-content blox-math\">\n\n\\$\\$\ne\\^\\{i\\\\pi\\} \\+ 1 \\= 0\n\\$\\$\n\n</div><span class=\"blox-math-number\">(1.1)</span></div>"
+        ));
+        assert!(html.contains(
+            "\n\n\\\\\\[ a\\_1 \\* b\\_2 \\\\\\\\ c \\\\\\]\n\n</div><span class=\"blox-math-number\">(1.2)</span>"
+        ));
+        let mut rendered = String::new();
+        pulldown_cmark::html::push_html(&mut rendered, Parser::new(&html));
+        assert!(rendered.contains(r"\[ a_1 * b_2 \\ c \]"));
+        assert!(html.contains("By [(1.1)](#blox-equation-euler)."));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blox_list() -> Result<()> {
+        let config = default_test_config();
+        let chapters = [
+            SourceChapter {
+                content: "```blox exercise label = \"a\", tags = [\"midterm\", \"hard\"]\nA\n```\n\n```blox exercise tags = [\"hard\"]\nB\n```\n\n```blox alert tags = [\"midterm\"]\nC\n```\n\n{{blox-list: alert scope = \"chapter\"}}\n",
+                path: Some(PathBuf::from("first.md")),
+                number: Some("1.".to_string()),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "{{ blox-list: exercise tags = \"midterm\" }}\n\n{{blox-list: exercise tags = [\"hard\"]}}\n\n{{ blox-list: exercise size = 2 }}\n\n{{ blox-list: alert scope = \"chapter\" }}\n",
+                path: Some(PathBuf::from("lists.md")),
+                ..Default::default()
+            },
+        ];
+        let html = BloxProcessor::process(&chapters, &config)?;
+
+        assert_eq!(
+            html[1],
+            "- [Exercise 1.1](first.html#blox-exercise-a)\n\n\n- [Exercise 1.1](first.html#blox-exercise-a)\n- [Exercise 1.2](first.html)\n\n\n**[??blox-list: exercise size = 2 ??]**\n\n\n"
+        );
+        assert!(html[0].ends_with("</div></div>\n\n- Alert\n\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_glossary() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+definition = { name = "Definition", kind = "glossary" }
+"#,
+        )?;
+        let chapters = [
+            SourceChapter {
+                content: "```blox definition title = \"Vector space\"\nA\n```\n\n```blox definition title = \"Group\"\nB\n```\n",
+                path: Some(PathBuf::from("algebra/intro.md")),
+                number: Some("1.".to_string()),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "```blox definition label = \"abelian\", title = \"group\"\nC\n```\n\nSee {{blox-ref: vector-space}}.\n",
+                path: Some(PathBuf::from("groups.md")),
+                number: Some("2.".to_string()),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "{{ blox-glossary }}\n",
+                path: Some(PathBuf::from("glossary.md")),
+                ..Default::default()
+            },
+        ];
+        let html = BloxProcessor::process(&chapters, &config)?;
+
+        assert!(html[0].starts_with("<div id=\"blox-definition-vector-space\" class="));
+        assert!(html[1].ends_with(
+            "See [Definition 1.1: Vector space](algebra/intro.html#blox-definition-vector-space).\n"
+        ));
+        assert_eq!(
+            html[2],
+            "- **Group**: [Definition 1.2: Group](algebra/intro.html#blox-definition-group), [Definition 2.1: group](groups.html#blox-definition-abelian)\n- **Vector space**: [Definition 1.1: Vector space](algebra/intro.html#blox-definition-vector-space)\n\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chapter_css() -> Result<()> {
+        let mut config = default_test_config();
+        config.chapter_css = true;
+        let html = process_with("```blox alert\nA\n```\n", &config)?;
+        let (_, style) = html.split_once("<style>").unwrap();
+        assert!(style.contains(".blox.blox-alert {") && !style.contains("blox-quote"));
+
+        assert!(!process_with("No blox\n", &config)?.contains("<style>"));
+
+        // Environments of the blox each chapter ends up rendering
+        let mut config = Config::from_book_toml(
+            r#"
+[preprocessor.blox]
+chapter_css = true
+collect_solutions = true
+
+[preprocessor.blox.environments]
+exercise = { name = "Exercise" }
+solution = { name = "Solution" }
+remark = { name = "Remark" }
+"#,
+        )?;
+        let chapters = [
+            SourceChapter {
+                content: "```blox exercise label = \"sum\"\nAdd.\n```\n\n```blox solution solution_of = \"sum\"\nTwo.\n```\n\n```blox remark label = \"later\", defer_rendering = true\nLater.\n```\n",
+                path: Some(PathBuf::from("first.md")),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "{{ blox-solutions }}\n\n{{ blox-render: later }}\n",
+                path: Some(PathBuf::from("solutions.md")),
+                ..Default::default()
+            },
+        ];
+        let styles = |config: &Config| -> Result<Vec<String>> {
+            Ok(BloxProcessor::process(&chapters, config)?
+                .into_iter()
+                .map(|html| html.split_once("<style>").unwrap_or_default().1.to_string())
+                .collect())
+        };
+        let style = styles(&config)?;
+        assert!(style[0].contains(".blox.blox-exercise {"));
+        assert!(!style[0].contains("blox-solution") && !style[0].contains("blox-remark"));
+        assert!(
+            style[1].contains(".blox.blox-solution {") && style[1].contains(".blox.blox-remark {")
+        );
+        assert!(!style[1].contains("blox-exercise"));
+
+        config.collect_solutions = false;
+        let style = styles(&config)?;
+        assert!(style[0].contains(".blox.blox-solution {"));
+        assert!(!style[1].contains("blox-solution"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blanks() -> Result<()> {
+        let mut config = default_test_config();
+        let chapters = [
+            SourceChapter {
+                content: "```blox exercise label = \"sum\"\n$1 + 1 =$ {{ blank: 2 }}, and {{blank:*two*}}\n```\n",
+                path: Some(PathBuf::from("first.md")),
+                number: Some("1.".to_string()),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "# Answers\n\n{{ blox-answers }}\n",
+                path: Some(PathBuf::from("answers.md")),
+                number: Some("2.".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let html = BloxProcessor::process(&chapters, &config)?;
+        assert!(html[0].contains(
+            r#"$1 + 1 =$ <span class="blox-blank"></span>, and <span class="blox-blank"></span>"#
+        ));
+        assert_eq!(
+            html[1],
+            "# Answers\n\n- [Exercise 1.1](first.html#blox-exercise-sum): 2; *two*\n\n"
+        );
+
+        config.answers = true;
+        let html = BloxProcessor::process(&chapters, &config)?;
+        assert!(html[0].contains(
+            r#"<span class="blox-blank">2</span>, and <span class="blox-blank">*two*</span>"#
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_number_style() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+theorem = { name = "Theorem" }
+exercise = { name = "Exercise", number_style = "alpha" }
+"#,
+        )?;
+        let content =
+            "```blox exercise\nA\n```\n\n```blox theorem\nB\n```\n\n```blox exercise\nC\n```\n";
+        let html = process_with(content, &config)?;
+
+        assert!(html.contains("Exercise 1.a"));
+        assert!(html.contains("Theorem 1.1"));
+        assert!(html.contains("Exercise 1.b"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plural() {
+        assert_eq!(plural("Theorem"), "Theorems");
+        assert_eq!(plural("Corollary"), "Corollaries");
+        assert_eq!(plural("Essay"), "Essays");
+        assert_eq!(plural("Box"), "Boxes");
+    }
+
+    #[test]
+    fn test_unicode_labels() -> Result<()> {
+        let content = "```blox exercise label = \"定理\"\nA\n```\n\n```blox exercise label = \"🚀\"\nB\n```\n\n```blox exercise label = \"Lösung\"\nC\n```\n\n{{ blox-nref: 定理 }}, {{ blox-nref: 🚀 }}, {{ blox-nref: Lösung }}\n";
+        let html = process_one(content)?;
+
+        assert!(html.contains(r#"<div id="blox-exercise-u5b9au7406" class="blox blox-exercise">"#));
+        assert!(html.contains(r#"<div id="blox-exercise-u1f680" class="blox blox-exercise">"#));
+        assert!(html.contains(r#"<div id="blox-exercise-Losung" class="blox blox-exercise">"#));
+        assert!(html.ends_with(
+            "[Exercise 1.1](#blox-exercise-u5b9au7406), [Exercise 1.2](#blox-exercise-u1f680), [Exercise 1.3](#blox-exercise-Losung)\n"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested() -> Result<()> {
+        let content = "````blox exercise label = \"outer\"\nTry:\n\n```blox exercise label = \"inner\"\nInner\n```\nAfter\n````\n\n```blox exercise\nLast\n```\n\nSee {{ blox-nref: inner }}.\n";
+        let html = process_one(content)?;
+
+        assert_eq!(
+            html,
+            r#"<div id="blox-exercise-outer" class="blox blox-exercise"><div class="blox-header">
+
+Exercise 1.1
+
+</div><div class="blox-content">
+
+
+Try:
+
+
+
+<div id="blox-exercise-inner" class="blox blox-exercise" data-depth="1"><div class="blox-header">
+
+Exercise 1.2
+
+</div><div class="blox-content">
+
+
+Inner
+
+
+</div></div>
+
+
+After
+
+
+</div></div>
+
+<div class="blox blox-exercise"><div class="blox-header">
+
+Exercise 1.3
+
+</div><div class="blox-content">
+
+
+Last
+
+
+</div></div>
+
+See [Exercise 1.2](#blox-exercise-inner).
+"#
+        );
+
+        let err = process_one("````blox exercise\n```blox unknown\n```\n````\n").unwrap_err();
+        assert!(format!("{err:#}").contains("Blox environment `unknown` not defined"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_equation_labels() -> Result<()> {
+        let content = "```blox exercise label = \"a\"\n$$\nx^2 \\label{eq:square}\n$$\n```\n\nBy {{ blox-nref: eq:square }}.\n";
+
+        let mut config = default_test_config();
+        let html = process_with(content, &config)?;
+        assert!(!html.contains("](#blox-exercise-a)"));
+
+        config.equation_labels = true;
+        let html = process_with(content, &config)?;
+        assert!(html.ends_with("By [Exercise 1.1](#blox-exercise-a).\n"));
+
+        // Unlabelled blox get their id from the first equation, transliterated like refs
+        let content = "```blox exercise\n$$\nx^2 \\label{eq:sq}\n$$\n```\n\n```blox exercise\n$$\ny \\label{équation}\n$$\n```\n\nBy {{ blox-nref: eq:sq }} and {{ blox-nref: équation }}.\n";
+        let html = process_with(content, &config)?;
+        assert!(html.starts_with(r#"<div id="blox-exercise-eq-sq" class="blox blox-exercise">"#));
+        assert!(html.contains(r#"<div id="blox-exercise-equation" class="blox blox-exercise">"#));
+        assert!(html.ends_with(
+            "By [Exercise 1.1](#blox-exercise-eq-sq) and [Exercise 1.2](#blox-exercise-equation).\n"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_navigation() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments]
+exercise = { name = "Exercise", navigation = true }
+alert = { name = "Alert" }
+"#,
+        )?;
+        let chapters = [
+            SourceChapter {
+                content: "```blox exercise label = \"a\"\n```\n\n```blox alert label = \"x\"\n```\n",
+                path: Some(PathBuf::from("one.md")),
+                number: Some("1.".to_string()),
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "```blox exercise label = \"b\"\n```\n",
+                path: Some(PathBuf::from("part/two.md")),
+                number: Some("2.".to_string()),
+                ..Default::default()
+            },
+        ];
+        let html = BloxProcessor::process(&chapters, &config)?;
+
+        assert!(html[0].starts_with(
+            r#"<div id="blox-exercise-a" class="blox blox-exercise" data-blox-next="part/two.html#blox-exercise-b" data-blox-next-title="Exercise 2.1">"#
+        ));
+        assert!(html[0].contains(r#"<div id="blox-alert-x" class="blox blox-alert">"#));
+        assert!(html[1].starts_with(
+            r#"<div id="blox-exercise-b" class="blox blox-exercise" data-blox-prev="../one.html#blox-exercise-a" data-blox-prev-title="Exercise 1.1">"#
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pdf_outline() -> Result<()> {
+        let mut config = default_test_config();
+        config.pdf_outline = true;
+
+        let content = "```blox exercise\nA\n```\n\n## Section\n\n```blox exercise\nB\n```\n\n```blox alert\nC\n```\n";
+        let html = process_with(content, &config)?;
+
+        assert!(html.contains("<div class=\"blox-header\">\n\n## Exercise 1.1\n\n</div>"));
+        assert!(html.contains("<div class=\"blox-header\">\n\n### Exercise 1.2\n\n</div>"));
+        assert!(html.contains("<div class=\"blox-header\">\n\nAlert\n\n</div>"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint() -> Result<()> {
+        let mut config = default_test_config();
+        config.fingerprint = true;
+
+        let fingerprint = |html: &str| html[..22].to_string();
+        let first = process_with("```blox alert\nA\n```\n\n```blox alert\nB\n```\n", &config)?;
+        let blox: Vec<&str> = first.split("\n\n<!--").collect();
+        assert!(first.starts_with("<!-- blox:"));
+        assert!(blox[0][10..18].chars().all(|c| c.is_ascii_hexdigit()));
+        assert!(blox[0].contains(" --><div class=\"blox blox-alert\">"));
+        assert_ne!(
+            fingerprint(blox[0]),
+            fingerprint(&format!("<!--{}", blox[1]))
+        );
+
+        let same = process_with("```blox alert\nA\n```\n", &config)?;
+        assert_eq!(fingerprint(&same), fingerprint(&first));
+        assert!(!process_one("```blox alert\nA\n```\n")?.contains("<!--"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_heading_capture() -> Result<()> {
+        let content = r#"# Chapter
+
+## Example
+
+<!-- blox begin exercise title = "Worked" -->
+Step one
+
+### Detail
+
+```blox alert
+Kept
+```
+
+## Next
+
+Text
+"#;
+        let html = process_one(content)?;
+        let (before, after) = html
+            .split_once("<div class=\"blox blox-exercise\">")
+            .unwrap();
+        assert_eq!(before, "# Chapter\n\n## Example\n\n");
+        assert!(after.contains("Exercise 1.1: Worked"));
+        assert!(after.contains("Step one\n\n### Detail\n\n```blox alert\nKept\n```\n\n</div>"));
+        assert!(after.ends_with("</div></div>\n\n## Next\n\nText\n"));
+
+        // Without a heading above, the next heading ends the capture
+        let html = process_one("<!-- blox begin alert -->\nA\n# H\n")?;
+        assert!(html.ends_with("A\n\n</div></div>\n\n# H\n"), "{html}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_comment_markers() -> Result<()> {
+        let content = r#"Intro
+
+<!-- blox: exercise title="Sum", label = "sum" -->
+Add **both**
+
+# Heading inside
+
+<!-- /blox -->
+
+See {{ blox-ref: sum }}
+"#;
+        let html = process_one(content)?;
+        assert_eq!(
+            html,
+            r#"Intro
+
+<div id="blox-exercise-sum" class="blox blox-exercise"><div class="blox-header">
+
+Exercise 1.1: Sum
+
+</div><div class="blox-content">
+
+Add **both**
+
+# Heading inside
+
+</div></div>
+
+See [Exercise 1.1: Sum](#blox-exercise-sum)
+"#
+        );
+
+        // Unclosed markers capture the rest of the chapter
+        let html = process_one("<!-- blox: alert -->\nA\n")?;
+        assert!(html.ends_with("A\n\n</div></div>\n\n"), "{html}");
+        assert_eq!(
+            process_one("A\n\n<!-- /blox -->\n")?,
+            "A\n\n<!-- /blox -->\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preserve_lines() -> Result<()> {
+        let mut config = default_test_config();
+        config.preserve_lines = true;
+
+        let content = "# Title\n\n```blox exercise title = \"T\"\nFirst *line*\n\n- item\n```\n\nProse\n\n```blox alert verbatim = true\n<x>\n```\n\n```blox alert\n```\nEnd\n";
+        let html = process_with(content, &config)?;
+
+        let source: Vec<&str> = content.lines().collect();
+        let lines: Vec<&str> = html.lines().collect();
+        assert_eq!(lines.len(), source.len(), "{html}");
+        for (i, line) in source.iter().enumerate() {
+            if !line.starts_with("```") {
+                assert!(
+                    lines[i].ends_with(line) || lines[i].contains("&lt;x&gt;"),
+                    "{i}: {html}"
+                );
+            }
+        }
+        assert_eq!(
+            lines[2],
+            r#"<!-- blox --><div class="blox blox-exercise"><div class="blox-header">Exercise 1.1: T</div><div class="blox-content">"#
+        );
+        assert_eq!(lines[6], "<!-- /blox --></div></div>");
+        assert_eq!(lines[11], "<pre>&lt;x&gt;</pre>");
+
+        Ok(())
+    }
 }
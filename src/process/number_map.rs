@@ -3,7 +3,9 @@ use crate::parse::Blox;
 use anyhow::{Context, Result};
 use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
+use std::path::Path;
 
+/// Next number of each counter
 pub struct NumberMap(HashMap<String, usize>);
 
 impl Deref for NumberMap {
@@ -22,26 +24,58 @@ impl DerefMut for NumberMap {
 
 impl NumberMap {
     pub fn new(config: &Config) -> Self {
-        Self(
-            config
-                .environments
-                .iter()
-                .map(|(env, _)| (env.clone(), 1))
-                .collect(),
-        )
+        Self(Self::starts(config).collect())
     }
+    /// Restarts the counters of environments numbered within sections
     pub fn reset(&mut self, config: &Config) {
-        self.iter_mut()
-            .filter(|(k, _)| config.prefix_number(k))
-            .for_each(|(_, v)| *v = 1);
+        for (counter, start) in Self::starts(config) {
+            let prefixed = config
+                .environment_keys()
+                .into_iter()
+                .any(|env| config.counter(env) == counter && config.prefix_number(env));
+            if prefixed {
+                self.insert(counter, start);
+            }
+        }
+    }
+    /// Start value of each counter, that of its first environment by `order`
+    fn starts(config: &Config) -> impl Iterator<Item = (String, usize)> {
+        let mut starts: Vec<(String, usize)> = Vec::new();
+        for env in config.environment_keys() {
+            if !starts
+                .iter()
+                .any(|(counter, _)| counter == config.counter(env))
+            {
+                starts.push((config.counter(env).to_string(), config.counter_start(env)));
+            }
+        }
+        starts.into_iter()
+    }
+    /// Sets the counters with start values in the chapter at `path`
+    pub fn start_chapter(&mut self, config: &Config, path: Option<&Path>) {
+        let Some(starts) = path.and_then(|p| config.chapter_counters.get(p)) else {
+            return;
+        };
+
+        for (counter, &start) in starts {
+            match self.get_mut(counter) {
+                Some(n) => *n = start,
+                None => log::warn!("Unknown counter in chapter_counters: {counter}"),
+            }
+        }
     }
-    pub fn set_blox(&mut self, blox: &mut Blox, section_number: Option<&str>) -> Result<()> {
+    pub fn set_blox(
+        &mut self,
+        config: &Config,
+        blox: &mut Blox,
+        section_number: Option<&str>,
+    ) -> Result<()> {
         let n = self
-            .get_mut(blox.env())
+            .get_mut(config.counter(blox.env()))
             .context("Couldn't find environment")?;
 
-        if blox.set_number(*n, section_number) {
-            *n += 1;
+        if blox.set_number(*n, section_number, config.number_style(blox.env())) {
+            *n += config.counter_step(blox.env());
         }
 
         Ok(())
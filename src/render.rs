@@ -1,75 +1,1009 @@
-use crate::config::Config;
+use crate::config::{Config, EnvironmentKind, OutputFormat};
 use crate::css::BloxCss;
-use crate::parse::Blox;
+use crate::parse::{Blox, blanks};
+use crate::typography::code_span_len;
+#[cfg(feature = "template")]
+use handlebars::Handlebars;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd, html};
+#[cfg(feature = "template")]
+use serde_json::json;
+use std::borrow::Cow;
+#[cfg(feature = "template")]
+use std::fmt;
+#[cfg(feature = "template")]
+use std::sync::Arc;
+
+/// Reading speed for the reading time of blox
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Opening and closing delimiters of inline math, longest first
+const MATH_DELIMITERS: [(&str, &str); 4] =
+    [("$$", "$$"), ("\\[", "\\]"), ("\\(", "\\)"), ("$", "$")];
+/// Surrounds the index of a math span while the rest is rendered
+const MATH_MARK: char = '\u{E000}';
+
+/// Parts of a blox header, each `None` if hidden
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderParts<'b> {
+    pub name: Option<Cow<'b, str>>,
+    pub number: Option<&'b str>,
+    pub title: Option<&'b str>,
+    /// Label of the blox this blox is about, like the theorem of a proof
+    pub of: Option<&'b str>,
+}
+
+impl HeaderParts<'_> {
+    /// Composes the parts like `Theorem 2.3: Title`, or `Proof of {{blox-ofref: thm}}`, with the
+    /// number given by `number`. `None` if all parts are hidden.
+    pub fn compose(&self, number: impl FnOnce(&str) -> String) -> Option<String> {
+        let head: Vec<String> = self
+            .name
+            .iter()
+            .map(|name| name.to_string())
+            .chain(self.number.map(number))
+            .chain(
+                self.of
+                    .map(|label| format!("of {{{{blox-ofref: {label}}}}}")),
+            )
+            .collect();
+        let head = head.join(" ");
+
+        match (head.is_empty(), self.title) {
+            (true, None) => None,
+            (true, Some(title)) => Some(title.to_string()),
+            (false, None) => Some(head),
+            (false, Some(title)) => Some(format!("{head}: {title}")),
+        }
+    }
+}
 
 pub struct BloxRender;
 impl BloxRender {
-    // Returns None if header should be hidden
-    fn header(config: &Config, blox: &Blox) -> Option<String> {
-        match blox.hide_header() {
-            true => None,
-            false => blox.title_auto(config),
+    /// The number of a blox header, as a link to the blox with `link_number`, and in an element
+    /// with the full number as `data-number` with `number_spans`
+    pub fn number_html(config: &Config, blox: &Blox, number: &str) -> String {
+        let class = BloxCss::number_class();
+        let data = match (config.number_spans, blox.number()) {
+            (true, Some(full)) => format!(r#" data-number="{}""#, attr_escape(full)),
+            _ => String::new(),
+        };
+
+        match blox.id_str(config).filter(|_| config.link_number) {
+            Some(id) => format!(r##"<a class="{class}" href="#{id}"{data}>{number}</a>"##),
+            None if config.number_spans => {
+                format!(r#"<span class="{class}"{data}>{number}</span>"#)
+            }
+            None => number.to_string(),
+        }
+    }
+
+    /// Renders a blox in the output format of the book
+    pub fn render(config: &Config, blox: &Blox) -> String {
+        match config.format() {
+            OutputFormat::Html => Self::html(config, blox),
+            OutputFormat::Typst => Self::typst(config, blox),
         }
     }
 
     pub fn html(config: &Config, blox: &Blox) -> String {
-        let block_class = BloxCss::block_class();
+        if config.preserve_lines {
+            return Self::html_lines(config, blox);
+        }
+        #[cfg(feature = "template")]
+        if let Some(template) = &config.compiled_template {
+            match Self::template_html(config, blox, template) {
+                Ok(html) => return html,
+                Err(err) => log::warn!("Failed to render blox template, using the default: {err}"),
+            }
+        }
+        match config.kind(blox.env()) {
+            EnvironmentKind::Figure | EnvironmentKind::Table => {
+                return Self::figure_html(config, blox);
+            }
+            EnvironmentKind::Equation => return Self::equation_html(config, blox),
+            EnvironmentKind::Block | EnvironmentKind::Glossary => {}
+        }
+
         let content_class = BloxCss::content_class();
+        let collapsible = blox.collapsible();
 
-        let header = Self::header(config, blox)
+        let header = blox
+            .header_text(config)
+            // A collapsible blox needs a summary to be expanded by
+            .or_else(|| collapsible.then(|| config.name(blox.env()).to_string()))
             .map(|h| {
+                let right = Self::header_right(config, blox)
+                    .map(|r| {
+                        format!(
+                            r#"<span class="{}">{r}</span>
+
+"#,
+                            BloxCss::header_right_class()
+                        )
+                    })
+                    .unwrap_or_default();
+                let h = protect_math(&h);
+                // A Markdown heading, so mdBook gives it an id
+                let h = match config.pdf_outline && blox.number().is_some() {
+                    true => format!("{} {h}", "#".repeat(blox.outline_level.clamp(2, 6).into())),
+                    false => h,
+                };
+                let tag = match collapsible {
+                    true => "summary",
+                    false => "div",
+                };
                 format!(
-                    r#"<div class="{header_class}">
+                    r#"<{tag} class="{header_class}">
 
 {h}
 
-</div>"#,
+{right}</{tag}>"#,
                     header_class = BloxCss::header_class()
                 )
             })
             .unwrap_or_default();
         let footer = blox
-            .footer()
+            .footer_text()
             .map(|f| {
                 format!(
                     r#"<div class="{footer_class}">
 
-{f}
+{}
 
 </div>"#,
+                    protect_math(&f),
                     footer_class = BloxCss::footer_class()
                 )
             })
             .unwrap_or_default();
 
-        let content = if blox.content.trim().is_empty() {
+        let hints: String = blox
+            .hints()
+            .iter()
+            .filter(|_| !blox.statement_only())
+            .map(|hint| {
+                format!(
+                    r#"<details class="{hint_class}"><summary>{title}</summary>
+
+{content}
+
+</details>"#,
+                    hint_class = BloxCss::hint_class(),
+                    title = inline_html(hint.title.as_deref().unwrap_or("Hint")),
+                    content = hint.content
+                )
+            })
+            .collect();
+
+        let (prefix, suffix) = Self::content_snippets(config, blox);
+        let content = if blox.content.trim().is_empty() || blox.statement_only() {
             String::new()
+        } else if blox.verbatim() {
+            // A `<pre>` starting a line is kept as one HTML block, blank lines included
+            format!(
+                r##"<div class="{content_class}">
+
+{prefix}<pre>{}</pre>{suffix}
+
+</div>"##,
+                html_escape(blox.content.trim_matches('\n'))
+            )
         } else {
             format!(
                 r##"<div class="{content_class}">
 
-{}
+{prefix}{}{suffix}
 
 </div>"##,
-                blox.content
+                panes_html(config, &fill_blanks(config, &blox.content))
             )
         };
 
+        // Solutions are revealed on demand, unless the whole blox collapses
+        let content = match blox.solution_of() {
+            Some(_) if !collapsible && !content.is_empty() => format!(
+                r#"<details class="{}"><summary>Show {}</summary>{content}</details>"#,
+                BloxCss::reveal_class(),
+                config.name(blox.env()).to_lowercase()
+            ),
+            _ => content,
+        };
+
+        let opening = Self::opening_tag(config, blox);
+        match collapsible {
+            true => {
+                let open = match blox.open() {
+                    true => " open",
+                    false => "",
+                };
+                let opening = opening.replacen("<div", "<details", 1);
+                let opening = opening.strip_suffix('>').unwrap_or(&opening);
+                format!(r##"{opening}{open}>{header}{content}{hints}{footer}</details>"##)
+            }
+            false => format!(r##"{opening}{header}{content}{hints}{footer}</div>"##),
+        }
+    }
+
+    /// Renders the `template` of the configuration with the fields of a blox
+    #[cfg(feature = "template")]
+    fn template_html(
+        config: &Config,
+        blox: &Blox,
+        template: &BloxTemplate,
+    ) -> anyhow::Result<String> {
+        let data = json!({
+            "env": blox.env(),
+            "name": config.name(blox.env()),
+            "id": blox.id_str(config),
+            "class": Self::class_str(config, blox),
+            "label": blox.label(),
+            "number": blox.number(),
+            "title": blox.title(),
+            "header": blox.header_text(config),
+            "header_right": Self::header_right(config, blox),
+            "footer": blox.footer_text(),
+            "content": match blox.verbatim() {
+                true => Cow::Borrowed(blox.content.as_ref()),
+                false => fill_blanks(config, &blox.content),
+            },
+        });
+        Ok(template.registry.render(BloxTemplate::NAME, &data)?)
+    }
+
+    /// Renders a figure: the image wrapped in a `blox-figure-image` div and, with a `lightbox` group,
+    /// in a link to the image for lightbox scripts, with the header as caption. Tables are
+    /// rendered alike, in a `blox-table-body` div.
+    fn figure_html(config: &Config, blox: &Blox) -> String {
+        let image = match blox.statement_only() {
+            true => "",
+            false => blox.content.trim_matches('\n'),
+        };
+        let (link_open, link_close) = match (config.lightbox(blox.env()), image_url(image)) {
+            (Some(group), Some(url)) => (
+                format!(
+                    r#"<a href="{}" data-lightbox="{}" data-title="{}">
+
+"#,
+                    attr_escape(&url),
+                    attr_escape(group),
+                    attr_escape(&blox.title_full(config))
+                ),
+                "\n\n</a>",
+            ),
+            _ => (String::new(), ""),
+        };
+        let figure = match image.is_empty() {
+            true => String::new(),
+            false => format!(
+                r#"<div class="{} {}">
+
+{link_open}{image}{link_close}
+
+</div>"#,
+                BloxCss::content_class(),
+                match config.kind(blox.env()) {
+                    EnvironmentKind::Table => BloxCss::table_body_class(),
+                    _ => BloxCss::figure_image_class(),
+                }
+            ),
+        };
+        let caption = blox
+            .header_text(config)
+            .map(|h| {
+                format!(
+                    r#"<figcaption class="{}">
+
+{}
+
+</figcaption>"#,
+                    BloxCss::header_class(),
+                    protect_math(&h)
+                )
+            })
+            .unwrap_or_default();
+        let footer = blox
+            .footer_text()
+            .map(|f| {
+                format!(
+                    r#"<div class="{}">
+
+{}
+
+</div>"#,
+                    BloxCss::footer_class(),
+                    protect_math(&f)
+                )
+            })
+            .unwrap_or_default();
+
+        let opening = Self::opening_tag(config, blox).replacen("<div", "<figure", 1);
+        match config.caption_above(blox.env()) {
+            true => format!("{opening}{caption}{figure}{footer}</figure>"),
+            false => format!("{opening}{figure}{caption}{footer}</figure>"),
+        }
+    }
+
+    /// Renders display math with its number in parentheses after it. Math without delimiters is
+    /// put between `$$`, which MathJax and KaTeX both render as display math.
+    fn equation_html(config: &Config, blox: &Blox) -> String {
+        let math = blox.content.trim();
+        let delimited = MATH_DELIMITERS
+            .iter()
+            .any(|(open, close)| math.starts_with(open) && math.ends_with(close));
+        let math = match delimited {
+            true => protect_math(math),
+            false => protect_math(&format!("$$\n{math}\n$$")),
+        };
+        let number = blox
+            .number()
+            .map(|n| {
+                format!(
+                    r#"<span class="{}">({n})</span>"#,
+                    BloxCss::math_number_class()
+                )
+            })
+            .unwrap_or_default();
+
+        let opening = Self::opening_tag(config, blox);
+        format!(
+            r#"{opening}<div class="{} {}">
+
+{math}
+
+</div>{number}</div>"#,
+            BloxCss::content_class(),
+            BloxCss::math_class()
+        )
+    }
+
+    /// Renders a blox as a call of the Typst function named after its environment, like
+    /// `#theorem(name: "Theorem", number: "2.1")[Content] <blox-theorem-label>`
+    fn typst(config: &Config, blox: &Blox) -> String {
+        let mut args = vec![format!("name: {}", typst_string(&config.name(blox.env())))];
+        if let Some(number) = blox.number().filter(|_| blox.header.number) {
+            args.push(format!("number: {}", typst_string(number)));
+        }
+        if let Some(title) = blox.title().filter(|_| blox.header.title) {
+            args.push(format!("title: {}", typst_string(title)));
+        }
+        if let Some(footer) = blox.footer_text() {
+            args.push(format!("footer: [{}]", typst_markup(&footer)));
+        }
+        if !blox.header.show {
+            args.push("header: false".to_string());
+        }
+
+        let (prefix, suffix) = Self::content_snippets(config, blox);
+        let content = if blox.statement_only() {
+            String::new()
+        } else if blox.verbatim() {
+            let raw = format!(
+                "#raw(block: true, {})",
+                typst_string(blox.content.trim_matches('\n'))
+            );
+            [typst_markup(&prefix), raw, typst_markup(&suffix)]
+                .into_iter()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        } else {
+            let panes: Vec<String> = panes(blox.content.trim_matches('\n'))
+                .into_iter()
+                .map(|(name, text)| match name {
+                    Some(name) => {
+                        format!("_{}._\n\n{}", pane_title(name), text.trim_matches('\n'))
+                    }
+                    None => text.trim_matches('\n').to_string(),
+                })
+                .filter(|pane| !pane.is_empty())
+                .collect();
+            let markdown = format!("{prefix}{}{suffix}", panes.join("\n\n"));
+            fill_blanks(config, &typst_markup(&markdown)).into_owned()
+        };
+        let label = blox
+            .id_str(config)
+            .map(|id| format!(" <{id}>"))
+            .unwrap_or_default();
+
+        format!("#{}({})[\n{content}\n]{label}", blox.env(), args.join(", "))
+    }
+
+    /// Renders the source of a blox which failed to parse, with the error
+    pub fn error_html(source: &str, err: &anyhow::Error) -> String {
+        format!(
+            r##"<div class="{block_class} {error_class}"><div class="{header_class}">
+
+Invalid blox
+
+</div><div class="{content_class}">
+
+<pre>{error}</pre>
+
+<pre>{source}</pre>
+
+</div></div>"##,
+            block_class = BloxCss::block_class(),
+            error_class = BloxCss::error_class(),
+            header_class = BloxCss::header_class(),
+            content_class = BloxCss::content_class(),
+            error = html_escape(&format!("{err:#}")),
+            source = html_escape(source.trim_end()),
+        )
+    }
+
+    /// Renders a fenced blox with as many lines as its source, keeping the Markdown content
+    /// on the lines it came from. Header, footer and hint titles are rendered as inline Markdown,
+    /// hint contents and content snippets are not processed.
+    fn html_lines(config: &Config, blox: &Blox) -> String {
+        let right = Self::header_right(config, blox)
+            .map(|r| {
+                format!(
+                    r#"<span class="{}">{r}</span>"#,
+                    BloxCss::header_right_class()
+                )
+            })
+            .unwrap_or_default();
+        let header = blox
+            .header_text(config)
+            .map(|h| {
+                format!(
+                    r#"<div class="{}">{}{right}</div>"#,
+                    BloxCss::header_class(),
+                    inline_html(&h).replace('\n', " ")
+                )
+            })
+            .unwrap_or_default();
+        let footer = blox
+            .footer_text()
+            .map(|f| {
+                let f = inline_html(&f).replace('\n', " ");
+                format!(r#"<div class="{}">{f}</div>"#, BloxCss::footer_class())
+            })
+            .unwrap_or_default();
+        let hints: String = blox
+            .hints()
+            .iter()
+            .filter(|_| !blox.statement_only())
+            .map(|hint| {
+                format!(
+                    r#"<details class="{}"><summary>{}</summary>{}</details>"#,
+                    BloxCss::hint_class(),
+                    inline_html(hint.title.as_deref().unwrap_or("Hint")).replace('\n', " "),
+                    hint.content.replace('\n', " ")
+                )
+            })
+            .collect();
+
+        // Source content lines, without the newlines after the opening and before the closing fence
+        let content = match blox.verbatim() {
+            true => Cow::Borrowed(blox.content.as_ref()),
+            // Pane delimiters are replaced by the pane titles, keeping their lines
+            false => Cow::Owned(
+                panes(&fill_blanks(config, &blox.content))
+                    .into_iter()
+                    .map(|(name, text)| match name {
+                        Some(name) => format!(
+                            "<!-- pane --><div class=\"{}-title\">{}</div>\n{text}",
+                            BloxCss::pane_class(),
+                            pane_title(name)
+                        ),
+                        None => text.to_string(),
+                    })
+                    .collect::<String>(),
+            ),
+        };
+        let content = content.strip_prefix('\n').unwrap_or(&content);
+        let content = content.strip_suffix('\n').unwrap_or(content);
+        let mut lines: Vec<String> = match content.is_empty() {
+            true => Vec::new(),
+            false => content.split('\n').map(|l| l.to_string()).collect(),
+        };
+
+        if blox.statement_only() {
+            lines.iter_mut().for_each(|l| l.clear());
+        } else if blox.verbatim() && !lines.is_empty() {
+            lines = lines.iter().map(|l| html_escape(l)).collect();
+            lines[0].insert_str(0, "<pre>");
+            lines.last_mut().unwrap().push_str("</pre>");
+        }
+
+        // Lines starting with a comment are HTML blocks ending on the same line, so the
+        // content in between is parsed as Markdown without surrounding blank lines
+        let opening = Self::opening_tag(config, blox);
+        let (prefix, suffix) = Self::content_snippets(config, blox);
+        let content_open = match lines.is_empty() || blox.statement_only() {
+            true => String::new(),
+            false => format!(
+                r#"<div class="{}">{}"#,
+                BloxCss::content_class(),
+                prefix.trim().replace('\n', " ")
+            ),
+        };
+        let content_close = match content_open.is_empty() {
+            true => String::new(),
+            false => format!("{}</div>", suffix.trim().replace('\n', " ")),
+        };
+        lines.insert(0, format!("<!-- blox -->{opening}{header}{content_open}"));
+        lines.push(format!(
+            "<!-- /blox -->{content_close}{hints}{footer}</div>"
+        ));
+
+        lines.join("\n")
+    }
+
+    /// Markdown of the environment's `content_prefix` and `content_suffix`, each separated
+    /// from the content by a blank line
+    fn content_snippets(config: &Config, blox: &Blox) -> (String, String) {
+        let prefix = config
+            .content_prefix(blox.env())
+            .map(|p| format!("{}\n\n", p.trim()))
+            .unwrap_or_default();
+        let suffix = config
+            .content_suffix(blox.env())
+            .map(|s| format!("\n\n{}", s.trim()))
+            .unwrap_or_default();
+        (prefix, suffix)
+    }
+
+    /// The `header_right` text and reading time, if shown
+    fn header_right(config: &Config, blox: &Blox) -> Option<String> {
+        let reading_time = config.show_reading_time(blox.env()).then(|| {
+            format!(
+                "~{} min",
+                blox.word_count().div_ceil(WORDS_PER_MINUTE).max(1)
+            )
+        });
+
+        match (blox.header_right(), reading_time) {
+            (None, None) => None,
+            (Some(right), None) => Some(right.to_string()),
+            (None, Some(time)) => Some(time),
+            (Some(right), Some(time)) => Some(format!("{right} · {time}")),
+        }
+    }
+
+    /// `<div>` tag with the id, classes and attributes of a blox
+    fn opening_tag(config: &Config, blox: &Blox) -> String {
         let id: String = blox
             .id_str(config)
             .map(|id| format!(r#" id="{id}""#))
             .unwrap_or("".to_string());
-        let group_str = config.group_str(blox.env()).unwrap();
+        let depth: String = match blox.depth {
+            0 => String::new(),
+            depth => format!(r#" data-depth="{depth}""#),
+        };
+        let lang: String = blox
+            .lang()
+            .map(|lang| format!(r#" lang="{}""#, attr_escape(lang)))
+            .unwrap_or_default();
+        let attrs: String = blox
+            .attrs()
+            .iter()
+            // The `lang` option takes precedence
+            .filter(|(key, _)| blox.lang().is_none() || key.as_str() != "lang")
+            .filter(|(key, _)| {
+                let valid = !key.is_empty()
+                    && key
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':'))
+                    && !matches!(key.as_str(), "id" | "class" | "data-depth");
+                if !valid {
+                    log::warn!("Ignoring blox attribute: {key}");
+                }
+                valid
+            })
+            .map(|(key, value)| format!(r#" {key}="{}""#, attr_escape(value)))
+            .collect();
+        let class_str = Self::class_str(config, blox);
+
+        format!(r#"<div{id} class="{class_str}"{depth}{lang}{attrs}>"#)
+    }
+
+    /// Classes of a blox: the block class, its environment, part and extra classes
+    fn class_str(config: &Config, blox: &Blox) -> String {
+        let mut class_str = format!(
+            "{} {}",
+            BloxCss::block_class(),
+            config.group_str(blox.env()).unwrap()
+        );
+        if let Some(part) = blox.part() {
+            class_str.push_str(&format!(" {part}"));
+        }
+        if let Some(class) = blox.class() {
+            class_str.push_str(&format!(" {}", attr_escape(class)));
+        }
+        class_str
+    }
+}
+
+/// A Handlebars template of the HTML of blox, registered once and shared by the clones of
+/// the configuration. Values are inserted as they are, since the output is Markdown.
+#[cfg(feature = "template")]
+#[derive(Clone)]
+pub struct BloxTemplate {
+    source: String,
+    registry: Arc<Handlebars<'static>>,
+}
+
+#[cfg(feature = "template")]
+impl BloxTemplate {
+    const NAME: &str = "blox";
+
+    pub fn new(source: String) -> anyhow::Result<Self> {
+        let mut registry = Handlebars::new();
+        registry.register_escape_fn(handlebars::no_escape);
+        registry.register_template_string(Self::NAME, &source)?;
+        Ok(Self {
+            source,
+            registry: Arc::new(registry),
+        })
+    }
+}
+
+#[cfg(feature = "template")]
+impl fmt::Debug for BloxTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BloxTemplate").field(&self.source).finish()
+    }
+}
+
+#[cfg(feature = "template")]
+impl PartialEq for BloxTemplate {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+/// Splits content into panes at lines like `---proof`, outside fenced code. The pane before
+/// the first delimiter has no name.
+fn panes(content: &str) -> Vec<(Option<&str>, &str)> {
+    let mut panes = Vec::new();
+    let mut name = None;
+    let mut start = 0;
+    let mut offset = 0;
+    let mut fence: Option<&str> = None;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim();
+        let line_fence = ["```", "~~~"].into_iter().find(|f| trimmed.starts_with(f));
+        match (fence, line_fence) {
+            (None, Some(f)) => fence = Some(f),
+            (Some(open), Some(f)) if open == f => fence = None,
+            _ => {}
+        }
+
+        let pane = trimmed.strip_prefix("---").filter(|n| {
+            fence.is_none()
+                && n.starts_with(|c: char| c.is_ascii_lowercase())
+                && n.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        });
+        if let Some(pane) = pane {
+            panes.push((name, &content[start..offset]));
+            name = Some(pane);
+            start = offset + line.len();
+        }
+        offset += line.len();
+    }
+    panes.push((name, &content[start..]));
+
+    panes
+}
+
+/// Content with its panes as labelled sections, collapsed if configured
+fn panes_html<'c>(config: &Config, content: &'c str) -> Cow<'c, str> {
+    let panes = panes(content);
+    if panes.len() == 1 {
+        return Cow::Borrowed(content);
+    }
+
+    let pane_class = BloxCss::pane_class();
+    let html: Vec<String> = panes
+        .into_iter()
+        .map(|(name, text)| {
+            let text = text.trim_matches('\n');
+            let Some(name) = name else {
+                return text.to_string();
+            };
+            let title = pane_title(name);
+            match config.collapsed_panes.iter().any(|p| p == name) {
+                true => format!(
+                    r#"<details class="{pane_class} {pane_class}-{name}"><summary>{title}</summary>
+
+{text}
+
+</details>"#
+                ),
+                false => format!(
+                    r#"<div class="{pane_class} {pane_class}-{name}"><div class="{pane_class}-title">{title}</div>
+
+{text}
+
+</div>"#
+                ),
+            }
+        })
+        .filter(|pane| !pane.is_empty())
+        .collect();
+
+    Cow::Owned(html.join("\n\n"))
+}
+
+/// Title of a pane, like `Proof` for `---proof`
+fn pane_title(name: &str) -> String {
+    let mut title = name.replace('-', " ");
+    title[..1].make_ascii_uppercase();
+    title
+}
+
+/// Replaces the `{{ blank: answer }}` blanks of content by underlined blanks, or by their
+/// answers if `answers`
+fn fill_blanks<'c>(config: &Config, content: &'c str) -> Cow<'c, str> {
+    let blanks = blanks(content);
+    if blanks.is_empty() {
+        return Cow::Borrowed(content);
+    }
+
+    let mut out = String::with_capacity(content.len());
+    let mut last = 0;
+    for (span, answer) in blanks {
+        out.push_str(&content[last..span.start]);
+        let answer = match config.answers {
+            true => answer,
+            false => "",
+        };
+        let blank = match (config.format(), answer.is_empty()) {
+            (OutputFormat::Html, _) => {
+                format!(
+                    r#"<span class="{}">{answer}</span>"#,
+                    BloxCss::blank_class()
+                )
+            }
+            (OutputFormat::Typst, true) => "#box(width: 6em, stroke: (bottom: 0.5pt))".to_string(),
+            (OutputFormat::Typst, false) => format!("#underline[{answer}]"),
+        };
+        out.push_str(&blank);
+        last = span.end;
+    }
+    out.push_str(&content[last..]);
+
+    Cow::Owned(out)
+}
+
+/// URL of the first image in Markdown
+fn image_url(markdown: &str) -> Option<String> {
+    Parser::new(markdown).find_map(|event| match event {
+        Event::Start(Tag::Image { dest_url, .. }) => Some(dest_url.to_string()),
+        _ => None,
+    })
+}
+
+/// Splits Markdown into text and math spans, like `$e^{i\pi}$` or `\(x\)`. Delimiters in
+/// code spans or escaped by a backslash are text.
+fn split_math(markdown: &str) -> Vec<(bool, &str)> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
 
-        format!(r##"<div{id} class="{block_class} {group_str}">{header}{content}{footer}</div>"##)
+    while let Some(c) = markdown[i..].chars().next() {
+        let rest = &markdown[i..];
+        let skip = match c {
+            '`' => code_span_len(rest),
+            '\\' if rest[1..].starts_with(['\\', '$']) => Some(2),
+            _ => None,
+        };
+        if let Some(len) = skip {
+            i += len;
+            continue;
+        }
+
+        let math_len = MATH_DELIMITERS.iter().find_map(|(open, close)| {
+            let inner = rest.strip_prefix(open)?;
+            let end = inner.find(close).filter(|&end| end > 0)?;
+            Some(open.len() + end + close.len())
+        });
+        match math_len {
+            Some(len) => {
+                if start < i {
+                    parts.push((false, &markdown[start..i]));
+                }
+                parts.push((true, &rest[..len]));
+                i += len;
+                start = i;
+            }
+            None => i += c.len_utf8(),
+        }
+    }
+
+    if start < markdown.len() {
+        parts.push((false, &markdown[start..]));
+    }
+    parts
+}
+
+/// Renders inline Markdown, like a title, to HTML. Math spans are kept as they are, for
+/// MathJax, and text of several paragraphs stays in `<p>` elements.
+fn inline_html(markdown: &str) -> String {
+    let mut math = Vec::new();
+    let mut source = String::with_capacity(markdown.len());
+    for (is_math, part) in split_math(markdown) {
+        match is_math {
+            true => {
+                source.push_str(&format!("{MATH_MARK}{}{MATH_MARK}", math.len()));
+                math.push(part);
+            }
+            false => source.push_str(part),
+        }
+    }
+
+    let mut out = String::new();
+    html::push_html(&mut out, Parser::new(source.trim()));
+    let out = out.trim_end();
+    let mut out = match out.strip_prefix("<p>").and_then(|o| o.strip_suffix("</p>")) {
+        Some(inline) if !inline.contains("<p>") => inline.to_string(),
+        _ => out.to_string(),
+    };
+
+    for (i, m) in math.into_iter().enumerate() {
+        out = out.replace(&format!("{MATH_MARK}{i}{MATH_MARK}"), &html_escape(m));
     }
+    out
+}
+
+/// Escapes the punctuation of math spans in Markdown rendered by mdBook, so MathJax gets them
+/// as written, e.g. `$a_1 * b_2$` without emphasis
+fn protect_math(markdown: &str) -> String {
+    split_math(markdown)
+        .into_iter()
+        .map(|(is_math, part)| match is_math {
+            true => part
+                .chars()
+                .map(|c| match c.is_ascii_punctuation() {
+                    true => format!("\\{c}"),
+                    false => c.to_string(),
+                })
+                .collect(),
+            false => part.to_string(),
+        })
+        .collect()
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Converts Markdown to Typst markup. Math is passed on as written, so it must be Typst math,
+/// and raw HTML is left out. Refs are replaced afterwards, as Markdown links.
+fn typst_markup(markdown: &str) -> String {
+    let mut out = String::with_capacity(markdown.len());
+    // Number of the next item of each open list, `None` if unordered
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut in_code = false;
+    let mut in_image = false;
+
+    for event in Parser::new_ext(
+        markdown,
+        Options::ENABLE_MATH | Options::ENABLE_STRIKETHROUGH,
+    ) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                out.push_str(&"=".repeat(level as usize));
+                out.push(' ');
+            }
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_)) => out.push_str("\n\n"),
+            Event::Start(Tag::BlockQuote(_)) => out.push_str("#quote(block: true)[\n"),
+            Event::End(TagEnd::BlockQuote(_)) => {
+                out.truncate(out.trim_end_matches('\n').len());
+                out.push_str("\n]\n\n");
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let lang = match kind {
+                    CodeBlockKind::Fenced(lang) => lang,
+                    CodeBlockKind::Indented => "".into(),
+                };
+                out.push_str(&format!("```{lang}\n"));
+                in_code = true;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                out.push_str("```\n\n");
+                in_code = false;
+            }
+            Event::Start(Tag::List(start)) => lists.push(start),
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    out.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(n)) => {
+                        out.push_str(&format!("{n}. "));
+                        *n += 1;
+                    }
+                    _ => out.push_str("- "),
+                }
+            }
+            Event::End(TagEnd::Item) => {
+                out.truncate(out.trim_end_matches('\n').len());
+                out.push('\n');
+            }
+            Event::Start(Tag::Emphasis) | Event::End(TagEnd::Emphasis) => out.push('_'),
+            Event::Start(Tag::Strong) | Event::End(TagEnd::Strong) => out.push('*'),
+            Event::Start(Tag::Strikethrough) => out.push_str("#strike["),
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                out.push_str(&format!("#link({})[", typst_string(&dest_url)));
+            }
+            Event::End(TagEnd::Strikethrough | TagEnd::Link) => out.push(']'),
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                out.push_str(&format!("#image({})", typst_string(&dest_url)));
+                in_image = true;
+            }
+            Event::End(TagEnd::Image) => in_image = false,
+            Event::Text(text) if in_code => out.push_str(&text),
+            Event::Text(text) if !in_image => {
+                for c in text.chars() {
+                    let line_start = out.is_empty() || out.ends_with('\n');
+                    if matches!(
+                        c,
+                        '\\' | '#'
+                            | '*'
+                            | '_'
+                            | '`'
+                            | '$'
+                            | '@'
+                            | '<'
+                            | '>'
+                            | '['
+                            | ']'
+                            | '~'
+                            | '/'
+                    ) || (line_start && matches!(c, '=' | '-' | '+'))
+                    {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+            }
+            Event::Code(code) => out.push_str(&format!("#raw({})", typst_string(&code))),
+            Event::InlineMath(math) => out.push_str(&format!("${math}$")),
+            Event::DisplayMath(math) => out.push_str(&format!("$ {} $", math.trim())),
+            Event::SoftBreak => out.push('\n'),
+            Event::HardBreak => out.push_str(" \\\n"),
+            Event::Rule => out.push_str("#line(length: 100%)\n\n"),
+            _ => {}
+        }
+    }
+
+    out.trim_end().to_string()
+}
+
+fn typst_string(s: &str) -> String {
+    format!(
+        r#""{}""#,
+        s.replace('\\', r"\\")
+            .replace('"', r#"\""#)
+            .replace('\n', r"\n")
+    )
+}
+
+fn attr_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::config::test::default_test_config;
-    use crate::parse::Blox;
+    use crate::parse::{Blox, Hint};
     use anyhow::Result;
     use pretty_assertions::assert_eq;
 
@@ -84,10 +1018,7 @@ mod test {
     #[test]
     fn test_html() -> Result<()> {
         check_html(
-            {
-                let blox = Blox::new("alert");
-                blox
-            },
+            Blox::new("alert"),
             r#"<div class="blox blox-alert"><div class="blox-header">
 
 Alert
@@ -126,16 +1057,443 @@ Alert 10
             {
                 let mut blox = Blox::new("alert");
                 blox.title = Some("Title".to_string());
-                blox.hide_name = true;
+                blox.header.name = false;
                 blox
             },
             r#"<div class="blox blox-alert"><div class="blox-header">
 
 Title
 
+</div></div>"#,
+        )?;
+
+        check_html(
+            {
+                let mut blox = Blox::new("alert");
+                blox.header.show = false;
+                blox.lang = Some("fr".to_string());
+                blox.attrs.insert("lang".to_string(), "de".to_string());
+                blox
+            },
+            r#"<div class="blox blox-alert" lang="fr"></div>"#,
+        )?;
+
+        check_html(
+            {
+                let mut blox = Blox::new("alert");
+                blox.header.show = false;
+                blox.depth = 2;
+                blox.attrs.insert("data-depth".to_string(), "0".to_string());
+                blox
+            },
+            r#"<div class="blox blox-alert" data-depth="2"></div>"#,
+        )?;
+
+        check_html(
+            {
+                let mut blox = Blox::new("exercise");
+                blox.header_right = Some("★★☆".to_string());
+                blox
+            },
+            r#"<div class="blox blox-exercise"><div class="blox-header">
+
+Exercise
+
+<span class="blox-header-right">★★☆</span>
+
+</div></div>"#,
+        )?;
+
+        check_html(
+            {
+                let mut blox = Blox::new("alert");
+                blox.header.show = false;
+                blox.attrs.insert("lang".to_string(), "de".to_string());
+                blox.attrs
+                    .insert("data-x".to_string(), r#"a"b"#.to_string());
+                blox.attrs.insert("onclick x".to_string(), String::new());
+                blox.hints.push(Hint {
+                    title: None,
+                    content: "Think".to_string(),
+                });
+                blox
+            },
+            r#"<div class="blox blox-alert" data-x="a&quot;b" lang="de"><details class="blox-hint"><summary>Hint</summary>
+
+Think
+
+</details></div>"#,
+        )?;
+
+        check_html(
+            {
+                let mut blox = Blox::new("alert");
+                blox.content = "\n<b>*x*</b>\n\n  & y\n".into();
+                blox.header.show = false;
+                blox.verbatim = true;
+                blox
+            },
+            r#"<div class="blox blox-alert"><div class="blox-content">
+
+<pre>&lt;b&gt;*x*&lt;/b&gt;
+
+  &amp; y</pre>
+
+</div></div>"#,
+        )?;
+
+        check_html(
+            {
+                let mut blox = Blox::new("alert");
+                blox.content = "Hidden".into();
+                blox.footer = Some("Footer".to_string());
+                blox.statement_only = true;
+                blox
+            },
+            r#"<div class="blox blox-alert"><div class="blox-header">
+
+Alert
+
+</div></div>"#,
+        )?;
+
+        check_html(
+            {
+                let mut blox = Blox::new("alert");
+                blox.content = "Content".into();
+                blox.footer = Some("Footer".to_string());
+                blox.header.show = false;
+                blox.hide_footer = true;
+                blox
+            },
+            r#"<div class="blox blox-alert"><div class="blox-content">
+
+Content
+
 </div></div>"#,
         )?;
 
         Ok(())
     }
+
+    #[test]
+    fn test_reading_time() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments.case]
+name = "Case"
+show_reading_time = true
+"#,
+        )?;
+
+        let mut blox = Blox::new("case");
+        blox.content = "word ".repeat(450).into();
+        blox.header_right = Some("MA101".to_string());
+        let html = BloxRender::html(&config, &blox);
+        assert!(html.contains(r#"<span class="blox-header-right">MA101 · ~3 min</span>"#));
+
+        blox.content = "".into();
+        blox.header_right = None;
+        let html = BloxRender::html(&config, &blox);
+        assert!(html.contains(r#"<span class="blox-header-right">~1 min</span>"#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_snippets() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments.lab]
+name = "Lab"
+content_prefix = "⚠︎ Read the *safety notes* first."
+content_suffix = "Clean up afterwards."
+"#,
+        )?;
+
+        let mut blox = Blox::new("lab");
+        blox.content = "\nMix the solutions.\n".into();
+        let html = BloxRender::html(&config, &blox);
+        assert!(html.contains(
+            "⚠︎ Read the *safety notes* first.\n\n\nMix the solutions.\n\n\nClean up afterwards.\n\n</div>"
+        ));
+
+        blox.content = "".into();
+        let html = BloxRender::html(&config, &blox);
+        assert!(!html.contains("safety notes"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_figure() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments.figure]
+name = "Figure"
+kind = "figure"
+lightbox = "book"
+"#,
+        )?;
+
+        let mut blox = Blox::new("figure");
+        blox.number = Some("1.2".to_string());
+        blox.title = Some("Setup".to_string());
+        blox.content = "\n![The setup](img/setup.png)\n".into();
+        assert_eq!(
+            BloxRender::html(&config, &blox),
+            r#"<figure class="blox blox-figure"><div class="blox-content blox-figure-image">
+
+<a href="img/setup.png" data-lightbox="book" data-title="Figure 1.2: Setup">
+
+![The setup](img/setup.png)
+
+</a>
+
+</div><figcaption class="blox-header">
+
+Figure 1.2: Setup
+
+</figcaption></figure>"#
+        );
+        assert_eq!(blox.title_auto(&config).as_deref(), Some("Figure 1.2"));
+
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments.table]
+name = "Table"
+kind = "table"
+"#,
+        )?;
+        let mut blox = Blox::new("table");
+        blox.number = Some("2.1".to_string());
+        blox.content = "\n| a | b |\n|---|---|\n| 1 | 2 |\n".into();
+        assert_eq!(
+            BloxRender::html(&config, &blox),
+            r#"<figure class="blox blox-table"><figcaption class="blox-header">
+
+Table 2.1
+
+</figcaption><div class="blox-content blox-table-body">
+
+| a | b |
+|---|---|
+| 1 | 2 |
+
+</div></figure>"#
+        );
+        assert_eq!(blox.title_auto(&config).as_deref(), Some("Table 2.1"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collapsible() -> Result<()> {
+        let config = Config::from_book_toml(
+            r#"
+[preprocessor.blox.environments.proof]
+name = "Proof"
+numbered = false
+collapsible = true
+open = false
+"#,
+        )?;
+
+        let blox = Blox::from_header(&config, "blox proof")?.unwrap();
+        assert!(blox.collapsible() && !blox.open());
+        assert_eq!(
+            BloxRender::html(&config, &blox),
+            r#"<details class="blox blox-proof"><summary class="blox-header">
+
+Proof
+
+</summary></details>"#
+        );
+
+        let mut blox =
+            Blox::from_header(&config, "blox proof open = true, label = \"p\"")?.unwrap();
+        blox.header.show = false;
+        blox.content = "\nTrivial.\n".into();
+        assert_eq!(
+            BloxRender::html(&config, &blox),
+            r#"<details id="blox-proof-p" class="blox blox-proof" open><summary class="blox-header">
+
+Proof
+
+</summary><div class="blox-content">
+
+
+Trivial.
+
+
+</div></details>"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "template")]
+    fn test_template() -> Result<()> {
+        let mut config = default_test_config();
+        config.compiled_template = Some(BloxTemplate::new(
+            r#"<section id="{{id}}" class="{{class}}"><h4>{{header}}</h4>
+
+{{content}}
+{{#if footer}}
+<p>{{footer}}</p>{{/if}}</section>"#
+                .to_string(),
+        )?);
+
+        let mut blox = Blox::new("exercise");
+        blox.label = Some("sum".to_string());
+        blox.number = Some("1.2".to_string());
+        blox.title = Some("A & B".to_string());
+        blox.content = "\n*x* < y\n".into();
+        assert_eq!(
+            BloxRender::html(&config, &blox),
+            r#"<section id="blox-exercise-sum" class="blox blox-exercise"><h4>Exercise 1.2: A & B</h4>
+
+
+*x* < y
+
+</section>"#
+        );
+
+        // Verbatim content is passed on as written
+        blox.content = "\n{{ blank: 42 }}\n".into();
+        blox.verbatim = true;
+        assert!(BloxRender::html(&config, &blox).contains("\n{{ blank: 42 }}\n"));
+        blox.verbatim = false;
+        assert!(!BloxRender::html(&config, &blox).contains("{{ blank"));
+
+        // Falls back to the default markup
+        assert!(BloxTemplate::new("{{#if}}".to_string()).is_err());
+        config.compiled_template = Some(BloxTemplate::new("{{missing env}}".to_string())?);
+        assert!(BloxRender::html(&config, &blox).starts_with(r#"<div id="blox-exercise-sum""#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_typst() {
+        let mut config = default_test_config();
+        config.format = Some(OutputFormat::Typst);
+
+        let mut blox = Blox::new("exercise");
+        blox.label = Some("sum".to_string());
+        blox.number = Some("2.1".to_string());
+        blox.title = Some(r#"The "sum""#.to_string());
+        blox.content = "\nAdd *both*.\n".into();
+        assert_eq!(
+            BloxRender::render(&config, &blox),
+            "#exercise(name: \"Exercise\", number: \"2.1\", title: \"The \\\"sum\\\"\")[\nAdd _both_.\n] <blox-exercise-sum>"
+        );
+
+        // The footer with its related blox, converted like the content
+        blox.footer = Some("From [Rudin](https://example.com)".to_string());
+        blox.see_also = vec!["prod".to_string()];
+        blox.content = "\nSum **#1** and `a_1`:\n\n1. $a + b$\n2. $c$\n\n> Note\n".into();
+        assert_eq!(
+            BloxRender::render(&config, &blox),
+            r##"#exercise(name: "Exercise", number: "2.1", title: "The \"sum\"", footer: [From #link("https://example.com")[Rudin]
+
+See also: {{blox-nref: prod}}])[
+Sum *\#1* and #raw("a_1"):
+
+1. $a + b$
+2. $c$
+
+#quote(block: true)[
+Note
+]
+] <blox-exercise-sum>"##
+        );
+
+        // Verbatim content is kept as written, blanks included
+        blox.footer = None;
+        blox.see_also.clear();
+        blox.verbatim = true;
+        blox.content = "\n*a* \"{{ blank: 42 }}\"\n  b\n".into();
+        assert_eq!(
+            BloxRender::render(&config, &blox),
+            r#"#exercise(name: "Exercise", number: "2.1", title: "The \"sum\"")[
+#raw(block: true, "*a* \"{{ blank: 42 }}\"\n  b")
+] <blox-exercise-sum>"#
+        );
+    }
+
+    #[test]
+    fn test_inline_markdown() {
+        assert_eq!(
+            inline_html(r"Euler's *formula* $e^{i\pi} < 0$ with `a $b$` and [link](x.md)"),
+            r#"Euler's <em>formula</em> $e^{i\pi} &lt; 0$ with <code>a $b$</code> and <a href="x.md">link</a>"#
+        );
+        assert_eq!(
+            inline_html(r"Costs \$5 and \(a_1 * b_2\)"),
+            r"Costs $5 and \(a_1 * b_2\)"
+        );
+        assert_eq!(inline_html("One\n\nTwo"), "<p>One</p>\n<p>Two</p>");
+
+        assert_eq!(
+            protect_math(r"The *sum* $a_1 * b_2$ and `$c$`"),
+            r"The *sum* \$a\_1 \* b\_2\$ and `$c$`"
+        );
+
+        let mut config = default_test_config();
+        config.preserve_lines = true;
+        let mut blox = Blox::new("exercise");
+        blox.title = Some("The *sum* $a_1 + a_2$".to_string());
+        blox.footer = Some("From [Rudin](rudin.md)".to_string());
+        let html = BloxRender::html(&config, &blox);
+        assert!(html.contains("Exercise: The <em>sum</em> $a_1 + a_2$</div>"));
+        assert!(html.contains(r#"From <a href="rudin.md">Rudin</a></div>"#));
+    }
+
+    #[test]
+    fn test_panes() {
+        let mut config = default_test_config();
+        let mut blox = Blox::new("exercise");
+        blox.content = "\nEvery *x* is y.\n---proof\n```\n---not-a-pane\n```\nTrivial.\n\n---remark\nSee also z.\n".into();
+
+        let html = BloxRender::html(&config, &blox);
+        assert!(html.contains(
+            r#"Every *x* is y.
+
+<details class="blox-pane blox-pane-proof"><summary>Proof</summary>
+
+```
+---not-a-pane
+```
+Trivial.
+
+</details>
+
+<div class="blox-pane blox-pane-remark"><div class="blox-pane-title">Remark</div>
+
+See also z.
+
+</div>"#
+        ));
+
+        config.collapsed_panes.clear();
+        assert!(
+            BloxRender::html(&config, &blox).contains(r#"<div class="blox-pane blox-pane-proof">"#)
+        );
+
+        config.preserve_lines = true;
+        let lines = BloxRender::html(&config, &blox);
+        assert_eq!(lines.lines().count(), blox.content.lines().count() + 1);
+        assert!(!lines.contains("---proof"));
+        assert!(lines.contains(
+            "Every *x* is y.\n<!-- pane --><div class=\"blox-pane-title\">Proof</div>\n```"
+        ));
+
+        config.preserve_lines = false;
+        config.format = Some(OutputFormat::Typst);
+        assert!(
+            BloxRender::render(&config, &blox)
+                .contains("Every _x_ is y.\n\n_Proof._\n\n```\n---not-a-pane\n```\n\nTrivial.")
+        );
+    }
 }
@@ -0,0 +1,91 @@
+use crate::config::Config;
+use crate::process::{BloxProcessor, Patterns, SourceChapter};
+use anyhow::Result;
+use std::borrow::Cow;
+
+/// Configuration and compiled patterns, reusable across runs (e.g. rebuilds under
+/// `mdbook serve`) and threads. Each run starts from a fresh processor state.
+#[derive(Debug, Clone)]
+pub struct Session {
+    config: Config,
+    patterns: Patterns,
+}
+
+impl Session {
+    pub fn new(config: Config) -> Result<Self> {
+        Ok(Self {
+            config,
+            patterns: Patterns::new()?,
+        })
+    }
+
+    #[inline]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// A processor with empty state
+    #[inline]
+    pub fn processor(&self) -> BloxProcessor<'_> {
+        BloxProcessor::with_patterns(&self.config, Cow::Borrowed(&self.patterns))
+    }
+
+    /// Renders the blox of all chapters, returning the new content of each chapter
+    pub fn process(&self, chapters: &[SourceChapter]) -> Result<Vec<String>> {
+        let mut processor = self.processor();
+        processor.parse(chapters)?;
+        processor.render(chapters)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::test::default_test_config;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_session_reuse() -> Result<()> {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Session>();
+
+        let session = Session::new(default_test_config())?;
+        let chapters = [SourceChapter {
+            content: "```blox exercise\nContent\n```\n",
+            number: Some("1.".to_string()),
+            ..Default::default()
+        }];
+
+        // Numbering restarts on every run
+        let first = session.process(&chapters)?;
+        let second = session.process(&chapters)?;
+        assert_eq!(first, second);
+        assert!(first[0].contains("Exercise 1.1"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_critical_css() -> Result<()> {
+        let mut config = default_test_config();
+        config.inline_critical_css = true;
+        let session = Session::new(config)?;
+
+        let chapters = [
+            SourceChapter {
+                content: "```blox alert\nA\n```\n\n```blox alert\nB\n```\n",
+                ..Default::default()
+            },
+            SourceChapter {
+                content: "No blox\n",
+                ..Default::default()
+            },
+        ];
+        let content = session.process(&chapters)?;
+        assert!(content[0].starts_with("<style>.blox{"));
+        assert_eq!(content[0].matches("<style>").count(), 1);
+        assert_eq!(content[1], "No blox\n");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+/// Converts straight quotes, dashes and ellipses to their typographic forms, like the
+/// `SMART_PUNCTUATION` option of pulldown-cmark. Code spans, HTML tags, comments and autolinks
+/// are kept as they are.
+pub fn smart_punctuation(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    // Last character of text, ignoring tags
+    let mut prev = None;
+
+    while let Some(c) = rest.chars().next() {
+        let verbatim = match c {
+            '`' => code_span_len(rest).inspect(|_| prev = Some(c)),
+            '<' => html_tag_len(rest),
+            _ => None,
+        };
+        if let Some(len) = verbatim {
+            out.push_str(&rest[..len]);
+            rest = &rest[len..];
+            continue;
+        }
+
+        let (replacement, len) = if rest.starts_with("---") {
+            ("—", 3)
+        } else if rest.starts_with("--") {
+            ("–", 2)
+        } else if rest.starts_with("...") {
+            ("…", 3)
+        } else if c == '"' {
+            (if opens(prev) { "“" } else { "”" }, 1)
+        } else if c == '\'' {
+            (if opens(prev) { "‘" } else { "’" }, 1)
+        } else {
+            out.push(c);
+            prev = Some(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+
+        out.push_str(replacement);
+        prev = replacement.chars().last();
+        rest = &rest[len..];
+    }
+
+    out
+}
+
+/// Length of the code span starting `text`, if it is closed
+pub(crate) fn code_span_len(text: &str) -> Option<usize> {
+    let ticks = text.len() - text.trim_start_matches('`').len();
+    text[ticks..]
+        .find(&text[..ticks])
+        .map(|end| 2 * ticks + end)
+}
+
+/// Length of the HTML tag, comment or autolink starting `text`, following CommonMark
+fn html_tag_len(text: &str) -> Option<usize> {
+    if let Some(comment) = text.strip_prefix("<!--") {
+        return comment.find("-->").map(|end| end + 7);
+    }
+    let rest = text.strip_prefix('<')?;
+    if let Some(len) = autolink_len(rest) {
+        return Some(len + 1);
+    }
+
+    let closing = rest.starts_with('/');
+    let mut s = rest.strip_prefix('/').unwrap_or(rest);
+    s = s.strip_prefix(|c: char| c.is_ascii_alphabetic())?;
+    s = s.trim_start_matches(|c: char| c.is_ascii_alphanumeric() || c == '-');
+    if closing {
+        s = s.trim_start();
+    } else {
+        // Attributes, each preceded by whitespace
+        loop {
+            let trimmed = s.trim_start();
+            let attribute =
+                trimmed.strip_prefix(|c: char| c.is_ascii_alphabetic() || c == '_' || c == ':');
+            let Some(attribute) = attribute.filter(|_| trimmed.len() < s.len()) else {
+                s = trimmed;
+                break;
+            };
+            s = attribute
+                .trim_start_matches(|c: char| c.is_ascii_alphanumeric() || "_.:-".contains(c));
+            if let Some(value) = s.trim_start().strip_prefix('=') {
+                s = after_attribute_value(value.trim_start())?;
+            }
+        }
+        s = s.strip_prefix('/').unwrap_or(s);
+    }
+    s = s.strip_prefix('>')?;
+
+    Some(text.len() - s.len())
+}
+
+/// The text after the quoted or unquoted attribute value starting `text`
+fn after_attribute_value(text: &str) -> Option<&str> {
+    match text.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let value = &text[1..];
+            value.find(quote).map(|end| &value[end + 1..])
+        }
+        _ => {
+            let rest =
+                text.trim_start_matches(|c: char| !c.is_whitespace() && !"\"'=<>`".contains(c));
+            (rest.len() < text.len()).then_some(rest)
+        }
+    }
+}
+
+/// Length of the autolink like `<https://example.com>` after its `<`
+fn autolink_len(text: &str) -> Option<usize> {
+    let scheme = text.find(':')?;
+    let end = text.find('>')?;
+    let valid_scheme = (2..=32).contains(&scheme)
+        && text.starts_with(|c: char| c.is_ascii_alphabetic())
+        && text[..scheme]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c));
+
+    (valid_scheme && scheme < end && !text[..end].contains(|c: char| c.is_whitespace() || c == '<'))
+        .then_some(end + 1)
+}
+
+/// Quotes open at the start, and after whitespace or opening punctuation
+#[inline]
+fn opens(prev: Option<char>) -> bool {
+    prev.is_none_or(|p| p.is_whitespace() || "([{“‘—–".contains(p))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_smart_punctuation() {
+        assert_eq!(
+            smart_punctuation(r#"The "best" case -- isn't it... 'Right'---no"#),
+            "The “best” case – isn’t it… ‘Right’—no"
+        );
+        assert_eq!(
+            smart_punctuation(r#"Use `a -- "b"` and <span class="x">"y"</span>"#),
+            r#"Use `a -- "b"` and <span class="x">“y”</span>"#
+        );
+        assert_eq!(smart_punctuation(r#""<em>x</em>""#), "“<em>x</em>”");
+        assert_eq!(
+            smart_punctuation(r#"If a < b > c then "a" -- done"#),
+            "If a < b > c then “a” – done"
+        );
+        assert_eq!(
+            smart_punctuation(r#"<img alt='a -- b' src=x.png/> <br /> </span > "q""#),
+            r#"<img alt='a -- b' src=x.png/> <br /> </span > “q”"#
+        );
+        assert_eq!(
+            smart_punctuation(r#"<!-- "c" --> <https://a.org/x--y> <a title="1 > 0">"b"</a>"#),
+            r#"<!-- "c" --> <https://a.org/x--y> <a title="1 > 0">“b”</a>"#
+        );
+        assert_eq!(
+            smart_punctuation("Unclosed ` and \"q\""),
+            "Unclosed ` and “q”"
+        );
+    }
+}